@@ -3,12 +3,19 @@ use axum::{
     extract::{FromRef, FromRequestParts},
     http::request::Parts,
 };
+use db::dtos::PipelineExecEvent;
 use hyper::StatusCode;
 use serde::{Deserialize, Serialize};
-use std::fmt::Debug;
+use std::{fmt::Debug, sync::Arc};
 use tokio::sync::broadcast;
 use uuid::Uuid;
 
+use crate::lib::{
+    mailer::Mailer,
+    oauth::OAuthConfig,
+    rate_limit::{RateLimitConfig, RateLimiter},
+};
+
 #[derive(Clone)]
 pub struct AppState {
     pub db: sqlx::Pool<sqlx::Postgres>,
@@ -16,6 +23,108 @@ pub struct AppState {
     pub jetstream: async_nats::jetstream::Context,
     pub ws_messages_broadcast: WsMessagesBroadcast,
     pub exec_events_consumer: ExecEventsConsumer,
+    pub exec_events_broadcast: ExecEventsBroadcast,
+    pub mailer: Arc<dyn Mailer>,
+    pub auth_config: AuthConfig,
+    pub oauth_config: OAuthConfig,
+    pub rate_limit_config: RateLimitConfig,
+}
+
+#[derive(Clone)]
+pub struct AuthConfig {
+    /// Refuse logging in until the email address of the account is verified.
+    pub require_email_verification: bool,
+    /// Base url of the web app, the links sent by email point to it.
+    pub app_url: String,
+}
+
+impl AuthConfig {
+    /// Reads `REQUIRE_EMAIL_VERIFICATION` and `APP_URL`.
+    pub fn from_env() -> Self {
+        Self {
+            require_email_verification: std::env::var("REQUIRE_EMAIL_VERIFICATION")
+                .is_ok_and(|value| value == "true" || value == "1"),
+            app_url: std::env::var("APP_URL")
+                .unwrap_or_else(|_| "http://localhost:3000".to_string())
+                .trim_end_matches('/')
+                .to_string(),
+        }
+    }
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for AuthConfig
+where
+    AppState: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = (StatusCode, String);
+
+    async fn from_request_parts(_parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let state = AppState::from_ref(state);
+        Ok(state.auth_config)
+    }
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for OAuthConfig
+where
+    AppState: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = (StatusCode, String);
+
+    async fn from_request_parts(_parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let state = AppState::from_ref(state);
+        Ok(state.oauth_config)
+    }
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for RateLimitConfig
+where
+    AppState: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = (StatusCode, String);
+
+    async fn from_request_parts(_parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let state = AppState::from_ref(state);
+        Ok(state.rate_limit_config)
+    }
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for RateLimiter
+where
+    AppState: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = (StatusCode, String);
+
+    async fn from_request_parts(_parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let state = AppState::from_ref(state);
+        Ok(RateLimiter {
+            redis: state.redis,
+            config: state.rate_limit_config,
+        })
+    }
+}
+
+pub struct AppMailer(pub Arc<dyn Mailer>);
+
+#[async_trait]
+impl<S> FromRequestParts<S> for AppMailer
+where
+    AppState: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = (StatusCode, String);
+
+    async fn from_request_parts(_parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let state = AppState::from_ref(state);
+        Ok(Self(state.mailer))
+    }
 }
 
 pub struct DatabaseConnection(pub sqlx::pool::PoolConnection<sqlx::Postgres>);
@@ -119,6 +228,23 @@ pub enum BroadcastEventAction {
     ),
 }
 
+impl BroadcastEventAction {
+    /// Pipeline the event is about, `None` for events addressed to the socket itself.
+    pub fn pipeline_id(&self) -> Option<Uuid> {
+        match self {
+            BroadcastEventAction::AuthState(_) => None,
+            BroadcastEventAction::IncludePipelineEditorParticipant(action) => {
+                Some(action.payload.pipeline_id)
+            }
+            BroadcastEventAction::ExcludePipelineEditorParticipant(action) => {
+                Some(action.payload.pipeline_id)
+            }
+            BroadcastEventAction::UpdateCursorPosition(action) => Some(action.payload.pipeline_id),
+            BroadcastEventAction::UpdateNodePosition(action) => Some(action.payload.pipeline_id),
+        }
+    }
+}
+
 #[derive(Debug, Serialize, Clone)]
 pub struct BroadcastEvent {
     pub sender_id: Uuid,
@@ -187,6 +313,16 @@ impl FromRef<AppState> for WsMessagesBroadcast {
     }
 }
 
+/// Events of `exec.events`, for requests waiting on an execution.
+#[derive(Clone)]
+pub struct ExecEventsBroadcast(pub broadcast::Sender<PipelineExecEvent>);
+
+impl FromRef<AppState> for ExecEventsBroadcast {
+    fn from_ref(state: &AppState) -> Self {
+        Self(state.exec_events_broadcast.0.clone())
+    }
+}
+
 pub struct RedisConnection(pub redis::aio::ConnectionManager);
 
 #[async_trait]
@@ -0,0 +1,117 @@
+use axum::{
+    async_trait,
+    extract::{ConnectInfo, FromRef, FromRequestParts},
+    http::{header, request::Parts},
+};
+use ipnet::IpNet;
+use std::{
+    convert::Infallible,
+    net::{IpAddr, SocketAddr},
+};
+
+use crate::{app_state::AppState, lib::session::ClientInfo};
+
+/// Address of the client, the peer's unless it's a trusted proxy. The hops of `X-Forwarded-For`
+/// are walked from the right, each trusted proxy vouching for the hop before it, up to the first
+/// one that isn't trusted. The ones further left are up to the client, so they're never read.
+fn client_ip(peer: IpAddr, forwarded_for: Option<&str>, trusted_proxies: &[IpNet]) -> IpAddr {
+    let is_trusted = |ip: &IpAddr| trusted_proxies.iter().any(|proxy| proxy.contains(ip));
+
+    let mut ip = peer;
+
+    for hop in forwarded_for.into_iter().flat_map(|hops| hops.rsplit(',')) {
+        if !is_trusted(&ip) {
+            break;
+        }
+
+        match hop.trim().parse() {
+            Ok(hop) => ip = hop,
+            Err(_) => break,
+        }
+    }
+
+    ip
+}
+
+/// Rate limits are keyed on the address, see `client_ip` for how it's resolved behind proxies.
+#[async_trait]
+impl<S> FromRequestParts<S> for ClientInfo
+where
+    AppState: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let state = AppState::from_ref(state);
+
+        let header = |name: header::HeaderName| {
+            parts
+                .headers
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(str::trim)
+                .filter(|value| !value.is_empty())
+        };
+
+        let ip = parts
+            .extensions
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(addr)| {
+                client_ip(
+                    addr.ip(),
+                    header(header::HeaderName::from_static("x-forwarded-for")),
+                    &state.rate_limit_config.trusted_proxies,
+                )
+                .to_string()
+            });
+
+        Ok(ClientInfo {
+            user_agent: header(header::USER_AGENT).map(str::to_string),
+            ip,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_client_ip() {
+        let trusted_proxies = ["10.0.0.0/8".parse().unwrap()];
+        let ip = |ip: &str| ip.parse::<IpAddr>().unwrap();
+
+        // Not behind a proxy, the header is up to the client
+        assert_eq!(
+            client_ip(ip("203.0.113.7"), Some("198.51.100.1"), &trusted_proxies),
+            ip("203.0.113.7")
+        );
+        assert_eq!(
+            client_ip(ip("10.0.0.1"), None, &trusted_proxies),
+            ip("10.0.0.1")
+        );
+        // The rightmost hop that isn't a trusted proxy, not the spoofed ones before it
+        assert_eq!(
+            client_ip(
+                ip("10.0.0.1"),
+                Some("198.51.100.1, 203.0.113.7, 10.0.0.2"),
+                &trusted_proxies
+            ),
+            ip("203.0.113.7")
+        );
+        // Every hop is a trusted proxy, the leftmost one is the closest to the client
+        assert_eq!(
+            client_ip(ip("10.0.0.1"), Some("10.0.0.3, 10.0.0.2"), &trusted_proxies),
+            ip("10.0.0.3")
+        );
+        assert_eq!(
+            client_ip(ip("10.0.0.1"), Some("unknown, 10.0.0.2"), &trusted_proxies),
+            ip("10.0.0.2")
+        );
+        assert_eq!(
+            client_ip(ip("10.0.0.1"), Some("198.51.100.1"), &[]),
+            ip("10.0.0.1")
+        );
+    }
+}
@@ -1,5 +1,5 @@
 use crate::lib::session::Session as SessionValue;
-use axum::extract::FromRef;
+use axum::extract::{FromRef, MatchedPath};
 use axum::response::IntoResponse;
 use axum::{async_trait, extract::FromRequestParts, http::request::Parts, response::Response};
 use axum_extra::{
@@ -7,7 +7,13 @@ use axum_extra::{
     TypedHeader,
 };
 
-use crate::{app_state::AppState, lib::session::validate_session_token};
+use crate::{
+    app_state::AppState,
+    lib::{
+        api_token::{is_api_token, required_scope, validate_api_token},
+        session::validate_session_token,
+    },
+};
 
 pub struct Session(pub SessionValue);
 
@@ -33,6 +39,10 @@ where
 
         let state = AppState::from_ref(state);
 
+        if is_api_token(bearer.token()) {
+            return api_token_session(parts, &state, bearer.token()).await;
+        }
+
         let session = validate_session_token(state.redis.clone(), bearer.token())
             .await
             .map_err(|_| {
@@ -54,3 +64,48 @@ where
         Ok(Session(session))
     }
 }
+
+/// Api tokens act as their user, within the scopes they were issued for.
+async fn api_token_session(
+    parts: &Parts,
+    state: &AppState,
+    token: &str,
+) -> Result<Session, Response> {
+    let mut conn = state.db.acquire().await.map_err(|error| {
+        (
+            axum::http::StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to acquire connection: {error}"),
+        )
+            .into_response()
+    })?;
+
+    let Some((session, scopes)) = validate_api_token(&mut conn, token)
+        .await
+        .map_err(IntoResponse::into_response)?
+    else {
+        return Err((
+            axum::http::StatusCode::UNAUTHORIZED,
+            "Invalid api token".to_string(),
+        )
+            .into_response());
+    };
+
+    let path = parts
+        .extensions
+        .get::<MatchedPath>()
+        .map_or_else(|| parts.uri.path(), MatchedPath::as_str);
+
+    match required_scope(&parts.method, path) {
+        Some(scope) if scopes.contains(&scope) => Ok(Session(session)),
+        Some(scope) => Err((
+            axum::http::StatusCode::FORBIDDEN,
+            format!("The api token lacks the {scope} scope"),
+        )
+            .into_response()),
+        None => Err((
+            axum::http::StatusCode::FORBIDDEN,
+            "Api tokens can't be used for this route".to_string(),
+        )
+            .into_response()),
+    }
+}
@@ -0,0 +1,22 @@
+use axum::Json;
+use hyper::StatusCode;
+use serde::Serialize;
+
+use crate::utils::internal_error;
+
+#[derive(Debug, Serialize)]
+pub struct ApiError {
+    pub error: String,
+}
+
+impl From<sqlx::Error> for ApiError {
+    fn from(err: sqlx::Error) -> Self {
+        ApiError {
+            error: err.to_string(),
+        }
+    }
+}
+
+pub fn api_error(error: sqlx::Error) -> (StatusCode, Json<ApiError>) {
+    (internal_error(&error), Json(ApiError::from(error)))
+}
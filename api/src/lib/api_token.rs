@@ -0,0 +1,215 @@
+use axum::http::Method;
+use db::dtos::ApiTokenScope;
+use hyper::StatusCode;
+use sqlx::PgConnection;
+use tracing::error;
+use uuid::Uuid;
+
+use crate::lib::{
+    session::Session,
+    token::{generate_token, hash_token},
+};
+
+/// Prefix telling api tokens apart from session tokens.
+pub const API_TOKEN_PREFIX: &str = "sbt_";
+
+/// How long `last_used_at` goes without being updated, saves a write on every request.
+const LAST_USED_AT_RESOLUTION: &str = "1 minute";
+
+pub fn generate_api_token() -> String {
+    format!("{API_TOKEN_PREFIX}{}", generate_token())
+}
+
+pub fn is_api_token(token: &str) -> bool {
+    token.starts_with(API_TOKEN_PREFIX)
+}
+
+/// Routes api tokens may call, with the scope each requires. Tokens are meant for automation,
+/// so the account, organization and team management routes only accept session tokens.
+pub fn required_scope(method: &Method, path: &str) -> Option<ApiTokenScope> {
+    let path = path.strip_prefix("/api/v0").unwrap_or(path);
+
+    match path {
+        "/trigger/pipelines/:id" | "/pipeline-execs/:id/retry" | "/pipeline-execs/:id/replay" => {
+            return Some(ApiTokenScope::Trigger)
+        }
+        "/auth/session" | "/teams" if method == Method::GET => return Some(ApiTokenScope::Read),
+        _ => {}
+    }
+
+    let automation = [
+        "/pipelines",
+        "/pipeline-triggers",
+        "/pipeline-nodes",
+        "/pipeline-node-connections",
+        "/pipeline-execs",
+        "/nodes",
+    ]
+    .iter()
+    .any(|prefix| {
+        path.strip_prefix(prefix)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+    });
+
+    match (automation, method) {
+        (false, _) => None,
+        (true, &Method::GET) => Some(ApiTokenScope::Read),
+        (true, _) => Some(ApiTokenScope::Edit),
+    }
+}
+
+/// Resolves the api token into a session of the user it was issued to, `None` when it's unknown,
+/// expired or revoked.
+pub async fn validate_api_token(
+    db: &mut PgConnection,
+    token: &str,
+) -> Result<Option<(Session, Vec<ApiTokenScope>)>, StatusCode> {
+    let api_token = sqlx::query!(
+        r#"
+        SELECT
+            t.id,
+            t.user_id,
+            u.name AS username,
+            t.scopes AS "scopes: Vec<ApiTokenScope>",
+            t.expires_at AS "expires_at: chrono::DateTime<chrono::Utc>",
+            t.created_at AS "created_at!: chrono::DateTime<chrono::Utc>",
+            (
+                t.last_used_at IS NULL
+                OR t.last_used_at < CURRENT_TIMESTAMP - $2::text::interval
+            ) AS "stale_last_used_at!"
+        FROM
+            api_tokens t
+        JOIN
+            users u ON u.id = t.user_id
+        WHERE
+            t.token_hash = $1
+            AND t.revoked_at IS NULL
+            AND (t.expires_at IS NULL OR t.expires_at > CURRENT_TIMESTAMP)
+        "#,
+        hash_token(token),
+        LAST_USED_AT_RESOLUTION
+    )
+    .fetch_optional(&mut *db)
+    .await
+    .map_err(|error| {
+        error!("Failed to look up api token: {error}");
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    let Some(api_token) = api_token else {
+        return Ok(None);
+    };
+
+    if api_token.stale_last_used_at {
+        sqlx::query!(
+            r#"
+            UPDATE
+                api_tokens
+            SET
+                last_used_at = CURRENT_TIMESTAMP
+            WHERE
+                id = $1
+            "#,
+            api_token.id
+        )
+        .execute(&mut *db)
+        .await
+        .map_err(|error| {
+            error!(
+                "Failed to update the last use of api token {}: {error}",
+                api_token.id
+            );
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    }
+
+    let session = Session {
+        id: format!("api_token:{}", api_token.id),
+        user_id: api_token.user_id,
+        username: api_token.username,
+        expires_at: api_token
+            .expires_at
+            .unwrap_or(chrono::DateTime::<chrono::Utc>::MAX_UTC),
+        user_agent: None,
+        ip: None,
+        created_at: api_token.created_at,
+        last_seen_at: chrono::Utc::now(),
+    };
+
+    Ok(Some((session, api_token.scopes)))
+}
+
+/// Issues an api token to the user along with its id and creation date, only its hash is stored
+/// so it's returned this once.
+pub async fn create_api_token(
+    db: &mut PgConnection,
+    user_id: Uuid,
+    created_by_user_id: Uuid,
+    name: &str,
+    scopes: &[ApiTokenScope],
+    expires_at: Option<chrono::DateTime<chrono::Utc>>,
+) -> Result<(String, Uuid, chrono::DateTime<chrono::Utc>), sqlx::Error> {
+    let token = generate_api_token();
+
+    let api_token = sqlx::query!(
+        r#"
+        INSERT INTO
+            api_tokens (user_id, name, token_hash, scopes, expires_at, created_by_user_id)
+        VALUES
+            ($1, $2, $3, $4, $5, $6)
+        RETURNING
+            id, created_at AS "created_at!: chrono::DateTime<chrono::Utc>"
+        "#,
+        user_id,
+        name,
+        hash_token(&token),
+        scopes as &[ApiTokenScope],
+        expires_at as _,
+        created_by_user_id
+    )
+    .fetch_one(&mut *db)
+    .await?;
+
+    Ok((token, api_token.id, api_token.created_at))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_required_scope() {
+        assert_eq!(
+            required_scope(&Method::POST, "/api/v0/trigger/pipelines/:id"),
+            Some(ApiTokenScope::Trigger)
+        );
+        assert_eq!(
+            required_scope(&Method::POST, "/pipeline-execs/:id/replay"),
+            Some(ApiTokenScope::Trigger)
+        );
+        assert_eq!(
+            required_scope(&Method::GET, "/pipeline-execs/:id"),
+            Some(ApiTokenScope::Read)
+        );
+        assert_eq!(
+            required_scope(&Method::PATCH, "/pipeline-nodes/:id"),
+            Some(ApiTokenScope::Edit)
+        );
+        assert_eq!(
+            required_scope(&Method::GET, "/teams"),
+            Some(ApiTokenScope::Read)
+        );
+        assert_eq!(required_scope(&Method::POST, "/teams"), None);
+        assert_eq!(required_scope(&Method::POST, "/auth/password/change"), None);
+        assert_eq!(required_scope(&Method::GET, "/api-tokens"), None);
+        assert_eq!(required_scope(&Method::GET, "/pipelinesx"), None);
+    }
+
+    #[test]
+    fn test_generate_api_token() {
+        let token = generate_api_token();
+
+        assert!(is_api_token(&token));
+        assert!(!is_api_token(&generate_token()));
+    }
+}
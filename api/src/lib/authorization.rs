@@ -0,0 +1,394 @@
+use axum::Json;
+use db::dtos::MemberRole;
+use hyper::StatusCode;
+use sqlx::PgConnection;
+use uuid::Uuid;
+
+use crate::{lib::api_error::ApiError, utils::internal_error};
+
+/// Why the caller can't reach a resource.
+#[derive(Debug)]
+pub enum AccessError {
+    /// The resource doesn't exist.
+    NotFound,
+    /// The caller's role in the team or organization owning the resource is below the required one.
+    Forbidden(MemberRole),
+    /// The team or organization owning the resource requires two-factor authentication, which the
+    /// caller hasn't enabled.
+    TwoFactorRequired,
+    Database(sqlx::Error),
+}
+
+impl From<sqlx::Error> for AccessError {
+    fn from(error: sqlx::Error) -> Self {
+        AccessError::Database(error)
+    }
+}
+
+impl From<AccessError> for StatusCode {
+    fn from(error: AccessError) -> Self {
+        match error {
+            AccessError::NotFound => StatusCode::NOT_FOUND,
+            AccessError::Forbidden(_) | AccessError::TwoFactorRequired => StatusCode::FORBIDDEN,
+            AccessError::Database(error) => internal_error(error),
+        }
+    }
+}
+
+impl From<AccessError> for (StatusCode, Json<ApiError>) {
+    fn from(error: AccessError) -> Self {
+        let message = match &error {
+            AccessError::NotFound => "Not found".to_string(),
+            AccessError::Forbidden(role) => {
+                format!("Requires the {role} role in the team or organization owning the resource")
+            }
+            AccessError::TwoFactorRequired => {
+                "The team or organization owning the resource requires two-factor authentication"
+                    .to_string()
+            }
+            AccessError::Database(error) => error.to_string(),
+        };
+
+        (error.into(), Json(ApiError { error: message }))
+    }
+}
+
+/// `None` when the resource wasn't found, otherwise the caller's role in its team, if any.
+fn check_role(
+    member_role: Option<Option<MemberRole>>,
+    required: MemberRole,
+) -> Result<MemberRole, AccessError> {
+    match member_role {
+        None => Err(AccessError::NotFound),
+        Some(Some(role)) if role >= required => Ok(role),
+        Some(_) => Err(AccessError::Forbidden(required)),
+    }
+}
+
+/// Policies requiring two-factor authentication apply to every member but service accounts, which
+/// only authenticate with api tokens.
+fn check_two_factor(required: bool, enabled: bool) -> Result<(), AccessError> {
+    if required && !enabled {
+        Err(AccessError::TwoFactorRequired)
+    } else {
+        Ok(())
+    }
+}
+
+/// Members can only grant roles up to their own, and only change the role of members not above them.
+pub fn check_role_change(
+    caller: MemberRole,
+    current: Option<MemberRole>,
+    granted: Option<MemberRole>,
+) -> Result<(), AccessError> {
+    match current.max(granted) {
+        Some(role) if role > caller => Err(AccessError::Forbidden(role)),
+        _ => Ok(()),
+    }
+}
+
+pub async fn authorize_organization(
+    db: &mut PgConnection,
+    user_id: Uuid,
+    organization_id: Uuid,
+    required: MemberRole,
+) -> Result<MemberRole, AccessError> {
+    let organization = sqlx::query!(
+        r#"
+        SELECT
+            (
+                SELECT u.organization_role FROM users u WHERE u.id = $2 AND u.organization_id = o.id
+            ) AS "role: MemberRole",
+            o.require_two_factor,
+            EXISTS(
+                SELECT 1 FROM users u
+                WHERE u.id = $2 AND (u.totp_enabled_at IS NOT NULL OR u.service_account_team_id IS NOT NULL)
+            ) AS "two_factor_enabled!"
+        FROM
+            organizations o
+        WHERE
+            o.id = $1
+        "#,
+        organization_id,
+        user_id
+    )
+    .fetch_optional(&mut *db)
+    .await?;
+
+    let role = check_role(
+        organization.as_ref().map(|organization| organization.role),
+        required,
+    )?;
+
+    if let Some(organization) = organization {
+        check_two_factor(
+            organization.require_two_factor,
+            organization.two_factor_enabled,
+        )?;
+    }
+
+    Ok(role)
+}
+
+/// Resolves the organization role as well, which applies to every team of the organization.
+pub async fn authorize_team(
+    db: &mut PgConnection,
+    user_id: Uuid,
+    team_id: Uuid,
+    required: MemberRole,
+) -> Result<MemberRole, AccessError> {
+    let team = sqlx::query!(
+        r#"
+        SELECT
+            team_member_role($2, t.id) AS "role: MemberRole",
+            (t.require_two_factor OR o.require_two_factor) AS "require_two_factor!",
+            EXISTS(
+                SELECT 1 FROM users u
+                WHERE u.id = $2 AND (u.totp_enabled_at IS NOT NULL OR u.service_account_team_id IS NOT NULL)
+            ) AS "two_factor_enabled!"
+        FROM
+            teams t
+        JOIN
+            organizations o ON o.id = t.organization_id
+        WHERE
+            t.id = $1
+        "#,
+        team_id,
+        user_id
+    )
+    .fetch_optional(&mut *db)
+    .await?;
+
+    let role = check_role(team.as_ref().map(|team| team.role), required)?;
+
+    if let Some(team) = team {
+        check_two_factor(team.require_two_factor, team.two_factor_enabled)?;
+    }
+
+    Ok(role)
+}
+
+/// Checks the caller's role in the team owning a resource, `None` when the resource wasn't found.
+async fn authorize_owning_team(
+    db: &mut PgConnection,
+    user_id: Uuid,
+    team_id: Option<Uuid>,
+    required: MemberRole,
+) -> Result<MemberRole, AccessError> {
+    match team_id {
+        Some(team_id) => authorize_team(db, user_id, team_id, required).await,
+        None => Err(AccessError::NotFound),
+    }
+}
+
+pub async fn authorize_pipeline(
+    db: &mut PgConnection,
+    user_id: Uuid,
+    pipeline_id: Uuid,
+    required: MemberRole,
+) -> Result<MemberRole, AccessError> {
+    let team_id = sqlx::query!(
+        r#"
+        SELECT
+            team_id
+        FROM
+            pipelines
+        WHERE
+            id = $1
+        "#,
+        pipeline_id
+    )
+    .fetch_optional(&mut *db)
+    .await?
+    .map(|pipeline| pipeline.team_id);
+
+    authorize_owning_team(db, user_id, team_id, required).await
+}
+
+pub async fn authorize_pipeline_node(
+    db: &mut PgConnection,
+    user_id: Uuid,
+    pipeline_node_id: Uuid,
+    required: MemberRole,
+) -> Result<MemberRole, AccessError> {
+    let team_id = sqlx::query!(
+        r#"
+        SELECT
+            p.team_id
+        FROM
+            pipeline_nodes pn
+        JOIN
+            pipelines p ON p.id = pn.pipeline_id
+        WHERE
+            pn.id = $1
+        "#,
+        pipeline_node_id
+    )
+    .fetch_optional(&mut *db)
+    .await?
+    .map(|pipeline_node| pipeline_node.team_id);
+
+    authorize_owning_team(db, user_id, team_id, required).await
+}
+
+pub async fn authorize_pipeline_node_input(
+    db: &mut PgConnection,
+    user_id: Uuid,
+    pipeline_node_input_id: Uuid,
+    required: MemberRole,
+) -> Result<MemberRole, AccessError> {
+    let team_id = sqlx::query!(
+        r#"
+        SELECT
+            p.team_id
+        FROM
+            pipeline_node_inputs pni
+        JOIN
+            pipeline_nodes pn ON pn.id = pni.pipeline_node_id
+        JOIN
+            pipelines p ON p.id = pn.pipeline_id
+        WHERE
+            pni.id = $1
+        "#,
+        pipeline_node_input_id
+    )
+    .fetch_optional(&mut *db)
+    .await?
+    .map(|input| input.team_id);
+
+    authorize_owning_team(db, user_id, team_id, required).await
+}
+
+pub async fn authorize_pipeline_node_output(
+    db: &mut PgConnection,
+    user_id: Uuid,
+    pipeline_node_output_id: Uuid,
+    required: MemberRole,
+) -> Result<MemberRole, AccessError> {
+    let team_id = sqlx::query!(
+        r#"
+        SELECT
+            p.team_id
+        FROM
+            pipeline_node_outputs pno
+        JOIN
+            pipeline_nodes pn ON pn.id = pno.pipeline_node_id
+        JOIN
+            pipelines p ON p.id = pn.pipeline_id
+        WHERE
+            pno.id = $1
+        "#,
+        pipeline_node_output_id
+    )
+    .fetch_optional(&mut *db)
+    .await?
+    .map(|output| output.team_id);
+
+    authorize_owning_team(db, user_id, team_id, required).await
+}
+
+pub async fn authorize_pipeline_trigger(
+    db: &mut PgConnection,
+    user_id: Uuid,
+    pipeline_trigger_id: Uuid,
+    required: MemberRole,
+) -> Result<MemberRole, AccessError> {
+    let team_id = sqlx::query!(
+        r#"
+        SELECT
+            p.team_id
+        FROM
+            pipeline_triggers pt
+        JOIN
+            pipelines p ON p.id = pt.pipeline_id
+        WHERE
+            pt.id = $1
+        "#,
+        pipeline_trigger_id
+    )
+    .fetch_optional(&mut *db)
+    .await?
+    .map(|trigger| trigger.team_id);
+
+    authorize_owning_team(db, user_id, team_id, required).await
+}
+
+pub async fn authorize_pipeline_exec(
+    db: &mut PgConnection,
+    user_id: Uuid,
+    pipeline_exec_id: Uuid,
+    required: MemberRole,
+) -> Result<MemberRole, AccessError> {
+    let team_id = sqlx::query!(
+        r#"
+        SELECT
+            p.team_id
+        FROM
+            pipeline_execs pe
+        JOIN
+            pipelines p ON p.id = pe.pipeline_id
+        WHERE
+            pe.id = $1
+        "#,
+        pipeline_exec_id
+    )
+    .fetch_optional(&mut *db)
+    .await?
+    .map(|pipeline_exec| pipeline_exec.team_id);
+
+    authorize_owning_team(db, user_id, team_id, required).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_role() {
+        assert!(matches!(
+            check_role(None, MemberRole::Viewer),
+            Err(AccessError::NotFound)
+        ));
+        assert!(matches!(
+            check_role(Some(None), MemberRole::Viewer),
+            Err(AccessError::Forbidden(MemberRole::Viewer))
+        ));
+        assert!(matches!(
+            check_role(Some(Some(MemberRole::Viewer)), MemberRole::Runner),
+            Err(AccessError::Forbidden(MemberRole::Runner))
+        ));
+        assert!(matches!(
+            check_role(Some(Some(MemberRole::Editor)), MemberRole::Runner),
+            Ok(MemberRole::Editor)
+        ));
+    }
+
+    #[test]
+    fn test_check_two_factor() {
+        assert!(check_two_factor(false, false).is_ok());
+        assert!(check_two_factor(true, true).is_ok());
+        assert!(matches!(
+            check_two_factor(true, false),
+            Err(AccessError::TwoFactorRequired)
+        ));
+    }
+
+    #[test]
+    fn test_check_role_change() {
+        assert!(check_role_change(MemberRole::Admin, None, Some(MemberRole::Admin)).is_ok());
+        assert!(check_role_change(
+            MemberRole::Admin,
+            Some(MemberRole::Editor),
+            Some(MemberRole::Viewer)
+        )
+        .is_ok());
+        assert!(matches!(
+            check_role_change(MemberRole::Admin, None, Some(MemberRole::Owner)),
+            Err(AccessError::Forbidden(MemberRole::Owner))
+        ));
+        assert!(matches!(
+            check_role_change(MemberRole::Admin, Some(MemberRole::Owner), None),
+            Err(AccessError::Forbidden(MemberRole::Owner))
+        ));
+    }
+}
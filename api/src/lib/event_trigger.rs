@@ -0,0 +1,330 @@
+use async_nats::jetstream::{
+    self,
+    consumer::{pull, Consumer},
+    AckKind,
+};
+use db::dtos::{
+    EventParamMapping, PipelineExec, PipelineExecPayloadParams, PipelineTriggerConfig,
+    PipelineTriggerSource,
+};
+use futures::StreamExt;
+use hyper::StatusCode;
+use sqlx::PgPool;
+use std::{collections::HashMap, error::Error, time::Duration};
+use tracing::{debug, error, info, warn};
+use uuid::Uuid;
+
+use crate::lib::{
+    pipeline_exec::{
+        insert_node_param, insert_pipeline_exec, publish_pipeline_exec, PipelineExecOrigin,
+    },
+    rate_limit::{RateLimited, RateLimiter},
+};
+
+/// Longest chain of pipeline executions started by event triggers of one another.
+pub const MAX_EVENT_TRIGGER_CHAIN_DEPTH: usize = 16;
+
+const CONSUMER_RETRY_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Starts the pipelines whose event triggers watch the pipeline executions finished on `exec.events`.
+/// An event is redelivered once the quota of the team allows for the runs it didn't start.
+pub async fn listen(
+    db: PgPool,
+    jetstream: jetstream::Context,
+    mut rate_limiter: RateLimiter,
+    consumer: Consumer<pull::Config>,
+) {
+    loop {
+        let mut messages = match consumer.messages().await {
+            Ok(messages) => messages,
+            Err(error) => {
+                error!("Failed to get exec events for event triggers: {error:?}");
+                tokio::time::sleep(CONSUMER_RETRY_INTERVAL).await;
+                continue;
+            }
+        };
+
+        while let Some(message) = messages.next().await {
+            let message = match message {
+                Ok(message) => message,
+                Err(error) => {
+                    error!("Failed to receive exec event: {error:?}");
+                    continue;
+                }
+            };
+
+            let ack = match handle_exec_event(&db, &jetstream, &mut rate_limiter, &message.payload)
+                .await
+            {
+                Ok(Ok(())) => AckKind::Ack,
+                Ok(Err(rate_limited)) => {
+                    AckKind::Nak(Some(Duration::from_secs(rate_limited.retry_after_secs)))
+                }
+                Err(error) => {
+                    error!("Failed to handle exec event for event triggers: {error:?}");
+                    AckKind::Ack
+                }
+            };
+
+            if let Err(error) = message.ack_with(ack).await {
+                error!("Failed to ack exec event: {error}");
+            }
+        }
+    }
+}
+
+async fn handle_exec_event(
+    db: &PgPool,
+    jetstream: &jetstream::Context,
+    rate_limiter: &mut RateLimiter,
+    payload: &[u8],
+) -> Result<Result<(), RateLimited>, Box<dyn Error + Send + Sync>> {
+    let raw_value = serde_json::from_slice::<serde_json::Value>(payload)?;
+
+    // Node execution events are of no interest, only whole pipeline executions trigger others
+    if raw_value.get("pipeline_node_id").is_some() {
+        return Ok(Ok(()));
+    }
+
+    let pipeline_exec = serde_json::from_value::<PipelineExec>(raw_value)?;
+
+    if !pipeline_exec.status.is_terminal() {
+        return Ok(Ok(()));
+    }
+
+    let triggers = sqlx::query!(
+        r#"
+        SELECT
+            id, pipeline_id, config
+        FROM
+            pipeline_triggers
+        WHERE
+            config->'source'->>'type' = 'event' AND config->'source'->>'pipeline_id' = $1
+        "#,
+        pipeline_exec.pipeline_id.to_string()
+    )
+    .fetch_all(db)
+    .await?;
+
+    if triggers.is_empty() {
+        return Ok(Ok(()));
+    }
+
+    let chain = sqlx::query!(
+        r#"
+        WITH RECURSIVE chain AS (
+            SELECT
+                id, pipeline_id, parent_pipeline_exec_id
+            FROM
+                pipeline_execs
+            WHERE
+                id = $1
+            UNION ALL
+            SELECT
+                pe.id, pe.pipeline_id, pe.parent_pipeline_exec_id
+            FROM
+                pipeline_execs pe
+            JOIN
+                chain ON pe.id = chain.parent_pipeline_exec_id
+        )
+        SELECT
+            pipeline_id AS "pipeline_id!"
+        FROM
+            chain
+        "#,
+        pipeline_exec.id
+    )
+    .fetch_all(db)
+    .await?
+    .into_iter()
+    .map(|row| row.pipeline_id)
+    .collect::<Vec<Uuid>>();
+
+    let results = sqlx::query!(
+        r#"
+        SELECT
+            pipeline_node_id, result
+        FROM
+            pipeline_node_execs
+        WHERE
+            pipeline_exec_id = $1 AND status = 'completed'
+        "#,
+        pipeline_exec.id
+    )
+    .fetch_all(db)
+    .await?
+    .into_iter()
+    .filter_map(|row| Some((row.pipeline_node_id, row.result?)))
+    .collect::<HashMap<Uuid, serde_json::Value>>();
+
+    for trigger in triggers {
+        let config = serde_json::from_value::<PipelineTriggerConfig>(trigger.config)?;
+
+        let Some(PipelineTriggerSource::Event(event_config)) = config.source() else {
+            continue;
+        };
+
+        if !event_config.statuses.contains(&pipeline_exec.status) {
+            continue;
+        }
+
+        if is_trigger_loop(&chain, trigger.pipeline_id) {
+            warn!(
+                "Skipped event trigger {} of pipeline {}, it would loop over pipeline exec {}",
+                trigger.id, trigger.pipeline_id, pipeline_exec.id
+            );
+
+            continue;
+        }
+
+        // The same finished execution may be announced more than once, and is announced again
+        // after going over the quota, the runs started already are skipped then
+        let already_started = sqlx::query_scalar!(
+            r#"
+            SELECT EXISTS (
+                SELECT 1
+                FROM pipeline_execs
+                WHERE pipeline_trigger_id = $1 AND parent_pipeline_exec_id = $2
+            ) AS "exists!"
+            "#,
+            trigger.id,
+            pipeline_exec.id
+        )
+        .fetch_one(db)
+        .await?;
+
+        if already_started {
+            debug!(
+                "Event trigger {} already started by pipeline exec {}",
+                trigger.id, pipeline_exec.id
+            );
+
+            continue;
+        }
+
+        let params = map_event_params(&event_config.params, &results);
+
+        // The unique (pipeline_trigger_id, parent_pipeline_exec_id) pair still makes sure
+        // concurrent deliveries start one run
+        let started = insert_pipeline_exec(
+            &mut *db.acquire().await?,
+            rate_limiter,
+            trigger.pipeline_id,
+            Some(trigger.id),
+            &PipelineExecOrigin {
+                parent_pipeline_exec_id: Some(pipeline_exec.id),
+                ..Default::default()
+            },
+            &params,
+        )
+        .await;
+
+        let started_id = match started {
+            Ok(Ok(started_id)) => started_id,
+            Ok(Err(rate_limited)) => {
+                warn!(
+                    "Event trigger {} of pipeline {} is over the trigger quota, retrying in {} seconds",
+                    trigger.id, trigger.pipeline_id, rate_limited.retry_after_secs
+                );
+
+                return Ok(Err(rate_limited));
+            }
+            Err(StatusCode::CONFLICT) => {
+                debug!(
+                    "Event trigger {} already started by pipeline exec {}",
+                    trigger.id, pipeline_exec.id
+                );
+
+                continue;
+            }
+            Err(status) => return Err(format!("Failed to start pipeline exec: {status}").into()),
+        };
+
+        publish_pipeline_exec(
+            jetstream,
+            trigger.pipeline_id,
+            started_id,
+            Some(trigger.id),
+            params,
+        )
+        .await?;
+
+        info!(
+            "Pipeline exec {} started by event trigger {} after pipeline exec {}",
+            started_id, trigger.id, pipeline_exec.id
+        );
+    }
+
+    Ok(Ok(()))
+}
+
+/// Whether starting `pipeline_id` after the executions `chain` (most recent first)
+/// would run a pipeline again or exceed [`MAX_EVENT_TRIGGER_CHAIN_DEPTH`].
+pub fn is_trigger_loop(chain: &[Uuid], pipeline_id: Uuid) -> bool {
+    chain.len() >= MAX_EVENT_TRIGGER_CHAIN_DEPTH || chain.contains(&pipeline_id)
+}
+
+/// Builds trigger params out of the upstream node results, mappings whose source is missing are skipped.
+pub fn map_event_params(
+    mappings: &[EventParamMapping],
+    results: &HashMap<Uuid, serde_json::Value>,
+) -> PipelineExecPayloadParams {
+    let mut params = PipelineExecPayloadParams::new();
+
+    for mapping in mappings {
+        let Some(result) = results.get(&mapping.source_pipeline_node_id) else {
+            continue;
+        };
+
+        let value = match mapping.output.as_ref() {
+            Some(output) => result.get(output).cloned(),
+            None => Some(result.clone()),
+        };
+
+        if let Some(value) = value {
+            insert_node_param(&mut params, mapping.pipeline_node_id, &mapping.input, value);
+        }
+    }
+
+    params
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_trigger_loop() {
+        let (a, b) = (Uuid::new_v4(), Uuid::new_v4());
+
+        assert!(is_trigger_loop(&[a], a));
+        assert!(!is_trigger_loop(&[a], b));
+        assert!(is_trigger_loop(&vec![a; MAX_EVENT_TRIGGER_CHAIN_DEPTH], b));
+    }
+
+    #[test]
+    fn test_map_event_params() {
+        let (source_node_id, target_node_id) = (Uuid::new_v4(), Uuid::new_v4());
+        let mappings = vec![
+            EventParamMapping {
+                pipeline_node_id: target_node_id,
+                input: "message".to_string(),
+                source_pipeline_node_id: source_node_id,
+                output: Some("text".to_string()),
+            },
+            EventParamMapping {
+                pipeline_node_id: target_node_id,
+                input: "missing".to_string(),
+                source_pipeline_node_id: Uuid::new_v4(),
+                output: None,
+            },
+        ];
+
+        let results = HashMap::from([(source_node_id, serde_json::json!({ "text": "hello" }))]);
+
+        assert_eq!(
+            map_event_params(&mappings, &results).get(&target_node_id),
+            Some(&serde_json::json!({ "message": "hello" }))
+        );
+    }
+}
@@ -0,0 +1,108 @@
+use db::dtos::{PipelineExec, PipelineExecEvent, PipelineNodeExec};
+use futures::StreamExt;
+use std::time::Duration;
+use tokio::sync::broadcast;
+use tracing::{debug, error};
+
+const SUBSCRIBE_RETRY_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Parses an event published by the event bridge on `exec.events`, node execution events are the
+/// ones carrying a `pipeline_node_id`.
+pub fn parse_exec_event(payload: &[u8]) -> Result<PipelineExecEvent, serde_json::Error> {
+    let raw_value = serde_json::from_slice::<serde_json::Value>(payload)?;
+
+    if raw_value.get("pipeline_node_id").is_some() {
+        serde_json::from_value::<PipelineNodeExec>(raw_value).map(PipelineExecEvent::Node)
+    } else {
+        serde_json::from_value::<PipelineExec>(raw_value).map(PipelineExecEvent::Pipeline)
+    }
+}
+
+/// Forwards the events of `exec.events` to in-process subscribers.
+///
+/// Uses a core NATS subscription rather than a durable consumer, every api instance gets every
+/// event and requests waiting on an execution don't compete with the event triggers for them.
+pub async fn forward(
+    nats_client: async_nats::Client,
+    sender: broadcast::Sender<PipelineExecEvent>,
+) {
+    loop {
+        let mut subscriber = match nats_client.subscribe("exec.events").await {
+            Ok(subscriber) => subscriber,
+            Err(error) => {
+                error!("Failed to subscribe to exec events: {error:?}");
+                tokio::time::sleep(SUBSCRIBE_RETRY_INTERVAL).await;
+                continue;
+            }
+        };
+
+        while let Some(message) = subscriber.next().await {
+            match parse_exec_event(&message.payload) {
+                // Sending only fails when nobody is waiting on an execution
+                Ok(event) => {
+                    let _ = sender.send(event);
+                }
+                Err(error) => debug!("Ignoring malformed exec event: {error}"),
+            }
+        }
+
+        error!("Exec events subscription closed, subscribing again");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use db::dtos::ExecStatus;
+    use serde_json::json;
+    use uuid::Uuid;
+
+    #[test]
+    fn test_parse_exec_event() {
+        let pipeline_exec_id = Uuid::new_v4();
+
+        let event = parse_exec_event(
+            json!({
+                "id": pipeline_exec_id,
+                "pipeline_id": Uuid::new_v4(),
+                "status": "completed",
+                "created_at": "2025-01-01T00:00:00Z",
+                "started_at": null,
+                "finished_at": null,
+            })
+            .to_string()
+            .as_bytes(),
+        )
+        .unwrap();
+
+        assert!(matches!(
+            event,
+            PipelineExecEvent::Pipeline(PipelineExec { id, status: ExecStatus::Completed, .. })
+                if id == pipeline_exec_id
+        ));
+
+        let event = parse_exec_event(
+            json!({
+                "id": Uuid::new_v4(),
+                "pipeline_exec_id": pipeline_exec_id,
+                "pipeline_node_id": Uuid::new_v4(),
+                "status": "failed",
+                "result": null,
+                "created_at": "2025-01-01T00:00:00Z",
+                "started_at": null,
+                "finished_at": null,
+            })
+            .to_string()
+            .as_bytes(),
+        )
+        .unwrap();
+
+        assert!(matches!(
+            event,
+            PipelineExecEvent::Node(PipelineNodeExec { pipeline_exec_id: id, status: ExecStatus::Failed, .. })
+                if id == pipeline_exec_id
+        ));
+
+        assert!(parse_exec_event(b"not json").is_err());
+    }
+}
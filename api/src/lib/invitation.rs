@@ -0,0 +1,192 @@
+use axum::Json;
+use db::dtos::MemberRole;
+use hyper::StatusCode;
+use sqlx::PgConnection;
+use uuid::Uuid;
+
+use crate::lib::{
+    api_error::{api_error, ApiError},
+    token::hash_token,
+};
+
+/// How long an invitation can be accepted for.
+pub const INVITATION_TTL: chrono::Duration = chrono::Duration::days(7);
+
+pub struct Invitation {
+    pub id: Uuid,
+    pub organization_id: Uuid,
+    pub team_id: Option<Uuid>,
+    pub email: String,
+    pub role: MemberRole,
+    pub expires_at: chrono::DateTime<chrono::Utc>,
+    pub accepted_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+/// Why an invitation can't be accepted, `None` when it can.
+pub fn invitation_rejection(
+    invitation: &Invitation,
+    email: &str,
+    now: chrono::DateTime<chrono::Utc>,
+) -> Option<&'static str> {
+    if invitation.accepted_at.is_some() {
+        Some("The invitation was already accepted")
+    } else if invitation.expires_at <= now {
+        Some("The invitation expired")
+    } else if !invitation.email.eq_ignore_ascii_case(email) {
+        Some("The invitation was sent to another email address")
+    } else {
+        None
+    }
+}
+
+/// Locks the invitation the token was issued for.
+pub async fn lock_invitation(
+    db: &mut PgConnection,
+    token: &str,
+) -> Result<Option<Invitation>, (StatusCode, Json<ApiError>)> {
+    sqlx::query_as!(
+        Invitation,
+        r#"
+        SELECT
+            id,
+            organization_id,
+            team_id,
+            email,
+            role AS "role: MemberRole",
+            expires_at AS "expires_at: chrono::DateTime<chrono::Utc>",
+            accepted_at AS "accepted_at: chrono::DateTime<chrono::Utc>"
+        FROM
+            invitations
+        WHERE
+            token_hash = $1
+        FOR UPDATE
+        "#,
+        hash_token(token)
+    )
+    .fetch_optional(&mut *db)
+    .await
+    .map_err(api_error)
+}
+
+/// Whether the user has a role in their organization or one of its teams.
+pub async fn has_memberships(
+    db: &mut PgConnection,
+    user_id: Uuid,
+) -> Result<bool, (StatusCode, Json<ApiError>)> {
+    sqlx::query!(
+        r#"
+        SELECT
+            (
+                u.organization_role IS NOT NULL
+                OR EXISTS(SELECT 1 FROM user_teams ut WHERE ut.user_id = u.id)
+            ) AS "has_memberships!"
+        FROM
+            users u
+        WHERE
+            u.id = $1
+        "#,
+        user_id
+    )
+    .fetch_one(&mut *db)
+    .await
+    .map(|user| user.has_memberships)
+    .map_err(api_error)
+}
+
+/// Moves the user into the invitation's organization and grants the invited role, in the team
+/// when the invitation is for one. Roles the user already has are never lowered.
+pub async fn join_invitation(
+    db: &mut PgConnection,
+    invitation: &Invitation,
+    user_id: Uuid,
+) -> Result<(), (StatusCode, Json<ApiError>)> {
+    sqlx::query!(
+        r#"
+        UPDATE
+            users
+        SET
+            organization_id = $1,
+            organization_role = CASE
+                WHEN $2::uuid IS NULL THEN GREATEST(organization_role, $3)
+                ELSE organization_role
+            END
+        WHERE
+            id = $4
+        "#,
+        invitation.organization_id,
+        invitation.team_id,
+        invitation.role as MemberRole,
+        user_id
+    )
+    .execute(&mut *db)
+    .await
+    .map_err(api_error)?;
+
+    if let Some(team_id) = invitation.team_id {
+        sqlx::query!(
+            r#"
+            INSERT INTO
+                user_teams (user_id, team_id, role)
+            VALUES
+                ($1, $2, $3)
+            ON CONFLICT (user_id, team_id) DO UPDATE SET
+                role = GREATEST(user_teams.role, EXCLUDED.role)
+            "#,
+            user_id,
+            team_id,
+            invitation.role as MemberRole
+        )
+        .execute(&mut *db)
+        .await
+        .map_err(api_error)?;
+    }
+
+    sqlx::query!(
+        r#"
+        UPDATE
+            invitations
+        SET
+            accepted_at = CURRENT_TIMESTAMP
+        WHERE
+            id = $1
+        "#,
+        invitation.id
+    )
+    .execute(&mut *db)
+    .await
+    .map_err(api_error)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_invitation_rejection() {
+        let now = chrono::Utc::now();
+        let mut invitation = Invitation {
+            id: Uuid::new_v4(),
+            organization_id: Uuid::new_v4(),
+            team_id: None,
+            email: "Bob@example.com".to_string(),
+            role: MemberRole::Editor,
+            expires_at: now + INVITATION_TTL,
+            accepted_at: None,
+        };
+
+        assert_eq!(
+            invitation_rejection(&invitation, "bob@example.com", now),
+            None
+        );
+        assert!(invitation_rejection(&invitation, "alex@example.com", now).is_some());
+
+        invitation.expires_at = now;
+        assert!(invitation_rejection(&invitation, "bob@example.com", now).is_some());
+
+        invitation.expires_at = now + INVITATION_TTL;
+        invitation.accepted_at = Some(now);
+        assert!(invitation_rejection(&invitation, "bob@example.com", now).is_some());
+    }
+}
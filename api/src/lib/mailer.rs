@@ -0,0 +1,71 @@
+use axum::async_trait;
+use std::{error::Error, path::PathBuf, sync::Arc};
+use tokio::io::AsyncWriteExt;
+use tracing::info;
+
+#[derive(Debug, Clone)]
+pub struct Mail {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+/// Delivers the mails sent to users, picked with the `MAILER` environment variable.
+#[async_trait]
+pub trait Mailer: Send + Sync {
+    async fn send(&self, mail: Mail) -> Result<(), Box<dyn Error + Send + Sync>>;
+}
+
+/// Logs mails instead of delivering them, for local development.
+pub struct LogMailer;
+
+#[async_trait]
+impl Mailer for LogMailer {
+    async fn send(&self, mail: Mail) -> Result<(), Box<dyn Error + Send + Sync>> {
+        info!(
+            "Mail to {} with subject \"{}\":\n{}",
+            mail.to, mail.subject, mail.body
+        );
+
+        Ok(())
+    }
+}
+
+/// Appends mails to a file instead of delivering them, for local development.
+pub struct FileMailer {
+    pub path: PathBuf,
+}
+
+#[async_trait]
+impl Mailer for FileMailer {
+    async fn send(&self, mail: Mail) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let mut file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .await?;
+
+        file.write_all(
+            format!(
+                "To: {}\nSubject: {}\n\n{}\n\n",
+                mail.to, mail.subject, mail.body
+            )
+            .as_bytes(),
+        )
+        .await?;
+
+        Ok(())
+    }
+}
+
+/// `MAILER=file` writes mails to `MAILER_FILE`, anything else logs them.
+pub fn mailer_from_env() -> Arc<dyn Mailer> {
+    match std::env::var("MAILER").as_deref() {
+        Ok("file") => Arc::new(FileMailer {
+            path: std::env::var("MAILER_FILE")
+                .unwrap_or_else(|_| "mails.log".to_string())
+                .into(),
+        }),
+        _ => Arc::new(LogMailer),
+    }
+}
@@ -1 +1,20 @@
+pub mod api_error;
+pub mod api_token;
+pub mod authorization;
+pub mod event_trigger;
+pub mod exec_events;
+pub mod invitation;
+pub mod mailer;
+pub mod nats_trigger;
+pub mod oauth;
+pub mod password;
+pub mod pipeline_exec;
+pub mod pipeline_exec_diff;
+pub mod pipeline_graph;
+pub mod pipeline_params;
+pub mod rate_limit;
 pub mod session;
+pub mod token;
+pub mod totp;
+pub mod user_token;
+pub mod webhook;
@@ -0,0 +1,633 @@
+use async_nats::{
+    jetstream::{self, consumer::pull, AckKind},
+    HeaderMap, Subject,
+};
+use db::dtos::{
+    NatsParamMapping, NatsParamSource, NatsTriggerConfig, PipelineExecPayloadParams,
+    PipelineTriggerConfig, PipelineTriggerSource,
+};
+use futures::StreamExt;
+use sqlx::PgPool;
+use std::{collections::HashMap, error::Error, time::Duration};
+use tokio::task::JoinHandle;
+use tracing::{error, info};
+use uuid::Uuid;
+
+use crate::lib::{
+    pipeline_exec::{
+        insert_node_param, insert_pipeline_exec, publish_pipeline_exec, PipelineExecOrigin,
+    },
+    rate_limit::{RateLimited, RateLimiter},
+};
+
+const RELOAD_INTERVAL: Duration = Duration::from_secs(5);
+const CAPACITY_POLL_INTERVAL: Duration = Duration::from_millis(500);
+const NAK_DELAY: Duration = Duration::from_secs(5);
+
+struct RunningNatsTrigger {
+    config: serde_json::Value,
+    handle: JoinHandle<()>,
+}
+
+/// Stream and durable consumer names the trigger consumes from, if it uses JetStream. Chosen
+/// durable names are prefixed with the trigger id, a trigger can't take over the consumer of
+/// another one.
+pub fn to_durable_consumer(
+    pipeline_trigger_id: Uuid,
+    config: &NatsTriggerConfig,
+) -> Option<(String, String)> {
+    let prefix = format!("PIPELINE_TRIGGER_{}", pipeline_trigger_id.simple());
+
+    config.jetstream.as_ref().map(|jetstream_config| {
+        (
+            jetstream_config.stream.clone(),
+            match &jetstream_config.durable_name {
+                Some(durable_name) => format!("{prefix}_{durable_name}"),
+                None => prefix,
+            },
+        )
+    })
+}
+
+/// Letters, digits, `-` and `_`, NATS rejects the other characters in consumer names.
+pub fn is_valid_durable_name(durable_name: &str) -> bool {
+    !durable_name.is_empty()
+        && durable_name
+            .chars()
+            .all(|char| char.is_ascii_alphanumeric() || char == '-' || char == '_')
+}
+
+/// Subjects of a team start with `triggers.{team_id}.`, the internal subjects carrying the
+/// executions of every team can't be subscribed to.
+pub fn to_team_subject_prefix(team_id: Uuid) -> String {
+    format!("triggers.{team_id}.")
+}
+
+pub fn is_team_subject(team_id: Uuid, subject: &str) -> bool {
+    is_valid_subject(subject)
+        && subject
+            .strip_prefix(&to_team_subject_prefix(team_id))
+            .is_some_and(|rest| !rest.is_empty())
+}
+
+/// Whether the stream only captures subjects of the team, which rules out the internal streams.
+pub async fn is_team_stream(
+    jetstream: &jetstream::Context,
+    team_id: Uuid,
+    stream: &str,
+) -> Result<bool, Box<dyn Error + Send + Sync>> {
+    let stream = jetstream.get_stream(stream).await?;
+    let subjects = &stream.cached_info().config.subjects;
+
+    Ok(!subjects.is_empty()
+        && subjects
+            .iter()
+            .all(|subject| is_team_subject(team_id, subject)))
+}
+
+/// Whether the trigger only consumes messages of its team, triggers stored before subjects were
+/// namespaced aren't started otherwise.
+fn is_team_config(team_id: Uuid, config: &NatsTriggerConfig) -> bool {
+    is_team_subject(team_id, &config.subject)
+        && config.jetstream.as_ref().is_none_or(|jetstream_config| {
+            jetstream_config
+                .durable_name
+                .as_deref()
+                .is_none_or(is_valid_durable_name)
+        })
+}
+
+/// Dot separated non-empty tokens, `>` only allowed as the last token.
+pub fn is_valid_subject(subject: &str) -> bool {
+    let tokens = subject.split('.').collect::<Vec<_>>();
+
+    tokens.iter().enumerate().all(|(index, token)| {
+        !token.is_empty()
+            && !token.contains(char::is_whitespace)
+            && (*token != ">" || index == tokens.len() - 1)
+            && (token.len() == 1 || !token.contains(['*', '>']))
+    })
+}
+
+/// Keeps one subscription per NATS trigger running, picking up configuration changes.
+///
+/// Core NATS subscriptions join a queue group per trigger, so only one api instance
+/// starts an execution for a given message.
+pub async fn supervise(
+    db: PgPool,
+    nats_client: async_nats::Client,
+    jetstream: jetstream::Context,
+    rate_limiter: RateLimiter,
+) {
+    let mut running = HashMap::<Uuid, RunningNatsTrigger>::new();
+
+    loop {
+        match sqlx::query!(
+            r#"
+            SELECT
+                pt.id, pt.pipeline_id, pt.config, p.team_id
+            FROM
+                pipeline_triggers pt
+            JOIN
+                pipelines p ON p.id = pt.pipeline_id
+            WHERE
+                pt.config->'source'->>'type' = 'nats'
+            "#
+        )
+        .fetch_all(&db)
+        .await
+        {
+            Ok(triggers) => {
+                let configured = triggers
+                    .iter()
+                    .map(|trigger| (trigger.id, &trigger.config))
+                    .collect::<HashMap<_, _>>();
+
+                running.retain(|id, trigger| {
+                    let keep = !trigger.handle.is_finished()
+                        && configured.get(id) == Some(&&trigger.config);
+
+                    if !keep {
+                        trigger.handle.abort();
+                    }
+
+                    keep
+                });
+
+                for trigger in triggers {
+                    if running.contains_key(&trigger.id) {
+                        continue;
+                    }
+
+                    let Ok(config) =
+                        serde_json::from_value::<PipelineTriggerConfig>(trigger.config.clone())
+                    else {
+                        error!("Invalid config of pipeline trigger: {}", trigger.id);
+                        continue;
+                    };
+
+                    let Some(PipelineTriggerSource::Nats(nats_config)) = config.source() else {
+                        continue;
+                    };
+
+                    if !is_team_config(trigger.team_id, nats_config) {
+                        error!(
+                            "NATS subject of pipeline trigger {} is outside of its team: {}",
+                            trigger.id, nats_config.subject
+                        );
+                        continue;
+                    }
+
+                    if let Some((stream, _)) = to_durable_consumer(trigger.id, nats_config) {
+                        match is_team_stream(&jetstream, trigger.team_id, &stream).await {
+                            Ok(true) => {}
+                            Ok(false) => {
+                                error!(
+                                    "Stream {stream} of pipeline trigger {} captures subjects outside of its team",
+                                    trigger.id
+                                );
+                                continue;
+                            }
+                            Err(error) => {
+                                error!(
+                                    "Failed to look up stream {stream} of pipeline trigger {}: {error:?}",
+                                    trigger.id
+                                );
+                                continue;
+                            }
+                        }
+                    }
+
+                    info!(
+                        "Subscribing pipeline trigger {} to NATS subject: {}",
+                        trigger.id, nats_config.subject
+                    );
+
+                    let handle = tokio::spawn(run(
+                        db.clone(),
+                        nats_client.clone(),
+                        jetstream.clone(),
+                        rate_limiter.clone(),
+                        trigger.id,
+                        trigger.pipeline_id,
+                        nats_config.clone(),
+                    ));
+
+                    running.insert(
+                        trigger.id,
+                        RunningNatsTrigger {
+                            config: trigger.config,
+                            handle,
+                        },
+                    );
+                }
+            }
+            Err(error) => error!("Failed to load NATS pipeline triggers: {error:?}"),
+        }
+
+        tokio::time::sleep(RELOAD_INTERVAL).await;
+    }
+}
+
+async fn run(
+    db: PgPool,
+    nats_client: async_nats::Client,
+    jetstream: jetstream::Context,
+    mut rate_limiter: RateLimiter,
+    pipeline_trigger_id: Uuid,
+    pipeline_id: Uuid,
+    config: NatsTriggerConfig,
+) {
+    let result = match &config.jetstream {
+        None => {
+            run_core(
+                &db,
+                &nats_client,
+                &jetstream,
+                &mut rate_limiter,
+                pipeline_trigger_id,
+                pipeline_id,
+                &config,
+            )
+            .await
+        }
+        Some(_) => {
+            run_jetstream(
+                &db,
+                &jetstream,
+                &mut rate_limiter,
+                pipeline_trigger_id,
+                pipeline_id,
+                &config,
+            )
+            .await
+        }
+    };
+
+    if let Err(error) = result {
+        error!("NATS subscription of pipeline trigger {pipeline_trigger_id} stopped: {error:?}");
+    }
+}
+
+async fn run_core(
+    db: &PgPool,
+    nats_client: &async_nats::Client,
+    jetstream: &jetstream::Context,
+    rate_limiter: &mut RateLimiter,
+    pipeline_trigger_id: Uuid,
+    pipeline_id: Uuid,
+    config: &NatsTriggerConfig,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let mut subscriber = nats_client
+        .queue_subscribe(
+            config.subject.clone(),
+            format!("pipeline-trigger-{pipeline_trigger_id}"),
+        )
+        .await?;
+
+    while let Some(message) = subscriber.next().await {
+        let params = map_nats_params(
+            &config.params,
+            &message.subject,
+            message.headers.as_ref(),
+            &message.payload,
+        );
+
+        loop {
+            match start(
+                db,
+                jetstream,
+                rate_limiter,
+                pipeline_trigger_id,
+                pipeline_id,
+                config,
+                &params,
+            )
+            .await
+            {
+                Ok(Ok(true)) => break,
+                Ok(Ok(false)) => tokio::time::sleep(CAPACITY_POLL_INTERVAL).await,
+                // Core NATS doesn't redeliver, the message waits for the quota
+                Ok(Err(rate_limited)) => {
+                    tokio::time::sleep(Duration::from_secs(rate_limited.retry_after_secs)).await
+                }
+                // Core NATS doesn't redeliver, the message is dropped
+                Err(error) => {
+                    error!(
+                        "Failed to start pipeline exec for NATS message of pipeline trigger {pipeline_trigger_id}: {error:?}"
+                    );
+                    break;
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+async fn run_jetstream(
+    db: &PgPool,
+    jetstream: &jetstream::Context,
+    rate_limiter: &mut RateLimiter,
+    pipeline_trigger_id: Uuid,
+    pipeline_id: Uuid,
+    config: &NatsTriggerConfig,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let Some((stream, durable_name)) = to_durable_consumer(pipeline_trigger_id, config) else {
+        return Ok(());
+    };
+
+    // A single message in flight, the next one is only delivered once an execution started for this one
+    let consumer = jetstream
+        .get_stream(stream)
+        .await?
+        .create_consumer(pull::Config {
+            durable_name: durable_name.into(),
+            filter_subject: config.subject.clone(),
+            ack_policy: jetstream::consumer::AckPolicy::Explicit,
+            deliver_policy: jetstream::consumer::DeliverPolicy::New,
+            max_ack_pending: 1,
+            ..Default::default()
+        })
+        .await?;
+
+    let mut messages = consumer.messages().await?;
+
+    while let Some(message) = messages.next().await {
+        let message = match message {
+            Ok(message) => message,
+            Err(error) => {
+                error!("Failed to receive NATS message of pipeline trigger {pipeline_trigger_id}: {error:?}");
+                continue;
+            }
+        };
+
+        let params = map_nats_params(
+            &config.params,
+            &message.subject,
+            message.headers.as_ref(),
+            &message.payload,
+        );
+
+        let ack = loop {
+            match start(
+                db,
+                jetstream,
+                rate_limiter,
+                pipeline_trigger_id,
+                pipeline_id,
+                config,
+                &params,
+            )
+            .await
+            {
+                Ok(Ok(true)) => break AckKind::Ack,
+                // Redelivered once the quota of the team allows for it
+                Ok(Err(rate_limited)) => {
+                    break AckKind::Nak(Some(Duration::from_secs(rate_limited.retry_after_secs)))
+                }
+                Ok(Ok(false)) => {
+                    if let Err(error) = message.ack_with(AckKind::Progress).await {
+                        error!("Failed to extend ack deadline of NATS message: {error}");
+                    }
+                    tokio::time::sleep(CAPACITY_POLL_INTERVAL).await;
+                }
+                // Redelivered later, possibly to another api instance
+                Err(error) => {
+                    error!(
+                        "Failed to start pipeline exec for NATS message of pipeline trigger {pipeline_trigger_id}: {error:?}"
+                    );
+                    break AckKind::Nak(Some(NAK_DELAY));
+                }
+            }
+        };
+
+        if let Err(error) = message.ack_with(ack).await {
+            error!("Failed to ack NATS message of pipeline trigger {pipeline_trigger_id}: {error}");
+        }
+    }
+
+    Ok(())
+}
+
+/// Starts an execution unless the trigger already has as many unfinished ones as its concurrency
+/// limit allows, returns whether it started. `RateLimited` when the team is over its trigger quota.
+///
+/// The count and the insert happen under a transaction-scoped advisory lock on the trigger, api
+/// instances consuming the same trigger can't go over the limit together.
+async fn start(
+    db: &PgPool,
+    jetstream: &jetstream::Context,
+    rate_limiter: &mut RateLimiter,
+    pipeline_trigger_id: Uuid,
+    pipeline_id: Uuid,
+    config: &NatsTriggerConfig,
+    params: &PipelineExecPayloadParams,
+) -> Result<Result<bool, RateLimited>, Box<dyn Error + Send + Sync>> {
+    let mut tx = db.begin().await?;
+
+    if let Some(limit) = config.concurrency_limit() {
+        sqlx::query!(
+            "SELECT pg_advisory_xact_lock(hashtextextended($1::uuid::text, 0))",
+            pipeline_trigger_id
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        let unfinished = sqlx::query!(
+            r#"
+            SELECT
+                COUNT(*) AS "count!"
+            FROM
+                pipeline_execs
+            WHERE
+                pipeline_trigger_id = $1 AND status IN ('pending', 'running')
+            "#,
+            pipeline_trigger_id
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+
+        if unfinished.count >= i64::from(limit) {
+            return Ok(Ok(false));
+        }
+    }
+
+    let pipeline_exec_id = match insert_pipeline_exec(
+        &mut tx,
+        rate_limiter,
+        pipeline_id,
+        Some(pipeline_trigger_id),
+        &PipelineExecOrigin::default(),
+        params,
+    )
+    .await
+    .map_err(|status| format!("Failed to start pipeline exec: {status}"))?
+    {
+        Ok(pipeline_exec_id) => pipeline_exec_id,
+        Err(rate_limited) => return Ok(Err(rate_limited)),
+    };
+
+    tx.commit().await?;
+
+    publish_pipeline_exec(
+        jetstream,
+        pipeline_id,
+        pipeline_exec_id,
+        Some(pipeline_trigger_id),
+        params.clone(),
+    )
+    .await?;
+
+    info!(
+        "Pipeline exec {pipeline_exec_id} started by NATS message of pipeline trigger: {pipeline_trigger_id}"
+    );
+
+    Ok(Ok(true))
+}
+
+/// Builds trigger params out of the message, mappings whose source is missing are skipped.
+pub fn map_nats_params(
+    mappings: &[NatsParamMapping],
+    subject: &Subject,
+    headers: Option<&HeaderMap>,
+    payload: &[u8],
+) -> PipelineExecPayloadParams {
+    let body = serde_json::from_slice::<serde_json::Value>(payload).unwrap_or_else(|_| {
+        serde_json::Value::String(String::from_utf8_lossy(payload).into_owned())
+    });
+
+    let mut params = PipelineExecPayloadParams::new();
+
+    for mapping in mappings {
+        let value = match &mapping.source {
+            NatsParamSource::Body { pointer } => body.pointer(pointer).cloned(),
+            NatsParamSource::Header { name } => headers
+                .and_then(|headers| headers.get(name.as_str()))
+                .map(|value| serde_json::Value::String(value.to_string())),
+            NatsParamSource::Subject => Some(serde_json::Value::String(subject.to_string())),
+        };
+
+        if let Some(value) = value {
+            insert_node_param(&mut params, mapping.pipeline_node_id, &mapping.input, value);
+        }
+    }
+
+    params
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use db::dtos::NatsJetStreamConfig;
+
+    #[test]
+    fn test_is_valid_subject() {
+        assert!(is_valid_subject("orders.created"));
+        assert!(is_valid_subject("orders.*.created"));
+        assert!(is_valid_subject("orders.>"));
+        assert!(!is_valid_subject(""));
+        assert!(!is_valid_subject("orders..created"));
+        assert!(!is_valid_subject("orders.>.created"));
+        assert!(!is_valid_subject("orders created"));
+        assert!(!is_valid_subject("orders.a*"));
+    }
+
+    #[test]
+    fn test_is_team_subject() {
+        let team_id = Uuid::new_v4();
+
+        assert!(is_team_subject(
+            team_id,
+            &format!("triggers.{team_id}.orders.created")
+        ));
+        assert!(is_team_subject(team_id, &format!("triggers.{team_id}.>")));
+        assert!(!is_team_subject(team_id, &format!("triggers.{team_id}.")));
+        assert!(!is_team_subject(
+            team_id,
+            &format!("triggers.{}.>", Uuid::new_v4())
+        ));
+        assert!(!is_team_subject(team_id, "triggers.*.>"));
+        assert!(!is_team_subject(team_id, ">"));
+        assert!(!is_team_subject(team_id, "pipeline.exec"));
+        assert!(!is_team_subject(team_id, "exec.events"));
+    }
+
+    #[test]
+    fn test_to_durable_consumer() {
+        let pipeline_trigger_id = Uuid::new_v4();
+        let config = |durable_name: Option<&str>| NatsTriggerConfig {
+            subject: "triggers.team.orders".to_string(),
+            jetstream: Some(NatsJetStreamConfig {
+                stream: "ORDERS".to_string(),
+                durable_name: durable_name.map(str::to_string),
+            }),
+            params: vec![],
+            max_concurrency: None,
+            ordered: false,
+        };
+
+        assert_eq!(
+            to_durable_consumer(pipeline_trigger_id, &config(None)),
+            Some((
+                "ORDERS".to_string(),
+                format!("PIPELINE_TRIGGER_{}", pipeline_trigger_id.simple())
+            ))
+        );
+        assert_eq!(
+            to_durable_consumer(pipeline_trigger_id, &config(Some("orders"))),
+            Some((
+                "ORDERS".to_string(),
+                format!("PIPELINE_TRIGGER_{}_orders", pipeline_trigger_id.simple())
+            ))
+        );
+        assert!(is_valid_durable_name("orders-v2_1"));
+        assert!(!is_valid_durable_name("orders.v2"));
+        assert!(!is_valid_durable_name(""));
+    }
+
+    #[test]
+    fn test_map_nats_params() {
+        let pipeline_node_id = Uuid::new_v4();
+        let mappings = vec![
+            NatsParamMapping {
+                pipeline_node_id,
+                input: "message".to_string(),
+                source: NatsParamSource::Body {
+                    pointer: "/text".to_string(),
+                },
+            },
+            NatsParamMapping {
+                pipeline_node_id,
+                input: "topic".to_string(),
+                source: NatsParamSource::Subject,
+            },
+            NatsParamMapping {
+                pipeline_node_id,
+                input: "source".to_string(),
+                source: NatsParamSource::Header {
+                    name: "x-source".to_string(),
+                },
+            },
+        ];
+
+        let mut headers = HeaderMap::new();
+        headers.insert("x-source", "billing");
+
+        let params = map_nats_params(
+            &mappings,
+            &Subject::from("orders.created"),
+            Some(&headers),
+            br#"{"text":"hello"}"#,
+        );
+
+        assert_eq!(
+            params.get(&pipeline_node_id),
+            Some(&serde_json::json!({
+                "message": "hello",
+                "topic": "orders.created",
+                "source": "billing",
+            }))
+        );
+    }
+}
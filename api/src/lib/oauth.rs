@@ -0,0 +1,411 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use db::dtos::KeyProviderType;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{collections::HashMap, error::Error};
+
+use crate::lib::token::generate_token;
+
+/// How long the authorization of a login attempt can be completed for.
+pub const OAUTH_STATE_TTL_SECS: u64 = 600;
+
+/// Cookie binding a login attempt to the browser which started it, a callback carrying the state
+/// of another browser is rejected.
+pub const OAUTH_STATE_COOKIE: &str = "oauth_state";
+const OAUTH_STATE_COOKIE_PATH: &str = "/api/v0/auth/oauth";
+
+/// `Set-Cookie` value of the state cookie, an empty value with no lifetime clears it.
+pub fn oauth_state_cookie(state: &str, secure: bool) -> String {
+    let max_age = if state.is_empty() {
+        0
+    } else {
+        OAUTH_STATE_TTL_SECS
+    };
+
+    format!(
+        "{OAUTH_STATE_COOKIE}={state}; Path={OAUTH_STATE_COOKIE_PATH}; Max-Age={max_age}; HttpOnly; SameSite=Lax{}",
+        if secure { "; Secure" } else { "" }
+    )
+}
+
+/// Value of the cookie in a `Cookie` header.
+pub fn find_cookie<'a>(cookie_header: &'a str, name: &str) -> Option<&'a str> {
+    cookie_header.split(';').find_map(|cookie| {
+        cookie
+            .trim()
+            .split_once('=')
+            .filter(|(cookie_name, _)| *cookie_name == name)
+            .map(|(_, value)| value)
+    })
+}
+
+/// OAuth2 client of a login provider, endpoints default to the provider's ones and can be pointed
+/// at another server, such as a local mock.
+#[derive(Debug, Clone)]
+pub struct OAuthProvider {
+    pub kind: KeyProviderType,
+    pub client_id: String,
+    pub client_secret: String,
+    pub authorize_url: String,
+    pub token_url: String,
+    pub userinfo_url: String,
+    /// Endpoint listing the addresses of the user along with whether they're verified, GitHub only.
+    pub emails_url: Option<String>,
+    pub scopes: Vec<String>,
+}
+
+impl OAuthProvider {
+    /// Reads `OAUTH_<PROVIDER>_CLIENT_ID` and `OAUTH_<PROVIDER>_CLIENT_SECRET`, `None` when the
+    /// provider isn't configured. `OAUTH_<PROVIDER>_AUTHORIZE_URL`, `_TOKEN_URL`,
+    /// `_USERINFO_URL` and `_EMAILS_URL` override the endpoints.
+    pub fn from_env(kind: KeyProviderType) -> Option<Self> {
+        let prefix = format!("OAUTH_{}", kind.as_str().to_uppercase());
+        let var = |name: &str| {
+            std::env::var(format!("{prefix}_{name}"))
+                .ok()
+                .filter(|value| !value.is_empty())
+        };
+
+        let (authorize_url, token_url, userinfo_url, emails_url, scopes) = match kind {
+            KeyProviderType::Github => (
+                "https://github.com/login/oauth/authorize",
+                "https://github.com/login/oauth/access_token",
+                "https://api.github.com/user",
+                Some("https://api.github.com/user/emails"),
+                vec!["read:user", "user:email"],
+            ),
+            KeyProviderType::Google => (
+                "https://accounts.google.com/o/oauth2/v2/auth",
+                "https://oauth2.googleapis.com/token",
+                "https://openidconnect.googleapis.com/v1/userinfo",
+                None,
+                vec!["openid", "email", "profile"],
+            ),
+            KeyProviderType::Facebook => (
+                "https://www.facebook.com/v19.0/dialog/oauth",
+                "https://graph.facebook.com/v19.0/oauth/access_token",
+                "https://graph.facebook.com/v19.0/me?fields=id,name,email",
+                None,
+                vec!["email", "public_profile"],
+            ),
+            KeyProviderType::Password => return None,
+        };
+
+        Some(Self {
+            kind,
+            client_id: var("CLIENT_ID")?,
+            client_secret: var("CLIENT_SECRET")?,
+            authorize_url: var("AUTHORIZE_URL").unwrap_or_else(|| authorize_url.to_string()),
+            token_url: var("TOKEN_URL").unwrap_or_else(|| token_url.to_string()),
+            userinfo_url: var("USERINFO_URL").unwrap_or_else(|| userinfo_url.to_string()),
+            emails_url: var("EMAILS_URL").or_else(|| emails_url.map(str::to_string)),
+            scopes: scopes.into_iter().map(str::to_string).collect(),
+        })
+    }
+
+    pub fn authorization_url(
+        &self,
+        redirect_uri: &str,
+        state: &str,
+        code_challenge: &str,
+    ) -> Result<String, url::ParseError> {
+        let mut url = url::Url::parse(&self.authorize_url)?;
+
+        url.query_pairs_mut()
+            .append_pair("response_type", "code")
+            .append_pair("client_id", &self.client_id)
+            .append_pair("redirect_uri", redirect_uri)
+            .append_pair("scope", &self.scopes.join(" "))
+            .append_pair("state", state)
+            .append_pair("code_challenge", code_challenge)
+            .append_pair("code_challenge_method", "S256");
+
+        Ok(url.to_string())
+    }
+}
+
+/// Configured login providers by name, `github`, `google` or `facebook`.
+#[derive(Clone, Default)]
+pub struct OAuthConfig {
+    pub providers: HashMap<String, OAuthProvider>,
+    pub http: reqwest::Client,
+    /// Base url of the web app, the providers redirect back to it.
+    pub app_url: String,
+}
+
+impl OAuthConfig {
+    pub fn from_env(app_url: &str) -> Self {
+        let providers = [
+            KeyProviderType::Github,
+            KeyProviderType::Google,
+            KeyProviderType::Facebook,
+        ]
+        .into_iter()
+        .filter_map(OAuthProvider::from_env)
+        .map(|provider| (provider.kind.as_str().to_string(), provider))
+        .collect();
+
+        Self {
+            providers,
+            http: reqwest::Client::new(),
+            app_url: app_url.to_string(),
+        }
+    }
+
+    /// The provider redirects back to the web app, which hands the code over to the callback.
+    pub fn redirect_uri(&self, provider: &str) -> String {
+        format!("{}/auth/oauth/{provider}/callback", self.app_url)
+    }
+
+    /// The state cookie is only sent over https when the web app is served over it.
+    pub fn is_secure(&self) -> bool {
+        self.app_url.starts_with("https://")
+    }
+}
+
+/// Login attempt waiting for the provider to redirect back, stored under its `state`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OAuthState {
+    pub provider: String,
+    pub code_verifier: String,
+}
+
+pub fn to_redis_oauth_state_key(state: &str) -> String {
+    format!("oauth_state:{state}")
+}
+
+/// PKCE verifier, 32 random bytes as the RFC recommends.
+pub fn generate_code_verifier() -> String {
+    format!("{}{}", generate_token(), generate_token())
+}
+
+/// S256 PKCE challenge of the verifier.
+pub fn code_challenge(code_verifier: &str) -> String {
+    URL_SAFE_NO_PAD.encode(Sha256::digest(code_verifier.as_bytes()))
+}
+
+/// Identity of the user at the provider.
+#[derive(Debug, PartialEq)]
+pub struct OAuthIdentity {
+    /// Id of the user at the provider, stable across email changes.
+    pub subject: String,
+    pub name: Option<String>,
+    pub email: Option<String>,
+    pub email_verified: bool,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+}
+
+#[derive(Deserialize)]
+struct ProviderEmail {
+    email: String,
+    #[serde(default)]
+    primary: bool,
+    #[serde(default)]
+    verified: bool,
+}
+
+/// Normalizes the user info of the provider, OIDC claims for Google, the user resources of
+/// GitHub and Facebook otherwise.
+pub fn parse_identity(
+    kind: KeyProviderType,
+    userinfo: &serde_json::Value,
+) -> Result<OAuthIdentity, String> {
+    let subject = match userinfo.get("sub").or_else(|| userinfo.get("id")) {
+        Some(serde_json::Value::String(subject)) => subject.clone(),
+        Some(serde_json::Value::Number(subject)) => subject.to_string(),
+        _ => return Err("The provider didn't return the id of the user".to_string()),
+    };
+
+    let email = userinfo
+        .get("email")
+        .and_then(serde_json::Value::as_str)
+        .map(str::to_string);
+
+    let email_verified = match kind {
+        // Facebook doesn't tell whether the address is verified, GitHub's verified addresses come
+        // from the emails endpoint
+        KeyProviderType::Facebook | KeyProviderType::Github => false,
+        _ => match userinfo.get("email_verified") {
+            Some(serde_json::Value::Bool(verified)) => *verified,
+            Some(serde_json::Value::String(verified)) => verified == "true",
+            _ => false,
+        },
+    };
+
+    Ok(OAuthIdentity {
+        subject,
+        name: userinfo
+            .get("name")
+            .or_else(|| userinfo.get("login"))
+            .and_then(serde_json::Value::as_str)
+            .map(str::to_string),
+        email,
+        email_verified,
+    })
+}
+
+/// Exchanges the authorization code and fetches the identity of the user it was issued for.
+pub async fn fetch_identity(
+    http: &reqwest::Client,
+    provider: &OAuthProvider,
+    code: &str,
+    code_verifier: &str,
+    redirect_uri: &str,
+) -> Result<OAuthIdentity, Box<dyn Error + Send + Sync>> {
+    let token = http
+        .post(&provider.token_url)
+        .header(reqwest::header::ACCEPT, "application/json")
+        .form(&[
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("redirect_uri", redirect_uri),
+            ("client_id", &provider.client_id),
+            ("client_secret", &provider.client_secret),
+            ("code_verifier", code_verifier),
+        ])
+        .send()
+        .await?
+        .error_for_status()?
+        .json::<TokenResponse>()
+        .await?;
+
+    let userinfo = http
+        .get(&provider.userinfo_url)
+        .bearer_auth(&token.access_token)
+        .header(reqwest::header::ACCEPT, "application/json")
+        .header(reqwest::header::USER_AGENT, "shallabuf")
+        .send()
+        .await?
+        .error_for_status()?
+        .json::<serde_json::Value>()
+        .await?;
+
+    let mut identity = parse_identity(provider.kind, &userinfo)?;
+
+    if let Some(emails_url) = &provider.emails_url {
+        let emails = http
+            .get(emails_url)
+            .bearer_auth(&token.access_token)
+            .header(reqwest::header::ACCEPT, "application/json")
+            .header(reqwest::header::USER_AGENT, "shallabuf")
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<Vec<ProviderEmail>>()
+            .await?;
+
+        if let Some(primary) = emails
+            .into_iter()
+            .find(|email| email.primary && email.verified)
+        {
+            identity.email = Some(primary.email);
+            identity.email_verified = true;
+        }
+    }
+
+    Ok(identity)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_code_challenge() {
+        // Example of RFC 7636, appendix B
+        assert_eq!(
+            code_challenge("dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk"),
+            "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM"
+        );
+        assert!(generate_code_verifier().len() >= 43);
+    }
+
+    #[test]
+    fn test_parse_identity() {
+        assert_eq!(
+            parse_identity(
+                KeyProviderType::Google,
+                &json!({"sub": "1234", "name": "Alex", "email": "alex@mail.com", "email_verified": true})
+            ),
+            Ok(OAuthIdentity {
+                subject: "1234".to_string(),
+                name: Some("Alex".to_string()),
+                email: Some("alex@mail.com".to_string()),
+                email_verified: true,
+            })
+        );
+
+        assert_eq!(
+            parse_identity(
+                KeyProviderType::Github,
+                &json!({"id": 42, "login": "alex", "email": "alex@mail.com"})
+            ),
+            Ok(OAuthIdentity {
+                subject: "42".to_string(),
+                name: Some("alex".to_string()),
+                email: Some("alex@mail.com".to_string()),
+                email_verified: false,
+            })
+        );
+
+        assert_eq!(
+            parse_identity(
+                KeyProviderType::Facebook,
+                &json!({"id": "7", "name": "Alex", "email": "alex@mail.com"})
+            )
+            .map(|identity| identity.email_verified),
+            Ok(false)
+        );
+
+        assert!(parse_identity(KeyProviderType::Facebook, &json!({"name": "Alex"})).is_err());
+    }
+
+    #[test]
+    fn test_oauth_state_cookie() {
+        let cookie = oauth_state_cookie("abc", true);
+
+        assert!(cookie.starts_with("oauth_state=abc; "));
+        assert!(cookie.contains("HttpOnly"));
+        assert!(cookie.contains("SameSite=Lax"));
+        assert!(cookie.ends_with("; Secure"));
+        assert!(oauth_state_cookie("", false).contains("Max-Age=0"));
+
+        assert_eq!(
+            find_cookie("theme=dark; oauth_state=abc", OAUTH_STATE_COOKIE),
+            Some("abc")
+        );
+        assert_eq!(find_cookie("theme=dark", OAUTH_STATE_COOKIE), None);
+    }
+
+    #[test]
+    fn test_authorization_url() {
+        let provider = OAuthProvider {
+            kind: KeyProviderType::Google,
+            client_id: "client".to_string(),
+            client_secret: "secret".to_string(),
+            authorize_url: "http://localhost:8080/authorize".to_string(),
+            token_url: "http://localhost:8080/token".to_string(),
+            userinfo_url: "http://localhost:8080/userinfo".to_string(),
+            emails_url: None,
+            scopes: vec!["openid".to_string(), "email".to_string()],
+        };
+
+        let url = url::Url::parse(
+            &provider
+                .authorization_url("http://localhost:3000/callback", "state", "challenge")
+                .unwrap(),
+        )
+        .unwrap();
+        let query = url.query_pairs().into_owned().collect::<HashMap<_, _>>();
+
+        assert_eq!(query["client_id"], "client");
+        assert_eq!(query["scope"], "openid email");
+        assert_eq!(query["code_challenge_method"], "S256");
+        assert_eq!(query["redirect_uri"], "http://localhost:3000/callback");
+    }
+}
@@ -0,0 +1,58 @@
+use argon2::{
+    password_hash::{rand_core::OsRng, PasswordHash, PasswordHasher, SaltString},
+    Argon2, PasswordVerifier,
+};
+
+pub const MIN_PASSWORD_LENGTH: usize = 8;
+
+pub fn hash_password(password: &str) -> Result<String, argon2::password_hash::Error> {
+    let salt = SaltString::generate(&mut OsRng);
+
+    Ok(Argon2::default()
+        .hash_password(password.as_bytes(), &salt)?
+        .to_string())
+}
+
+pub fn verify_password(
+    password: &str,
+    password_hash: &str,
+) -> Result<bool, argon2::password_hash::Error> {
+    let parsed_password_hash = PasswordHash::new(password_hash)?;
+
+    match Argon2::default().verify_password(password.as_bytes(), &parsed_password_hash) {
+        Ok(()) => Ok(true),
+        Err(argon2::password_hash::Error::Password) => Ok(false),
+        Err(error) => Err(error),
+    }
+}
+
+/// Why the password can't be used, `None` when it can.
+pub fn password_rejection(password: &str) -> Option<String> {
+    if password.chars().count() < MIN_PASSWORD_LENGTH {
+        Some(format!(
+            "The password must be at least {MIN_PASSWORD_LENGTH} characters long"
+        ))
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hash_password() {
+        let password_hash = hash_password("correct horse").unwrap();
+
+        assert!(verify_password("correct horse", &password_hash).unwrap());
+        assert!(!verify_password("battery staple", &password_hash).unwrap());
+        assert!(verify_password("correct horse", "not a hash").is_err());
+    }
+
+    #[test]
+    fn test_password_rejection() {
+        assert!(password_rejection("short").is_some());
+        assert_eq!(password_rejection("long enough"), None);
+    }
+}
@@ -0,0 +1,197 @@
+use db::dtos::{self, PipelineExecPayloadParams, PipelineTriggerConfig};
+use hyper::StatusCode;
+use sqlx::PgConnection;
+use tracing::info;
+use uuid::Uuid;
+
+use crate::{
+    lib::{
+        pipeline_params::redact_pipeline_exec_params,
+        rate_limit::{check_trigger_quota, RateLimited, RateLimiter},
+    },
+    utils::internal_error,
+};
+
+/// Trigger a manual run of `pipeline_id` goes through, `pipeline_trigger_id` when given,
+/// otherwise the oldest trigger of the pipeline allowing manual execution.
+///
+/// Fails with `NOT_FOUND` when the given trigger isn't one of the pipeline,
+/// and with `FORBIDDEN` when the trigger doesn't allow manual execution.
+pub async fn find_manual_trigger(
+    db: &mut PgConnection,
+    pipeline_id: Uuid,
+    pipeline_trigger_id: Option<Uuid>,
+) -> Result<Uuid, StatusCode> {
+    let triggers = sqlx::query!(
+        r#"
+        SELECT
+            id, config
+        FROM
+            pipeline_triggers
+        WHERE
+            pipeline_id = $1 AND ($2::uuid IS NULL OR id = $2)
+        ORDER BY
+            created_at
+        "#,
+        pipeline_id,
+        pipeline_trigger_id
+    )
+    .fetch_all(&mut *db)
+    .await
+    .map_err(internal_error)?;
+
+    if triggers.is_empty() && pipeline_trigger_id.is_some() {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    for trigger in triggers {
+        let config: PipelineTriggerConfig =
+            serde_json::from_value(trigger.config).map_err(internal_error)?;
+
+        if config.allow_manual_execution() {
+            return Ok(trigger.id);
+        }
+    }
+
+    Err(StatusCode::FORBIDDEN)
+}
+
+/// Creates the `pipeline_execs` row and hands the run over to the scheduler.
+/// Only the scheduler gets the values of secret inputs, the stored params are redacted.
+pub async fn start_pipeline_exec(
+    db: &mut PgConnection,
+    jetstream: &async_nats::jetstream::Context,
+    rate_limiter: &mut RateLimiter,
+    id: Uuid,
+    pipeline_trigger_id: Option<Uuid>,
+    params: PipelineExecPayloadParams,
+) -> Result<Result<Uuid, RateLimited>, StatusCode> {
+    let pipeline_exec_id = match insert_pipeline_exec(
+        db,
+        rate_limiter,
+        id,
+        pipeline_trigger_id,
+        &PipelineExecOrigin::default(),
+        &params,
+    )
+    .await?
+    {
+        Ok(pipeline_exec_id) => pipeline_exec_id,
+        Err(rate_limited) => return Ok(Err(rate_limited)),
+    };
+
+    publish_pipeline_exec(jetstream, id, pipeline_exec_id, pipeline_trigger_id, params)
+        .await
+        .map_err(internal_error)?;
+
+    Ok(Ok(pipeline_exec_id))
+}
+
+/// Execution a new one follows from, if any.
+#[derive(Debug, Default)]
+pub struct PipelineExecOrigin {
+    /// Execution whose end fired the event trigger starting this one.
+    pub parent_pipeline_exec_id: Option<Uuid>,
+    pub retry_of_pipeline_exec_id: Option<Uuid>,
+    pub replay_of_pipeline_exec_id: Option<Uuid>,
+    /// Node versions of a replay, keyed by pipeline node.
+    pub pinned_node_versions: Option<serde_json::Value>,
+}
+
+/// Creates the `pipeline_execs` row with redacted params, the run must then be published with
+/// [`publish_pipeline_exec`] once the row is visible to the scheduler.
+///
+/// Every execution is counted against the trigger quota of the team, none is created over it.
+/// Fails with `CONFLICT` when the event trigger already started a run after the parent execution.
+pub async fn insert_pipeline_exec(
+    db: &mut PgConnection,
+    rate_limiter: &mut RateLimiter,
+    id: Uuid,
+    pipeline_trigger_id: Option<Uuid>,
+    origin: &PipelineExecOrigin,
+    params: &PipelineExecPayloadParams,
+) -> Result<Result<Uuid, RateLimited>, StatusCode> {
+    let stored_params = redact_pipeline_exec_params(
+        db,
+        id,
+        &serde_json::to_value(params).map_err(internal_error)?,
+    )
+    .await
+    .map_err(internal_error)?;
+
+    if let Err(rate_limited) = check_trigger_quota(db, rate_limiter, id).await? {
+        return Ok(Err(rate_limited));
+    }
+
+    let Some(pipeline_exec) = sqlx::query!(
+        r"
+        INSERT INTO pipeline_execs (
+            pipeline_id,
+            pipeline_trigger_id,
+            params,
+            parent_pipeline_exec_id,
+            retry_of_pipeline_exec_id,
+            replay_of_pipeline_exec_id,
+            pinned_node_versions
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, $7)
+        ON CONFLICT (pipeline_trigger_id, parent_pipeline_exec_id) DO NOTHING
+        RETURNING id
+        ",
+        id,
+        pipeline_trigger_id,
+        stored_params,
+        origin.parent_pipeline_exec_id,
+        origin.retry_of_pipeline_exec_id,
+        origin.replay_of_pipeline_exec_id,
+        origin.pinned_node_versions,
+    )
+    .fetch_optional(&mut *db)
+    .await
+    .map_err(internal_error)?
+    else {
+        return Err(StatusCode::CONFLICT);
+    };
+
+    info!(
+        "Pipeline execution record created with id: {}",
+        pipeline_exec.id
+    );
+
+    Ok(Ok(pipeline_exec.id))
+}
+
+pub async fn publish_pipeline_exec(
+    jetstream: &async_nats::jetstream::Context,
+    pipeline_id: Uuid,
+    pipeline_exec_id: Uuid,
+    pipeline_trigger_id: Option<Uuid>,
+    params: PipelineExecPayloadParams,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let payload = serde_json::to_string(&dtos::PipelineExecPayload {
+        pipeline_id,
+        pipeline_exec_id,
+        pipeline_trigger_id,
+        params,
+    })?;
+
+    jetstream.publish("pipeline.exec", payload.into()).await?;
+
+    Ok(())
+}
+
+/// Sets the `input` param of a pipeline node, creating the node params object on demand.
+pub fn insert_node_param(
+    params: &mut PipelineExecPayloadParams,
+    pipeline_node_id: Uuid,
+    input: &str,
+    value: serde_json::Value,
+) {
+    let node_params = params
+        .entry(pipeline_node_id)
+        .or_insert_with(|| serde_json::Value::Object(serde_json::Map::new()));
+
+    if let Some(node_params) = node_params.as_object_mut() {
+        node_params.insert(input.to_string(), value);
+    }
+}
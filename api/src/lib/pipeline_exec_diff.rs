@@ -0,0 +1,186 @@
+use db::dtos::ExecStatus;
+use serde::Serialize;
+use std::collections::{BTreeSet, HashMap};
+use uuid::Uuid;
+
+/// What a node execution ran with and returned, as compared between two executions.
+pub struct DiffedNodeExec {
+    pub pipeline_node_id: Uuid,
+    pub node_version: Option<String>,
+    pub status: ExecStatus,
+    pub result: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct OutputChange {
+    /// Output key of the result, none when the results aren't objects and differ as a whole.
+    pub output: Option<String>,
+    pub original: Option<serde_json::Value>,
+    pub replayed: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PipelineNodeExecDiff {
+    pub pipeline_node_id: Uuid,
+    pub original_node_version: Option<String>,
+    pub node_version: Option<String>,
+    pub original_status: Option<ExecStatus>,
+    pub status: Option<ExecStatus>,
+    pub changes: Vec<OutputChange>,
+}
+
+/// Compares the node executions of two runs of a pipeline, node by node.
+/// Nodes which only ran in one of them are listed with the missing side empty.
+pub fn diff_node_execs(
+    original: Vec<DiffedNodeExec>,
+    replayed: Vec<DiffedNodeExec>,
+) -> Vec<PipelineNodeExecDiff> {
+    let mut original = original
+        .into_iter()
+        .map(|node_exec| (node_exec.pipeline_node_id, node_exec))
+        .collect::<HashMap<_, _>>();
+    let mut replayed = replayed
+        .into_iter()
+        .map(|node_exec| (node_exec.pipeline_node_id, node_exec))
+        .collect::<HashMap<_, _>>();
+
+    let pipeline_node_ids = original
+        .keys()
+        .chain(replayed.keys())
+        .copied()
+        .collect::<BTreeSet<_>>();
+
+    pipeline_node_ids
+        .into_iter()
+        .map(|pipeline_node_id| {
+            let original = original.remove(&pipeline_node_id);
+            let replayed = replayed.remove(&pipeline_node_id);
+
+            let changes = diff_results(
+                original
+                    .as_ref()
+                    .and_then(|node_exec| node_exec.result.as_ref()),
+                replayed
+                    .as_ref()
+                    .and_then(|node_exec| node_exec.result.as_ref()),
+            );
+
+            let (original_node_version, original_status) = original
+                .map(|node_exec| (node_exec.node_version, Some(node_exec.status)))
+                .unwrap_or_default();
+            let (node_version, status) = replayed
+                .map(|node_exec| (node_exec.node_version, Some(node_exec.status)))
+                .unwrap_or_default();
+
+            PipelineNodeExecDiff {
+                pipeline_node_id,
+                original_node_version,
+                node_version,
+                original_status,
+                status,
+                changes,
+            }
+        })
+        .collect()
+}
+
+/// Outputs whose values differ between two results, compared by output key when both are objects.
+pub fn diff_results(
+    original: Option<&serde_json::Value>,
+    replayed: Option<&serde_json::Value>,
+) -> Vec<OutputChange> {
+    match (original, replayed) {
+        (Some(serde_json::Value::Object(original)), Some(serde_json::Value::Object(replayed))) => {
+            original
+                .keys()
+                .chain(replayed.keys())
+                .collect::<BTreeSet<_>>()
+                .into_iter()
+                .filter(|key| original.get(*key) != replayed.get(*key))
+                .map(|key| OutputChange {
+                    output: Some(key.clone()),
+                    original: original.get(key).cloned(),
+                    replayed: replayed.get(key).cloned(),
+                })
+                .collect()
+        }
+        (original, replayed) if original != replayed => vec![OutputChange {
+            output: None,
+            original: original.cloned(),
+            replayed: replayed.cloned(),
+        }],
+        _ => Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_diff_results() {
+        let original = serde_json::json!({ "text": "hello", "count": 1 });
+        let replayed = serde_json::json!({ "text": "hello", "count": 2, "lang": "en" });
+
+        assert_eq!(
+            diff_results(Some(&original), Some(&replayed)),
+            vec![
+                OutputChange {
+                    output: Some("count".to_string()),
+                    original: Some(serde_json::json!(1)),
+                    replayed: Some(serde_json::json!(2)),
+                },
+                OutputChange {
+                    output: Some("lang".to_string()),
+                    original: None,
+                    replayed: Some(serde_json::json!("en")),
+                },
+            ]
+        );
+        assert!(diff_results(Some(&original), Some(&original)).is_empty());
+        assert_eq!(
+            diff_results(Some(&original), None),
+            vec![OutputChange {
+                output: None,
+                original: Some(original.clone()),
+                replayed: None,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_diff_node_execs() {
+        let (kept, removed) = (Uuid::new_v4(), Uuid::new_v4());
+        let node_exec = |pipeline_node_id, node_version: &str, result| DiffedNodeExec {
+            pipeline_node_id,
+            node_version: Some(node_version.to_string()),
+            status: ExecStatus::Completed,
+            result: Some(result),
+        };
+
+        let diff = diff_node_execs(
+            vec![
+                node_exec(kept, "v1", serde_json::json!({ "text": "a" })),
+                node_exec(removed, "v1", serde_json::json!({})),
+            ],
+            vec![node_exec(kept, "v2", serde_json::json!({ "text": "b" }))],
+        );
+
+        let kept_diff = diff
+            .iter()
+            .find(|node| node.pipeline_node_id == kept)
+            .unwrap();
+        assert_eq!(kept_diff.original_node_version.as_deref(), Some("v1"));
+        assert_eq!(kept_diff.node_version.as_deref(), Some("v2"));
+        assert_eq!(kept_diff.changes.len(), 1);
+
+        let removed_diff = diff
+            .iter()
+            .find(|node| node.pipeline_node_id == removed)
+            .unwrap();
+        assert_eq!(removed_diff.status, None);
+        assert_eq!(removed_diff.changes.len(), 1);
+    }
+}
@@ -0,0 +1,340 @@
+use db::dtos::NodeConfig;
+use petgraph::{algo::tarjan_scc, graphmap::DiGraphMap};
+use serde::Serialize;
+use sqlx::PgConnection;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use uuid::Uuid;
+
+pub struct GraphConnection {
+    pub id: Uuid,
+    pub from_pipeline_id: Uuid,
+    pub from_pipeline_node_id: Uuid,
+    pub to_pipeline_id: Uuid,
+    pub to_pipeline_node_id: Uuid,
+    pub to_pipeline_node_input_id: Uuid,
+}
+
+pub struct GraphInput {
+    pub id: Uuid,
+    pub pipeline_node_id: Uuid,
+    pub key: String,
+    /// Required by the node config and without a default value.
+    pub required: bool,
+}
+
+#[derive(Debug, Serialize, PartialEq)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum PipelineGraphProblem {
+    #[serde(rename_all = "camelCase")]
+    SelfLoop {
+        connection_id: Uuid,
+        pipeline_node_id: Uuid,
+    },
+    #[serde(rename_all = "camelCase")]
+    Cycle { pipeline_node_ids: Vec<Uuid> },
+    #[serde(rename_all = "camelCase")]
+    DuplicateInputConnection {
+        pipeline_node_input_id: Uuid,
+        connection_ids: Vec<Uuid>,
+    },
+    #[serde(rename_all = "camelCase")]
+    CrossPipelineConnection { connection_id: Uuid },
+    #[serde(rename_all = "camelCase")]
+    UnconnectedRequiredInput {
+        pipeline_node_id: Uuid,
+        pipeline_node_input_id: Uuid,
+        key: String,
+    },
+}
+
+impl fmt::Display for PipelineGraphProblem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PipelineGraphProblem::SelfLoop {
+                pipeline_node_id, ..
+            } => write!(f, "Pipeline node {pipeline_node_id} is connected to itself"),
+            PipelineGraphProblem::Cycle { pipeline_node_ids } => write!(
+                f,
+                "Pipeline nodes form a cycle: {}",
+                pipeline_node_ids
+                    .iter()
+                    .map(Uuid::to_string)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            PipelineGraphProblem::DuplicateInputConnection {
+                pipeline_node_input_id,
+                ..
+            } => write!(
+                f,
+                "Pipeline node input {pipeline_node_input_id} has more than one connection"
+            ),
+            PipelineGraphProblem::CrossPipelineConnection { connection_id } => write!(
+                f,
+                "Connection {connection_id} links nodes of different pipelines"
+            ),
+            PipelineGraphProblem::UnconnectedRequiredInput {
+                pipeline_node_id,
+                key,
+                ..
+            } => write!(
+                f,
+                "Required input {key} of pipeline node {pipeline_node_id} is not connected"
+            ),
+        }
+    }
+}
+
+/// Connections touching the nodes of the pipeline, including the ones leading outside of it.
+pub async fn fetch_graph_connections(
+    db: &mut PgConnection,
+    pipeline_id: Uuid,
+) -> Result<Vec<GraphConnection>, sqlx::Error> {
+    sqlx::query_as!(
+        GraphConnection,
+        r#"
+        SELECT
+            pnc.id,
+            fpn.pipeline_id AS from_pipeline_id,
+            fpn.id AS from_pipeline_node_id,
+            tpn.pipeline_id AS to_pipeline_id,
+            tpn.id AS to_pipeline_node_id,
+            pni.id AS to_pipeline_node_input_id
+        FROM
+            pipeline_node_connections pnc
+        JOIN
+            pipeline_node_outputs pno ON pno.id = pnc.from_pipeline_node_output_id
+        JOIN
+            pipeline_nodes fpn ON fpn.id = pno.pipeline_node_id
+        JOIN
+            pipeline_node_inputs pni ON pni.id = pnc.to_pipeline_node_input_id
+        JOIN
+            pipeline_nodes tpn ON tpn.id = pni.pipeline_node_id
+        WHERE
+            fpn.pipeline_id = $1 OR tpn.pipeline_id = $1
+        ORDER BY
+            pnc.created_at
+        "#,
+        pipeline_id
+    )
+    .fetch_all(&mut *db)
+    .await
+}
+
+pub async fn fetch_graph_inputs(
+    db: &mut PgConnection,
+    pipeline_id: Uuid,
+) -> Result<Vec<GraphInput>, sqlx::Error> {
+    let inputs = sqlx::query!(
+        r#"
+        SELECT
+            pni.id, pni.pipeline_node_id, pni.key, n.config
+        FROM
+            pipeline_node_inputs pni
+        JOIN
+            pipeline_nodes pn ON pn.id = pni.pipeline_node_id
+        JOIN
+            nodes n ON n.id = pn.node_id
+        WHERE
+            pn.pipeline_id = $1
+        "#,
+        pipeline_id
+    )
+    .fetch_all(&mut *db)
+    .await?;
+
+    Ok(inputs
+        .into_iter()
+        .map(|input| {
+            let required = serde_json::from_value::<NodeConfig>(input.config).is_ok_and(|config| {
+                config
+                    .into_v1()
+                    .input(&input.key)
+                    .is_some_and(|node_input| {
+                        node_input.required && node_input.input.default_value().is_none()
+                    })
+            });
+
+            GraphInput {
+                id: input.id,
+                pipeline_node_id: input.pipeline_node_id,
+                key: input.key,
+                required,
+            }
+        })
+        .collect())
+}
+
+/// Whether adding the `from` -> `to` edge to the existing `edges` closes a cycle.
+pub fn creates_cycle(edges: &[(Uuid, Uuid)], from: Uuid, to: Uuid) -> bool {
+    let mut visited = HashSet::new();
+    let mut stack = vec![to];
+
+    while let Some(id) = stack.pop() {
+        if id == from {
+            return true;
+        }
+
+        if visited.insert(id) {
+            stack.extend(
+                edges
+                    .iter()
+                    .filter(|(edge_from, _)| *edge_from == id)
+                    .map(|(_, edge_to)| *edge_to),
+            );
+        }
+    }
+
+    false
+}
+
+/// Lists everything preventing the graph of `pipeline_id` from running as a DAG.
+pub fn find_graph_problems(
+    pipeline_id: Uuid,
+    connections: &[GraphConnection],
+    inputs: &[GraphInput],
+) -> Vec<PipelineGraphProblem> {
+    let mut problems = Vec::new();
+    let mut graph = DiGraphMap::<Uuid, ()>::new();
+    let mut input_connections = HashMap::<Uuid, Vec<Uuid>>::new();
+
+    for connection in connections {
+        if connection.from_pipeline_id != pipeline_id || connection.to_pipeline_id != pipeline_id {
+            problems.push(PipelineGraphProblem::CrossPipelineConnection {
+                connection_id: connection.id,
+            });
+
+            continue;
+        }
+
+        if connection.from_pipeline_node_id == connection.to_pipeline_node_id {
+            problems.push(PipelineGraphProblem::SelfLoop {
+                connection_id: connection.id,
+                pipeline_node_id: connection.from_pipeline_node_id,
+            });
+        } else {
+            graph.add_edge(
+                connection.from_pipeline_node_id,
+                connection.to_pipeline_node_id,
+                (),
+            );
+        }
+
+        input_connections
+            .entry(connection.to_pipeline_node_input_id)
+            .or_default()
+            .push(connection.id);
+    }
+
+    for component in tarjan_scc(&graph) {
+        if component.len() > 1 {
+            let mut pipeline_node_ids = component;
+            pipeline_node_ids.sort();
+            problems.push(PipelineGraphProblem::Cycle { pipeline_node_ids });
+        }
+    }
+
+    for input in inputs {
+        match input_connections.get(&input.id) {
+            Some(connection_ids) if connection_ids.len() > 1 => {
+                problems.push(PipelineGraphProblem::DuplicateInputConnection {
+                    pipeline_node_input_id: input.id,
+                    connection_ids: connection_ids.clone(),
+                });
+            }
+            None if input.required => {
+                problems.push(PipelineGraphProblem::UnconnectedRequiredInput {
+                    pipeline_node_id: input.pipeline_node_id,
+                    pipeline_node_input_id: input.id,
+                    key: input.key.clone(),
+                });
+            }
+            _ => {}
+        }
+    }
+
+    problems
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn connect(pipeline_id: Uuid, from: Uuid, to: Uuid, input: Uuid) -> GraphConnection {
+        GraphConnection {
+            id: Uuid::new_v4(),
+            from_pipeline_id: pipeline_id,
+            from_pipeline_node_id: from,
+            to_pipeline_id: pipeline_id,
+            to_pipeline_node_id: to,
+            to_pipeline_node_input_id: input,
+        }
+    }
+
+    #[test]
+    fn test_creates_cycle() {
+        let (a, b, c) = (Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4());
+        let edges = vec![(a, b), (b, c)];
+
+        assert!(creates_cycle(&edges, c, a));
+        assert!(creates_cycle(&edges, a, a));
+        assert!(!creates_cycle(&edges, a, c));
+        assert!(!creates_cycle(&[], a, b));
+    }
+
+    #[test]
+    fn test_find_graph_problems() {
+        let pipeline_id = Uuid::new_v4();
+        let (a, b, c) = (Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4());
+        let inputs = [a, b, c].map(|pipeline_node_id| GraphInput {
+            id: Uuid::new_v4(),
+            pipeline_node_id,
+            key: "message".to_string(),
+            required: true,
+        });
+
+        let mut foreign = connect(pipeline_id, c, a, inputs[0].id);
+        foreign.from_pipeline_id = Uuid::new_v4();
+
+        let connections = vec![
+            connect(pipeline_id, a, b, inputs[1].id),
+            connect(pipeline_id, b, a, inputs[0].id),
+            connect(pipeline_id, b, a, inputs[0].id),
+            connect(pipeline_id, b, b, inputs[1].id),
+            foreign,
+        ];
+
+        let problems = find_graph_problems(pipeline_id, &connections, &inputs);
+
+        let mut cycle = vec![a, b];
+        cycle.sort();
+
+        assert!(problems.contains(&PipelineGraphProblem::Cycle {
+            pipeline_node_ids: cycle
+        }));
+        assert!(problems.contains(&PipelineGraphProblem::SelfLoop {
+            connection_id: connections[3].id,
+            pipeline_node_id: b,
+        }));
+        assert!(
+            problems.contains(&PipelineGraphProblem::CrossPipelineConnection {
+                connection_id: connections[4].id,
+            })
+        );
+        assert!(
+            problems.contains(&PipelineGraphProblem::DuplicateInputConnection {
+                pipeline_node_input_id: inputs[0].id,
+                connection_ids: vec![connections[1].id, connections[2].id],
+            })
+        );
+        assert!(
+            problems.contains(&PipelineGraphProblem::UnconnectedRequiredInput {
+                pipeline_node_id: c,
+                pipeline_node_input_id: inputs[2].id,
+                key: "message".to_string(),
+            })
+        );
+        assert_eq!(problems.len(), 6);
+    }
+}
@@ -0,0 +1,659 @@
+use db::dtos::{
+    excluded_pipeline_nodes, NodeConfig, NodeConfigV1, NodeInputTypeV1, PipelineContract,
+    PipelineExecPayloadParams, REDACTED_VALUE,
+};
+use serde::Serialize;
+use sqlx::PgConnection;
+use std::{
+    collections::{HashMap, HashSet},
+    error::Error,
+};
+use uuid::Uuid;
+
+use crate::lib::pipeline_exec::insert_node_param;
+
+#[derive(Debug, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct PipelineParamError {
+    /// Named pipeline input the error is about, if any.
+    pub pipeline_input: Option<String>,
+    pub pipeline_node_id: Option<Uuid>,
+    /// Node input the error is about, none when it's about the params of the whole node.
+    pub input: Option<String>,
+    pub error: String,
+}
+
+impl PipelineParamError {
+    fn sort_key(&self) -> (&Option<String>, Option<Uuid>, &Option<String>) {
+        (&self.pipeline_input, self.pipeline_node_id, &self.input)
+    }
+}
+
+pub struct ParamNode {
+    pub id: Uuid,
+    pub trigger_id: Option<Uuid>,
+    pub config: NodeConfigV1,
+    pub input_values: serde_json::Value,
+}
+
+pub struct ParamConnection {
+    pub from_pipeline_node_id: Uuid,
+    pub to_pipeline_node_id: Uuid,
+    pub to_key: String,
+}
+
+/// Checks the named inputs and outputs of a pipeline point at declared inputs and outputs of its nodes.
+pub fn check_pipeline_contract(
+    contract: &PipelineContract,
+    configs: &HashMap<Uuid, NodeConfigV1>,
+) -> Result<(), String> {
+    let mut input_names = HashSet::new();
+
+    for input in contract.inputs() {
+        if input.name.is_empty() || !input_names.insert(input.name.as_str()) {
+            return Err(format!(
+                "Invalid or duplicated input name: {:?}",
+                input.name
+            ));
+        }
+
+        let Some(config) = configs.get(&input.pipeline_node_id) else {
+            return Err(format!(
+                "Input {} targets a node outside of the pipeline",
+                input.name
+            ));
+        };
+
+        if config.input(&input.input).is_none() {
+            return Err(format!(
+                "Input {} targets an undeclared node input: {}",
+                input.name, input.input
+            ));
+        }
+    }
+
+    let mut output_names = HashSet::new();
+
+    for output in contract.outputs() {
+        if output.name.is_empty() || !output_names.insert(output.name.as_str()) {
+            return Err(format!(
+                "Invalid or duplicated output name: {:?}",
+                output.name
+            ));
+        }
+
+        let Some(config) = configs.get(&output.pipeline_node_id) else {
+            return Err(format!(
+                "Output {} comes from a node outside of the pipeline",
+                output.name
+            ));
+        };
+
+        if let Some(key) = output.output.as_ref() {
+            if config.output(key).is_none() {
+                return Err(format!(
+                    "Output {} comes from an undeclared node output: {key}",
+                    output.name
+                ));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Turns trigger params into node params: keys which are pipeline node ids are taken as is,
+/// the other ones are named pipeline inputs mapped onto node inputs through the contract.
+pub fn map_pipeline_inputs(
+    contract: &PipelineContract,
+    params: serde_json::Map<String, serde_json::Value>,
+) -> (PipelineExecPayloadParams, Vec<PipelineParamError>) {
+    let mut node_params = PipelineExecPayloadParams::new();
+    let mut named_params = Vec::new();
+    let mut errors = Vec::new();
+
+    for (key, value) in params {
+        match Uuid::parse_str(&key) {
+            Ok(pipeline_node_id) => {
+                node_params.insert(pipeline_node_id, value);
+            }
+            Err(_) => named_params.push((key, value)),
+        }
+    }
+
+    for input in contract.inputs() {
+        if input.required && !named_params.iter().any(|(name, _)| *name == input.name) {
+            errors.push(PipelineParamError {
+                pipeline_input: Some(input.name.clone()),
+                pipeline_node_id: None,
+                input: None,
+                error: "required pipeline input has no value".to_string(),
+            });
+        }
+    }
+
+    for (name, value) in named_params {
+        let Some(input) = contract.inputs().iter().find(|input| input.name == name) else {
+            errors.push(PipelineParamError {
+                pipeline_input: Some(name),
+                pipeline_node_id: None,
+                input: None,
+                error: "not an input of the pipeline".to_string(),
+            });
+
+            continue;
+        };
+
+        insert_node_param(
+            &mut node_params,
+            input.pipeline_node_id,
+            &input.input,
+            value,
+        );
+    }
+
+    (node_params, errors)
+}
+
+/// Lists what's wrong with the params of a run of `pipeline_id` started by `pipeline_trigger_id`,
+/// before anything gets executed.
+pub async fn check_pipeline_exec_params(
+    db: &mut PgConnection,
+    pipeline_id: Uuid,
+    pipeline_trigger_id: Option<Uuid>,
+    params: &PipelineExecPayloadParams,
+) -> Result<Vec<PipelineParamError>, Box<dyn Error + Send + Sync>> {
+    let nodes = sqlx::query!(
+        r#"
+        SELECT
+            pn.id, pn.trigger_id, pn.input_values, n.config
+        FROM
+            pipeline_nodes pn
+        JOIN
+            nodes n ON n.id = pn.node_id
+        WHERE
+            pn.pipeline_id = $1
+        "#,
+        pipeline_id
+    )
+    .fetch_all(&mut *db)
+    .await?
+    .into_iter()
+    .map(|node| {
+        Ok(ParamNode {
+            id: node.id,
+            trigger_id: node.trigger_id,
+            config: serde_json::from_value::<NodeConfig>(node.config)?.into_v1(),
+            input_values: node.input_values,
+        })
+    })
+    .collect::<Result<Vec<_>, serde_json::Error>>()?;
+
+    let connections = sqlx::query_as!(
+        ParamConnection,
+        r#"
+        SELECT
+            pno.pipeline_node_id AS from_pipeline_node_id,
+            pni.pipeline_node_id AS to_pipeline_node_id,
+            pni.key AS to_key
+        FROM
+            pipeline_node_connections pnc
+        JOIN
+            pipeline_node_outputs pno ON pno.id = pnc.from_pipeline_node_output_id
+        JOIN
+            pipeline_node_inputs pni ON pni.id = pnc.to_pipeline_node_input_id
+        JOIN
+            pipeline_nodes pn ON pn.id = pni.pipeline_node_id
+        WHERE
+            pn.pipeline_id = $1
+        "#,
+        pipeline_id
+    )
+    .fetch_all(&mut *db)
+    .await?;
+
+    Ok(find_param_errors(
+        &nodes,
+        &connections,
+        pipeline_trigger_id,
+        params,
+    ))
+}
+
+/// Checks the params target nodes of the pipeline and fit their inputs,
+/// and that every required input of the nodes taking part in the run gets a value
+/// from a default, a stored value, a connection or the params.
+pub fn find_param_errors(
+    nodes: &[ParamNode],
+    connections: &[ParamConnection],
+    pipeline_trigger_id: Option<Uuid>,
+    params: &PipelineExecPayloadParams,
+) -> Vec<PipelineParamError> {
+    let mut errors = Vec::new();
+    let nodes_by_id = nodes
+        .iter()
+        .map(|node| (node.id, node))
+        .collect::<HashMap<_, _>>();
+
+    for (pipeline_node_id, node_params) in params {
+        let Some(node) = nodes_by_id.get(pipeline_node_id) else {
+            errors.push(PipelineParamError {
+                pipeline_input: None,
+                pipeline_node_id: Some(*pipeline_node_id),
+                input: None,
+                error: "not a node of the pipeline".to_string(),
+            });
+
+            continue;
+        };
+
+        let serde_json::Value::Object(node_params) = node_params else {
+            errors.push(PipelineParamError {
+                pipeline_input: None,
+                pipeline_node_id: Some(*pipeline_node_id),
+                input: None,
+                error: "params of a node have to be an object".to_string(),
+            });
+
+            continue;
+        };
+
+        if let Err(input_errors) = node.config.coerce_input_values(node_params.clone()) {
+            errors.extend(
+                input_errors
+                    .into_iter()
+                    .map(|(input, error)| PipelineParamError {
+                        pipeline_input: None,
+                        pipeline_node_id: Some(*pipeline_node_id),
+                        input: Some(input),
+                        error,
+                    }),
+            );
+        }
+    }
+
+    let excluded = excluded_pipeline_nodes(
+        &nodes
+            .iter()
+            .map(|node| (node.id, node.trigger_id))
+            .collect(),
+        &connections
+            .iter()
+            .map(|connection| {
+                (
+                    connection.from_pipeline_node_id,
+                    connection.to_pipeline_node_id,
+                )
+            })
+            .collect::<Vec<_>>(),
+        pipeline_trigger_id,
+    );
+
+    let connected = connections
+        .iter()
+        .map(|connection| (connection.to_pipeline_node_id, connection.to_key.as_str()))
+        .collect::<HashSet<_>>();
+
+    for node in nodes.iter().filter(|node| !excluded.contains(&node.id)) {
+        for input in node.config.inputs.iter().filter(|input| input.required) {
+            let satisfied = input.input.default_value().is_some()
+                || node.input_values.get(&input.key).is_some()
+                || connected.contains(&(node.id, input.key.as_str()))
+                || params
+                    .get(&node.id)
+                    .is_some_and(|node_params| node_params.get(&input.key).is_some());
+
+            if !satisfied {
+                errors.push(PipelineParamError {
+                    pipeline_input: None,
+                    pipeline_node_id: Some(node.id),
+                    input: Some(input.key.clone()),
+                    error: "required input has no value".to_string(),
+                });
+            }
+        }
+    }
+
+    errors.sort_by(|a, b| a.sort_key().cmp(&b.sort_key()));
+
+    errors
+}
+
+/// Configs of the nodes of `pipeline_id`, keyed by pipeline node.
+pub async fn fetch_node_configs(
+    db: &mut PgConnection,
+    pipeline_id: Uuid,
+) -> Result<HashMap<Uuid, NodeConfigV1>, Box<dyn Error + Send + Sync>> {
+    let configs = sqlx::query!(
+        r#"
+        SELECT
+            pn.id, n.config
+        FROM
+            pipeline_nodes pn
+        JOIN
+            nodes n ON n.id = pn.node_id
+        WHERE
+            pn.pipeline_id = $1
+        "#,
+        pipeline_id
+    )
+    .fetch_all(&mut *db)
+    .await?
+    .into_iter()
+    .map(|node| {
+        Ok((
+            node.id,
+            serde_json::from_value::<NodeConfig>(node.config)?.into_v1(),
+        ))
+    })
+    .collect::<Result<HashMap<_, _>, serde_json::Error>>()?;
+
+    Ok(configs)
+}
+
+/// Trigger params of an execution of `pipeline_id` as they can be stored and displayed, secret inputs redacted.
+pub async fn redact_pipeline_exec_params(
+    db: &mut PgConnection,
+    pipeline_id: Uuid,
+    params: &serde_json::Value,
+) -> Result<serde_json::Value, Box<dyn Error + Send + Sync>> {
+    let configs = fetch_node_configs(db, pipeline_id).await?;
+
+    Ok(redact_exec_params(params, &configs))
+}
+
+/// Applies the overrides to stored params, node by node and input by input. Only the redacted
+/// values of secret inputs are stored, so each has to be overridden, an error is returned for
+/// each that isn't.
+pub fn restore_exec_params(
+    params: &mut PipelineExecPayloadParams,
+    overrides: PipelineExecPayloadParams,
+    configs: &HashMap<Uuid, NodeConfigV1>,
+) -> Vec<PipelineParamError> {
+    for (pipeline_node_id, node_overrides) in overrides {
+        match (
+            params
+                .get_mut(&pipeline_node_id)
+                .and_then(serde_json::Value::as_object_mut),
+            node_overrides,
+        ) {
+            (Some(node_params), serde_json::Value::Object(node_overrides)) => {
+                node_params.extend(node_overrides);
+            }
+            (_, node_overrides) => {
+                params.insert(pipeline_node_id, node_overrides);
+            }
+        }
+    }
+
+    let mut errors = Vec::new();
+
+    for (pipeline_node_id, node_params) in params.iter() {
+        let (Some(config), Some(node_params)) =
+            (configs.get(pipeline_node_id), node_params.as_object())
+        else {
+            continue;
+        };
+
+        for (key, value) in node_params {
+            if value.as_str() == Some(REDACTED_VALUE)
+                && config
+                    .input(key)
+                    .is_some_and(|input| matches!(input.input, NodeInputTypeV1::Secret))
+            {
+                errors.push(PipelineParamError {
+                    pipeline_input: None,
+                    pipeline_node_id: Some(*pipeline_node_id),
+                    input: Some(key.clone()),
+                    error: "secret input has to be supplied again".to_string(),
+                });
+            }
+        }
+    }
+
+    errors.sort_by(|a, b| a.sort_key().cmp(&b.sort_key()));
+    errors
+}
+
+/// Replaces the values of secret inputs in params keyed by pipeline node.
+pub fn redact_exec_params(
+    params: &serde_json::Value,
+    configs: &HashMap<Uuid, NodeConfigV1>,
+) -> serde_json::Value {
+    let mut params = params.clone();
+
+    if let Some(nodes_params) = params.as_object_mut() {
+        for (pipeline_node_id, node_params) in nodes_params.iter_mut() {
+            let config = Uuid::parse_str(pipeline_node_id)
+                .ok()
+                .and_then(|pipeline_node_id| configs.get(&pipeline_node_id));
+
+            if let Some(config) = config {
+                *node_params = config.redact_params(node_params);
+            }
+        }
+    }
+
+    params
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use db::dtos::{NodeInputTypeV1, NodeInputV1};
+
+    fn node(trigger_id: Option<Uuid>, inputs: Vec<(&str, NodeInputTypeV1)>) -> ParamNode {
+        ParamNode {
+            id: Uuid::new_v4(),
+            trigger_id,
+            config: NodeConfigV1 {
+                inputs: inputs
+                    .into_iter()
+                    .map(|(key, input)| NodeInputV1 {
+                        key: key.to_string(),
+                        input,
+                        label: None,
+                        required: true,
+                        description: None,
+                    })
+                    .collect(),
+                outputs: vec![],
+                input_schema: None,
+                output_schema: None,
+            },
+            input_values: serde_json::json!({}),
+        }
+    }
+
+    #[test]
+    fn test_map_pipeline_inputs() {
+        let pipeline_node_id = Uuid::new_v4();
+        let contract = PipelineContract::V0(db::dtos::PipelineContractV0 {
+            inputs: vec![
+                db::dtos::PipelineInput {
+                    name: "text".to_string(),
+                    pipeline_node_id,
+                    input: "message".to_string(),
+                    required: true,
+                    description: None,
+                },
+                db::dtos::PipelineInput {
+                    name: "case".to_string(),
+                    pipeline_node_id,
+                    input: "case".to_string(),
+                    required: true,
+                    description: None,
+                },
+            ],
+            outputs: vec![],
+        });
+
+        let params = serde_json::json!({
+            "text": "hello",
+            "unknown": 1,
+            pipeline_node_id.to_string(): { "suffix": "!" },
+        });
+
+        let (node_params, errors) =
+            map_pipeline_inputs(&contract, params.as_object().cloned().unwrap());
+
+        assert_eq!(
+            node_params.get(&pipeline_node_id),
+            Some(&serde_json::json!({ "message": "hello", "suffix": "!" }))
+        );
+        assert_eq!(
+            errors
+                .iter()
+                .map(|error| error.pipeline_input.as_deref())
+                .collect::<Vec<_>>(),
+            vec![Some("case"), Some("unknown")]
+        );
+    }
+
+    #[test]
+    fn test_find_param_errors() {
+        let (manual, webhook) = (Uuid::new_v4(), Uuid::new_v4());
+        let text = || NodeInputTypeV1::Text { default: None };
+
+        let source = node(Some(manual), vec![("message", text())]);
+        let target = node(None, vec![("message", text())]);
+        let mut stored = node(
+            Some(manual),
+            vec![(
+                "count",
+                NodeInputTypeV1::Number {
+                    min: None,
+                    max: None,
+                    default: None,
+                },
+            )],
+        );
+        stored.input_values = serde_json::json!({ "count": 1 });
+        let other_trigger = node(Some(webhook), vec![("message", text())]);
+
+        let connections = vec![ParamConnection {
+            from_pipeline_node_id: source.id,
+            to_pipeline_node_id: target.id,
+            to_key: "message".to_string(),
+        }];
+
+        let unknown_node_id = Uuid::new_v4();
+        let nodes = vec![source, target, stored, other_trigger];
+
+        let params = PipelineExecPayloadParams::from([
+            (nodes[2].id, serde_json::json!({ "count": "many" })),
+            (unknown_node_id, serde_json::json!({})),
+        ]);
+
+        let mut expected = vec![
+            PipelineParamError {
+                pipeline_input: None,
+                pipeline_node_id: Some(nodes[0].id),
+                input: Some("message".to_string()),
+                error: "required input has no value".to_string(),
+            },
+            PipelineParamError {
+                pipeline_input: None,
+                pipeline_node_id: Some(nodes[2].id),
+                input: Some("count".to_string()),
+                error: "\"many\" is not a number".to_string(),
+            },
+            PipelineParamError {
+                pipeline_input: None,
+                pipeline_node_id: Some(unknown_node_id),
+                input: None,
+                error: "not a node of the pipeline".to_string(),
+            },
+        ];
+        expected.sort_by(|a, b| a.sort_key().cmp(&b.sort_key()));
+
+        assert_eq!(
+            find_param_errors(&nodes, &connections, Some(manual), &params),
+            expected
+        );
+    }
+
+    #[test]
+    fn test_redact_exec_params() {
+        let secret = node(
+            None,
+            vec![
+                ("message", NodeInputTypeV1::Text { default: None }),
+                ("token", NodeInputTypeV1::Secret),
+            ],
+        );
+        let configs = HashMap::from([(secret.id, secret.config)]);
+        let unknown_node_id = Uuid::new_v4();
+
+        let params = serde_json::json!({
+            secret.id.to_string(): { "message": "hello", "token": "s3cr3t" },
+            unknown_node_id.to_string(): { "token": "kept" },
+        });
+
+        assert_eq!(
+            redact_exec_params(&params, &configs),
+            serde_json::json!({
+                secret.id.to_string(): { "message": "hello", "token": db::dtos::REDACTED_VALUE },
+                unknown_node_id.to_string(): { "token": "kept" },
+            })
+        );
+    }
+
+    #[test]
+    fn test_restore_exec_params() {
+        let secret = node(
+            None,
+            vec![
+                ("message", NodeInputTypeV1::Text { default: None }),
+                ("token", NodeInputTypeV1::Secret),
+                ("key", NodeInputTypeV1::Secret),
+            ],
+        );
+        let configs = HashMap::from([(secret.id, secret.config)]);
+
+        let stored = PipelineExecPayloadParams::from([(
+            secret.id,
+            serde_json::json!({
+                "message": REDACTED_VALUE,
+                "token": REDACTED_VALUE,
+                "key": REDACTED_VALUE,
+            }),
+        )]);
+
+        let mut params = stored.clone();
+        let errors = restore_exec_params(
+            &mut params,
+            PipelineExecPayloadParams::from([(
+                secret.id,
+                serde_json::json!({ "token": "s3cr3t" }),
+            )]),
+            &configs,
+        );
+
+        assert_eq!(
+            params[&secret.id],
+            serde_json::json!({ "message": REDACTED_VALUE, "token": "s3cr3t", "key": REDACTED_VALUE })
+        );
+        assert_eq!(
+            errors,
+            vec![PipelineParamError {
+                pipeline_input: None,
+                pipeline_node_id: Some(secret.id),
+                input: Some("key".to_string()),
+                error: "secret input has to be supplied again".to_string(),
+            }]
+        );
+
+        let mut params = stored;
+        let errors = restore_exec_params(
+            &mut params,
+            PipelineExecPayloadParams::from([(
+                secret.id,
+                serde_json::json!({ "token": "s3cr3t", "key": "k3y" }),
+            )]),
+            &configs,
+        );
+
+        assert!(errors.is_empty());
+    }
+}
@@ -0,0 +1,435 @@
+use axum::{
+    extract::{Request, State},
+    http::{header, HeaderMap, HeaderValue},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use hyper::StatusCode;
+use ipnet::IpNet;
+use redis::AsyncCommands;
+use sqlx::PgConnection;
+use std::net::IpAddr;
+use tracing::error;
+use uuid::Uuid;
+
+use crate::{
+    app_state::AppState,
+    lib::{
+        api_error::ApiError,
+        api_token::is_api_token,
+        session::{generate_session_id, to_redis_session_key, ClientInfo, Session},
+        token::hash_token,
+    },
+};
+
+const MINUTE_SECS: u64 = 60;
+
+/// Limits of the api, a limit of `0` disables it.
+#[derive(Clone, Debug)]
+pub struct RateLimitConfig {
+    /// Requests per minute from a client address.
+    pub ip_per_minute: u64,
+    /// Requests per minute from a user or an api token.
+    pub identity_per_minute: u64,
+    /// Failed logins into an account before it's locked out.
+    pub login_max_failures: u64,
+    /// Failed logins from a client address before it's locked out.
+    pub login_max_failures_per_ip: u64,
+    /// How long failed logins are counted for, and so how long a lockout lasts at most.
+    pub login_lockout_secs: u64,
+    /// Pipeline triggers per minute of a team without a quota of its own.
+    pub trigger_per_minute: u64,
+    /// Proxies in front of the api, the client address is read from `X-Forwarded-For` only when
+    /// the request comes through one of them.
+    pub trusted_proxies: Vec<IpNet>,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self {
+            ip_per_minute: 600,
+            identity_per_minute: 300,
+            login_max_failures: 5,
+            login_max_failures_per_ip: 20,
+            login_lockout_secs: 900,
+            trigger_per_minute: 60,
+            trusted_proxies: Vec::new(),
+        }
+    }
+}
+
+impl RateLimitConfig {
+    /// Reads `RATE_LIMIT_IP_PER_MINUTE`, `RATE_LIMIT_IDENTITY_PER_MINUTE`, `LOGIN_MAX_FAILURES`,
+    /// `LOGIN_MAX_FAILURES_PER_IP`, `LOGIN_LOCKOUT_SECS`, `TRIGGER_RATE_LIMIT_PER_MINUTE` and
+    /// `TRUSTED_PROXIES`, a comma separated list of addresses and CIDR ranges.
+    pub fn from_env() -> Self {
+        let defaults = Self::default();
+        let var = |name: &str, default: u64| {
+            std::env::var(name)
+                .ok()
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(default)
+        };
+
+        Self {
+            ip_per_minute: var("RATE_LIMIT_IP_PER_MINUTE", defaults.ip_per_minute),
+            identity_per_minute: var(
+                "RATE_LIMIT_IDENTITY_PER_MINUTE",
+                defaults.identity_per_minute,
+            ),
+            login_max_failures: var("LOGIN_MAX_FAILURES", defaults.login_max_failures),
+            login_max_failures_per_ip: var(
+                "LOGIN_MAX_FAILURES_PER_IP",
+                defaults.login_max_failures_per_ip,
+            ),
+            login_lockout_secs: var("LOGIN_LOCKOUT_SECS", defaults.login_lockout_secs),
+            trigger_per_minute: var("TRIGGER_RATE_LIMIT_PER_MINUTE", defaults.trigger_per_minute),
+            trusted_proxies: std::env::var("TRUSTED_PROXIES")
+                .map(|proxies| parse_trusted_proxies(&proxies))
+                .unwrap_or(defaults.trusted_proxies),
+        }
+    }
+}
+
+fn parse_trusted_proxies(proxies: &str) -> Vec<IpNet> {
+    proxies
+        .split(',')
+        .map(str::trim)
+        .filter(|proxy| !proxy.is_empty())
+        .filter_map(|proxy| {
+            let net = proxy
+                .parse::<IpNet>()
+                .or_else(|_| proxy.parse::<IpAddr>().map(IpNet::from));
+
+            if net.is_err() {
+                error!("Ignoring the invalid trusted proxy {proxy}");
+            }
+
+            net.ok()
+        })
+        .collect()
+}
+
+/// Redis connection along with the limits, for handlers and tasks counting hits of their own.
+#[derive(Clone)]
+pub struct RateLimiter {
+    pub redis: redis::aio::ConnectionManager,
+    pub config: RateLimitConfig,
+}
+
+/// Rejection of a rate limited request, `429` telling when to retry.
+#[derive(Debug, PartialEq)]
+pub struct RateLimited {
+    pub retry_after_secs: u64,
+}
+
+impl IntoResponse for RateLimited {
+    fn into_response(self) -> Response {
+        let mut response = (
+            StatusCode::TOO_MANY_REQUESTS,
+            Json(ApiError {
+                error: format!(
+                    "Too many requests, retry in {} seconds",
+                    self.retry_after_secs
+                ),
+            }),
+        )
+            .into_response();
+
+        response.headers_mut().insert(
+            header::RETRY_AFTER,
+            HeaderValue::from(self.retry_after_secs),
+        );
+
+        response
+    }
+}
+
+fn to_redis_rate_limit_key(bucket: &str) -> String {
+    format!("rate_limit:{bucket}")
+}
+
+/// `RateLimited` once the count is over the limit, until the window resets.
+fn check_count(count: u64, limit: u64, ttl_secs: i64) -> Result<(), RateLimited> {
+    if limit == 0 || count <= limit {
+        Ok(())
+    } else {
+        Err(RateLimited {
+            retry_after_secs: ttl_secs.max(1) as u64,
+        })
+    }
+}
+
+/// Counts a hit in a fixed window starting with the first hit, returns the count and the seconds
+/// left in the window.
+async fn count_hit(
+    redis: &mut redis::aio::ConnectionManager,
+    key: &str,
+    window_secs: u64,
+) -> Result<(u64, i64), redis::RedisError> {
+    redis::pipe()
+        .atomic()
+        .set_options(
+            key,
+            0,
+            redis::SetOptions::default()
+                .conditional_set(redis::ExistenceCheck::NX)
+                .with_expiration(redis::SetExpiry::EX(window_secs)),
+        )
+        .ignore()
+        .incr(key, 1)
+        .ttl(key)
+        .query_async(redis)
+        .await
+}
+
+/// Counts a hit in the bucket, rejecting it when it's over the limit.
+pub async fn hit(
+    redis: &mut redis::aio::ConnectionManager,
+    bucket: &str,
+    limit: u64,
+    window_secs: u64,
+) -> Result<Result<(), RateLimited>, redis::RedisError> {
+    if limit == 0 {
+        return Ok(Ok(()));
+    }
+
+    let (count, ttl_secs) = count_hit(redis, &to_redis_rate_limit_key(bucket), window_secs).await?;
+
+    Ok(check_count(count, limit, ttl_secs))
+}
+
+/// Bucket of the caller when authenticated, by api token or by user for sessions.
+async fn identity_bucket(
+    redis: &mut redis::aio::ConnectionManager,
+    headers: &HeaderMap,
+) -> Result<Option<String>, redis::RedisError> {
+    let Some(token) = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+    else {
+        return Ok(None);
+    };
+
+    if is_api_token(token) {
+        return Ok(Some(format!("api_token:{}", hash_token(token))));
+    }
+
+    let session: Option<String> = redis
+        .get(to_redis_session_key(&generate_session_id(token)))
+        .await?;
+
+    Ok(session
+        .and_then(|session| serde_json::from_str::<Session>(&session).ok())
+        .map(|session| format!("user:{}", session.user_id)))
+}
+
+/// Limits the requests per client address, and per user or api token when authenticated.
+/// Requests go through when Redis fails, the api shouldn't go down with the limiter.
+pub async fn rate_limit(
+    State(state): State<AppState>,
+    client: ClientInfo,
+    request: Request,
+    next: Next,
+) -> Response {
+    let config = &state.rate_limit_config;
+    let mut redis = state.redis.clone();
+
+    let mut buckets = Vec::with_capacity(2);
+
+    if let Some(ip) = &client.ip {
+        buckets.push((format!("ip:{ip}"), config.ip_per_minute));
+    }
+
+    match identity_bucket(&mut redis, request.headers()).await {
+        Ok(Some(bucket)) => buckets.push((bucket, config.identity_per_minute)),
+        Ok(None) => {}
+        Err(error) => error!("Failed to resolve the rate limit identity: {error}"),
+    }
+
+    for (bucket, limit) in buckets {
+        match hit(&mut redis, &bucket, limit, MINUTE_SECS).await {
+            Ok(Ok(())) => {}
+            Ok(Err(rate_limited)) => return rate_limited.into_response(),
+            Err(error) => error!("Failed to rate limit {bucket}: {error}"),
+        }
+    }
+
+    next.run(request).await
+}
+
+fn login_failure_buckets(email: &str, ip: Option<&str>) -> (String, Option<String>) {
+    (
+        format!("login_failures:email:{}", email.trim().to_lowercase()),
+        ip.map(|ip| format!("login_failures:ip:{ip}")),
+    )
+}
+
+/// Rejects logins into an account, or from an address, that failed too many times lately.
+pub async fn check_login_lockout(
+    redis: &mut redis::aio::ConnectionManager,
+    config: &RateLimitConfig,
+    email: &str,
+    client: &ClientInfo,
+) -> Result<Result<(), RateLimited>, redis::RedisError> {
+    let (email_bucket, ip_bucket) = login_failure_buckets(email, client.ip.as_deref());
+
+    for (bucket, max_failures) in [
+        (Some(email_bucket), config.login_max_failures),
+        (ip_bucket, config.login_max_failures_per_ip),
+    ] {
+        let Some(bucket) = bucket.filter(|_| max_failures > 0) else {
+            continue;
+        };
+
+        let key = to_redis_rate_limit_key(&bucket);
+        let (failures, ttl_secs): (Option<u64>, i64) =
+            redis::pipe().get(&key).ttl(&key).query_async(redis).await?;
+
+        // Locked out from the failure reaching the limit on
+        if let Err(rate_limited) = check_count(failures.unwrap_or(0) + 1, max_failures, ttl_secs) {
+            return Ok(Err(rate_limited));
+        }
+    }
+
+    Ok(Ok(()))
+}
+
+pub async fn record_login_failure(
+    redis: &mut redis::aio::ConnectionManager,
+    config: &RateLimitConfig,
+    email: &str,
+    client: &ClientInfo,
+) -> Result<(), redis::RedisError> {
+    let (email_bucket, ip_bucket) = login_failure_buckets(email, client.ip.as_deref());
+
+    for bucket in [Some(email_bucket), ip_bucket].into_iter().flatten() {
+        count_hit(
+            redis,
+            &to_redis_rate_limit_key(&bucket),
+            config.login_lockout_secs,
+        )
+        .await?;
+    }
+
+    Ok(())
+}
+
+/// A successful login forgives the failures of the account, not the ones of the address.
+pub async fn clear_login_failures(
+    redis: &mut redis::aio::ConnectionManager,
+    email: &str,
+) -> Result<(), redis::RedisError> {
+    let (email_bucket, _) = login_failure_buckets(email, None);
+    redis.del(to_redis_rate_limit_key(&email_bucket)).await
+}
+
+/// Counts a run of the pipeline against the trigger quota of its team, the team's own or the
+/// default one.
+pub async fn check_trigger_quota(
+    db: &mut PgConnection,
+    rate_limiter: &mut RateLimiter,
+    pipeline_id: Uuid,
+) -> Result<Result<(), RateLimited>, StatusCode> {
+    let team = sqlx::query!(
+        r#"
+        SELECT
+            t.id, t.trigger_rate_limit_per_minute
+        FROM
+            pipelines p
+        JOIN
+            teams t ON t.id = p.team_id
+        WHERE
+            p.id = $1
+        "#,
+        pipeline_id
+    )
+    .fetch_one(&mut *db)
+    .await
+    .map_err(|error| {
+        error!("Failed to look up the trigger quota of pipeline {pipeline_id}: {error}");
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    let limit = team
+        .trigger_rate_limit_per_minute
+        .map_or(rate_limiter.config.trigger_per_minute, |quota| quota as u64);
+
+    match hit(
+        &mut rate_limiter.redis,
+        &format!("trigger:team:{}", team.id),
+        limit,
+        MINUTE_SECS,
+    )
+    .await
+    {
+        Ok(outcome) => Ok(outcome),
+        Err(error) => {
+            error!("Failed to count the trigger of team {}: {error}", team.id);
+            Ok(Ok(()))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_count() {
+        assert_eq!(check_count(1, 5, 60), Ok(()));
+        assert_eq!(check_count(5, 5, 60), Ok(()));
+        assert_eq!(
+            check_count(6, 5, 42),
+            Err(RateLimited {
+                retry_after_secs: 42
+            })
+        );
+        // No expiry left to report, the key is about to expire
+        assert_eq!(
+            check_count(6, 5, -1),
+            Err(RateLimited {
+                retry_after_secs: 1
+            })
+        );
+        assert_eq!(check_count(1_000, 0, 60), Ok(()));
+    }
+
+    #[test]
+    fn test_rate_limited_response() {
+        let response = RateLimited {
+            retry_after_secs: 30,
+        }
+        .into_response();
+
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.headers()[header::RETRY_AFTER], "30");
+    }
+
+    #[test]
+    fn test_login_failure_buckets() {
+        assert_eq!(
+            login_failure_buckets(" Alex@Mail.com ", Some("127.0.0.1")),
+            (
+                "login_failures:email:alex@mail.com".to_string(),
+                Some("login_failures:ip:127.0.0.1".to_string())
+            )
+        );
+        assert_eq!(login_failure_buckets("alex@mail.com", None).1, None);
+    }
+
+    #[test]
+    fn test_parse_trusted_proxies() {
+        assert_eq!(
+            parse_trusted_proxies(" 10.0.0.0/8, 127.0.0.1,,::1 , proxy "),
+            vec![
+                "10.0.0.0/8".parse::<IpNet>().unwrap(),
+                "127.0.0.1/32".parse().unwrap(),
+                "::1/128".parse().unwrap(),
+            ]
+        );
+        assert!(parse_trusted_proxies("").is_empty());
+    }
+}
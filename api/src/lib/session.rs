@@ -1,12 +1,18 @@
 use chrono::Utc;
 use hyper::StatusCode;
-use rand::Rng;
-use redis::AsyncCommands;
+use redis::{AsyncCommands, ExistenceCheck, SetExpiry, SetOptions};
 use serde::{Deserialize, Serialize};
-use sha2::{Digest, Sha256};
 use tracing::{error, info, warn};
 use uuid::Uuid;
 
+use crate::lib::token::{generate_token, hash_token};
+
+/// How long a session lasts without being used.
+pub const SESSION_TTL: chrono::Duration = chrono::Duration::minutes(30);
+
+/// How often using a session records it as seen, saves a write on every request.
+const LAST_SEEN_AT_RESOLUTION: chrono::Duration = chrono::Duration::minutes(1);
+
 #[derive(Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Session {
@@ -14,38 +20,85 @@ pub struct Session {
     pub user_id: Uuid,
     pub username: String,
     pub expires_at: chrono::DateTime<chrono::Utc>,
+    /// User agent of the client that logged in.
+    #[serde(default)]
+    pub user_agent: Option<String>,
+    /// Address of the client that logged in.
+    #[serde(default)]
+    pub ip: Option<String>,
+    #[serde(default = "Utc::now")]
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    #[serde(default = "Utc::now")]
+    pub last_seen_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// Client a session is created for.
+#[derive(Clone, Debug, Default)]
+pub struct ClientInfo {
+    pub user_agent: Option<String>,
+    pub ip: Option<String>,
+}
+
+/// Seconds until the session expires, Redis drops it then.
+fn session_ttl_secs(session: &Session, now: chrono::DateTime<chrono::Utc>) -> u64 {
+    (session.expires_at - now).num_seconds().max(1) as u64
+}
+
+/// Slides the expiry of a session in use, once less than half of its lifetime is left, and records
+/// it as seen. Returns whether the session changed and has to be stored again.
+fn touch_session(session: &mut Session, now: chrono::DateTime<chrono::Utc>) -> bool {
+    let mut changed = false;
+
+    if session.expires_at - now < SESSION_TTL / 2 {
+        session.expires_at = now + SESSION_TTL;
+        changed = true;
+    }
+
+    if now - session.last_seen_at >= LAST_SEEN_AT_RESOLUTION {
+        session.last_seen_at = now;
+        changed = true;
+    }
+
+    changed
 }
 
 pub fn generate_session_token() -> String {
-    let mut bytes = [0u8; 20];
-    rand::rng().fill(&mut bytes);
-    base32::encode(base32::Alphabet::Rfc4648 { padding: false }, &bytes).to_lowercase()
+    generate_token()
 }
 
 pub fn generate_session_id(token: &str) -> String {
-    let mut hasher = Sha256::new();
-    hasher.update(token.as_bytes());
-    hex::encode(hasher.finalize()).to_lowercase()
+    hash_token(token)
 }
 
 pub fn to_redis_session_key(session_id: &str) -> String {
     format!("session:{session_id}")
 }
 
+/// Set of the ids of the user's sessions, some of them may have expired since. Each session
+/// written pushes its expiry to a full session lifetime, so it outlives every session in it.
+pub fn to_redis_user_sessions_key(user_id: Uuid) -> String {
+    format!("user_sessions:{user_id}")
+}
+
 pub async fn create_session(
     mut redis: redis::aio::ConnectionManager,
     token: &str,
     user_id: Uuid,
     username: &str,
+    client: ClientInfo,
 ) -> Result<Session, StatusCode> {
     let session_id = generate_session_id(token);
 
-    let expires_at = chrono::Utc::now() + chrono::Duration::minutes(30);
+    let now = Utc::now();
     let session = Session {
         id: session_id,
         user_id,
         username: username.to_string(),
-        expires_at,
+        expires_at: now + SESSION_TTL,
+        user_agent: client.user_agent,
+        ip: client.ip,
+        created_at: now,
+        last_seen_at: now,
     };
 
     let Ok(session_str) = serde_json::to_string(&session) else {
@@ -54,13 +107,25 @@ pub async fn create_session(
 
     let _: () = redis
         .set_ex(
-            format!("session:{}", session.id),
+            to_redis_session_key(&session.id),
             session_str,
-            session.expires_at.timestamp() as u64,
+            session_ttl_secs(&session, now),
         )
         .await
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
+    let user_sessions_key = to_redis_user_sessions_key(user_id);
+
+    let _: () = redis::pipe()
+        .atomic()
+        .sadd(&user_sessions_key, &session.id)
+        .ignore()
+        .expire(&user_sessions_key, SESSION_TTL.num_seconds())
+        .ignore()
+        .query_async(&mut redis)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
     Ok(session)
 }
 
@@ -92,41 +157,49 @@ pub async fn validate_session_token(
 
     let now = Utc::now();
 
-    if now.timestamp() >= session.expires_at.timestamp() {
+    if now >= session.expires_at {
         info!("Session expired for session_id: {session_id}");
-        let _: () = redis
-            .del(to_redis_session_key(&session_id))
-            .await
-            .map_err(|error| {
-                error!("Failed to delete expired session_id: {session_id}, error: {error}");
-                StatusCode::INTERNAL_SERVER_ERROR
-            })?;
+        invalidate_session(redis, session.user_id, &session_id).await?;
 
         return Ok(None);
     }
 
-    let fifteen_minutes = chrono::Duration::minutes(15);
-
-    if now >= session.expires_at - fifteen_minutes {
-        info!("Extending session for session_id: {}", session_id);
-        session.expires_at = Utc::now() + fifteen_minutes;
+    if touch_session(&mut session, now) {
+        info!("Extending session for session_id: {session_id}");
 
         let updated_session = serde_json::to_string(&session).map_err(|error| {
             error!("Failed to serialize updated session: {error}");
             StatusCode::INTERNAL_SERVER_ERROR
         })?;
 
+        // Only while the session still exists, so it can't be revived after being revoked
         let _: () = redis
-            .set_ex(
+            .set_options(
                 to_redis_session_key(&session_id),
                 updated_session,
-                session.expires_at.timestamp() as u64,
+                SetOptions::default()
+                    .conditional_set(ExistenceCheck::XX)
+                    .with_expiration(SetExpiry::EX(session_ttl_secs(&session, now))),
             )
             .await
             .map_err(|error| {
                 error!("Failed to update session in Redis for session_id: {session_id}, error: {error}");
                 StatusCode::INTERNAL_SERVER_ERROR
             })?;
+
+        let _: () = redis
+            .expire(
+                to_redis_user_sessions_key(session.user_id),
+                SESSION_TTL.num_seconds(),
+            )
+            .await
+            .map_err(|error| {
+                error!(
+                    "Failed to extend the session index of user {}: {error}",
+                    session.user_id
+                );
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?;
     }
 
     Ok(Some(session))
@@ -134,6 +207,7 @@ pub async fn validate_session_token(
 
 pub async fn invalidate_session(
     mut redis: redis::aio::ConnectionManager,
+    user_id: Uuid,
     session_id: &str,
 ) -> Result<(), StatusCode> {
     let _: () = redis
@@ -141,5 +215,142 @@ pub async fn invalidate_session(
         .await
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
+    let _: () = redis
+        .srem(to_redis_user_sessions_key(user_id), session_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
     Ok(())
 }
+
+/// Sessions of the user that haven't expired, dropping the expired ones from the user's index.
+pub async fn list_user_sessions(
+    mut redis: redis::aio::ConnectionManager,
+    user_id: Uuid,
+) -> Result<Vec<Session>, StatusCode> {
+    let user_sessions_key = to_redis_user_sessions_key(user_id);
+
+    let session_ids: Vec<String> = redis
+        .smembers(&user_sessions_key)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let mut sessions = Vec::with_capacity(session_ids.len());
+
+    for session_id in session_ids {
+        let session_str: Option<String> = redis
+            .get(to_redis_session_key(&session_id))
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+        match session_str.and_then(|session_str| serde_json::from_str::<Session>(&session_str).ok())
+        {
+            Some(session) if session.expires_at > Utc::now() => sessions.push(session),
+            _ => {
+                let _: () = redis
+                    .srem(&user_sessions_key, &session_id)
+                    .await
+                    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+            }
+        }
+    }
+
+    sessions.sort_by_key(|session| session.created_at);
+
+    Ok(sessions)
+}
+
+/// Invalidates every session of the user, but `except_session_id` when given.
+pub async fn invalidate_user_sessions(
+    mut redis: redis::aio::ConnectionManager,
+    user_id: Uuid,
+    except_session_id: Option<&str>,
+) -> Result<(), StatusCode> {
+    let user_sessions_key = to_redis_user_sessions_key(user_id);
+
+    let session_ids: Vec<String> = redis
+        .smembers(&user_sessions_key)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    for session_id in session_ids
+        .iter()
+        .filter(|session_id| Some(session_id.as_str()) != except_session_id)
+    {
+        let _: () = redis
+            .del(to_redis_session_key(session_id))
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+        let _: () = redis
+            .srem(&user_sessions_key, session_id)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session(now: chrono::DateTime<chrono::Utc>) -> Session {
+        Session {
+            id: generate_session_id(&generate_session_token()),
+            user_id: Uuid::new_v4(),
+            username: "alex".to_string(),
+            expires_at: now + SESSION_TTL,
+            user_agent: None,
+            ip: None,
+            created_at: now,
+            last_seen_at: now,
+        }
+    }
+
+    #[test]
+    fn test_touch_session() {
+        let now = Utc::now();
+        let mut session = session(now);
+
+        assert!(!touch_session(
+            &mut session,
+            now + chrono::Duration::seconds(10)
+        ));
+        assert_eq!(session.expires_at, now + SESSION_TTL);
+
+        let later = now + chrono::Duration::minutes(5);
+        assert!(touch_session(&mut session, later));
+        assert_eq!(session.expires_at, now + SESSION_TTL);
+        assert_eq!(session.last_seen_at, later);
+
+        let later = now + chrono::Duration::minutes(20);
+        assert!(touch_session(&mut session, later));
+        assert_eq!(session.expires_at, later + SESSION_TTL);
+    }
+
+    #[test]
+    fn test_session_ttl_secs() {
+        let now = Utc::now();
+        let mut session = session(now);
+
+        assert_eq!(
+            session_ttl_secs(&session, now),
+            SESSION_TTL.num_seconds() as u64
+        );
+
+        session.expires_at = now;
+        assert_eq!(session_ttl_secs(&session, now), 1);
+    }
+
+    #[test]
+    fn test_session_without_client_info() {
+        let session = serde_json::from_str::<Session>(
+            r#"{"id":"id","userId":"6f1b3b2e-9f5a-4a3c-8f0e-0b1e2d3c4b5a","username":"alex","expiresAt":"2025-01-01T00:00:00Z"}"#,
+        )
+        .unwrap();
+
+        assert_eq!(session.user_agent, None);
+        assert_eq!(session.ip, None);
+    }
+}
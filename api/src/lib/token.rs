@@ -0,0 +1,15 @@
+use rand::Rng;
+use sha2::{Digest, Sha256};
+
+/// Random token handed out to a user, only its hash is stored.
+pub fn generate_token() -> String {
+    let mut bytes = [0u8; 20];
+    rand::rng().fill(&mut bytes);
+    base32::encode(base32::Alphabet::Rfc4648 { padding: false }, &bytes).to_lowercase()
+}
+
+pub fn hash_token(token: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(token.as_bytes());
+    hex::encode(hasher.finalize()).to_lowercase()
+}
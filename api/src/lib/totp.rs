@@ -0,0 +1,345 @@
+use hmac::{Hmac, Mac};
+use hyper::StatusCode;
+use rand::Rng;
+use redis::AsyncCommands;
+use serde::{Deserialize, Serialize};
+use sha1::Sha1;
+use sqlx::PgConnection;
+use subtle::ConstantTimeEq;
+use uuid::Uuid;
+
+use crate::lib::token::{generate_token, hash_token};
+
+/// Issuer shown by authenticator apps.
+const TOTP_ISSUER: &str = "Shallabuf";
+const TOTP_STEP_SECS: i64 = 30;
+const TOTP_DIGITS: u32 = 6;
+/// Steps before and after the current one a code is still accepted for, covers clock drift.
+const TOTP_SKEW_STEPS: i64 = 1;
+
+pub const RECOVERY_CODE_COUNT: usize = 10;
+
+/// How long the second step of a login can be completed for.
+pub const LOGIN_CHALLENGE_TTL_SECS: u64 = 300;
+/// Wrong codes a login challenge tolerates before it's dropped.
+pub const LOGIN_CHALLENGE_MAX_ATTEMPTS: u32 = 5;
+
+/// 160 bits secret as RFC 4226 recommends, base32 encoded for authenticator apps.
+pub fn generate_totp_secret() -> String {
+    let mut bytes = [0u8; 20];
+    rand::rng().fill(&mut bytes);
+    base32::encode(base32::Alphabet::Rfc4648 { padding: false }, &bytes)
+}
+
+/// `otpauth://` URI authenticator apps enroll from, usually shown as a QR code.
+pub fn provisioning_uri(secret: &str, account: &str) -> String {
+    let mut url = url::Url::parse("otpauth://totp/").expect("static url is valid");
+    url.set_path(&format!("/{TOTP_ISSUER}:{account}"));
+
+    url.query_pairs_mut()
+        .append_pair("secret", secret)
+        .append_pair("issuer", TOTP_ISSUER)
+        .append_pair("algorithm", "SHA1")
+        .append_pair("digits", &TOTP_DIGITS.to_string())
+        .append_pair("period", &TOTP_STEP_SECS.to_string());
+
+    url.to_string()
+}
+
+/// HOTP value of the counter, RFC 4226.
+fn hotp(key: &[u8], counter: u64) -> String {
+    let mut mac = Hmac::<Sha1>::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(&counter.to_be_bytes());
+    let digest = mac.finalize().into_bytes();
+
+    let offset = (digest[digest.len() - 1] & 0x0f) as usize;
+    let binary = u32::from_be_bytes([
+        digest[offset] & 0x7f,
+        digest[offset + 1],
+        digest[offset + 2],
+        digest[offset + 3],
+    ]);
+
+    format!(
+        "{:0width$}",
+        binary % 10u32.pow(TOTP_DIGITS),
+        width = TOTP_DIGITS as usize
+    )
+}
+
+/// Time step the code matches, within the tolerated clock drift around `unix_time`.
+pub fn verify_totp_code(secret: &str, code: &str, unix_time: i64) -> Option<i64> {
+    let key = base32::decode(base32::Alphabet::Rfc4648 { padding: false }, secret)?;
+    let code = code.trim().replace(' ', "");
+    let current_step = unix_time / TOTP_STEP_SECS;
+
+    (current_step - TOTP_SKEW_STEPS..=current_step + TOTP_SKEW_STEPS).find(|&step| {
+        step >= 0 && bool::from(hotp(&key, step as u64).as_bytes().ct_eq(code.as_bytes()))
+    })
+}
+
+/// Codes shown once to the user, formatted as `xxxxx-xxxxx`.
+pub fn generate_recovery_codes() -> Vec<String> {
+    (0..RECOVERY_CODE_COUNT)
+        .map(|_| {
+            let token = generate_token();
+            format!("{}-{}", &token[..5], &token[5..10])
+        })
+        .collect()
+}
+
+/// Recovery codes are matched regardless of case and dashes.
+pub fn normalize_recovery_code(code: &str) -> String {
+    let code = code.trim().replace('-', "").to_lowercase();
+
+    match code.get(..5).zip(code.get(5..)) {
+        Some((start, end)) => format!("{start}-{end}"),
+        None => code,
+    }
+}
+
+/// Login waiting for its second step, stored under the hash of its token.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LoginChallenge {
+    pub user_id: Uuid,
+    pub username: String,
+}
+
+pub fn to_redis_login_challenge_key(challenge_id: &str) -> String {
+    format!("login_challenge:{challenge_id}")
+}
+
+pub fn to_redis_login_challenge_attempts_key(challenge_id: &str) -> String {
+    format!("login_challenge_attempts:{challenge_id}")
+}
+
+/// Counts an attempt at completing the login, atomically so concurrent guesses can't go over the
+/// limit, returns the attempts so far.
+pub async fn count_login_challenge_attempt(
+    redis: &mut redis::aio::ConnectionManager,
+    challenge_id: &str,
+) -> Result<u32, redis::RedisError> {
+    let key = to_redis_login_challenge_attempts_key(challenge_id);
+
+    let (attempts,): (u32,) = redis::pipe()
+        .atomic()
+        .incr(&key, 1)
+        .expire(&key, LOGIN_CHALLENGE_TTL_SECS as i64)
+        .ignore()
+        .query_async(redis)
+        .await?;
+
+    Ok(attempts)
+}
+
+/// Starts a login waiting for its second step, returns the token completing it and its expiry.
+pub async fn create_login_challenge(
+    mut redis: redis::aio::ConnectionManager,
+    user_id: Uuid,
+    username: &str,
+) -> Result<(String, chrono::DateTime<chrono::Utc>), StatusCode> {
+    let token = generate_token();
+    let challenge = LoginChallenge {
+        user_id,
+        username: username.to_string(),
+    };
+
+    let challenge_str =
+        serde_json::to_string(&challenge).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let _: () = redis
+        .set_ex(
+            to_redis_login_challenge_key(&hash_token(&token)),
+            challenge_str,
+            LOGIN_CHALLENGE_TTL_SECS,
+        )
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok((
+        token,
+        chrono::Utc::now() + chrono::Duration::seconds(LOGIN_CHALLENGE_TTL_SECS as i64),
+    ))
+}
+
+/// Accepts a TOTP code of the user, each code only once, or one of their unused recovery codes.
+pub async fn use_second_factor(
+    db: &mut PgConnection,
+    user_id: Uuid,
+    code: &str,
+) -> Result<bool, sqlx::Error> {
+    let totp_secret = sqlx::query_scalar!(
+        r#"
+        SELECT
+            totp_secret
+        FROM
+            users
+        WHERE
+            id = $1 AND totp_enabled_at IS NOT NULL
+        "#,
+        user_id
+    )
+    .fetch_optional(&mut *db)
+    .await?
+    .flatten();
+
+    let Some(totp_secret) = totp_secret else {
+        return Ok(false);
+    };
+
+    if let Some(step) = verify_totp_code(&totp_secret, code, chrono::Utc::now().timestamp()) {
+        let accepted = sqlx::query!(
+            r#"
+            UPDATE
+                users
+            SET
+                totp_last_used_step = $2
+            WHERE
+                id = $1 AND (totp_last_used_step IS NULL OR totp_last_used_step < $2)
+            "#,
+            user_id,
+            step
+        )
+        .execute(&mut *db)
+        .await?
+        .rows_affected();
+
+        return Ok(accepted == 1);
+    }
+
+    let used = sqlx::query!(
+        r#"
+        UPDATE
+            recovery_codes
+        SET
+            used_at = CURRENT_TIMESTAMP
+        WHERE
+            user_id = $1 AND code_hash = $2 AND used_at IS NULL
+        "#,
+        user_id,
+        hash_token(&normalize_recovery_code(code))
+    )
+    .execute(&mut *db)
+    .await?
+    .rows_affected();
+
+    Ok(used > 0)
+}
+
+/// Replaces the recovery codes of the user, the new ones are returned this once.
+pub async fn replace_recovery_codes(
+    db: &mut PgConnection,
+    user_id: Uuid,
+) -> Result<Vec<String>, sqlx::Error> {
+    sqlx::query!(
+        r#"
+        DELETE FROM
+            recovery_codes
+        WHERE
+            user_id = $1
+        "#,
+        user_id
+    )
+    .execute(&mut *db)
+    .await?;
+
+    let codes = generate_recovery_codes();
+    let code_hashes = codes
+        .iter()
+        .map(|code| hash_token(code))
+        .collect::<Vec<_>>();
+
+    sqlx::query!(
+        r#"
+        INSERT INTO
+            recovery_codes (user_id, code_hash)
+        SELECT
+            $1, UNNEST($2::varchar[])
+        "#,
+        user_id,
+        &code_hashes
+    )
+    .execute(&mut *db)
+    .await?;
+
+    Ok(codes)
+}
+
+/// Whether the organization of the user or one of their teams requires two-factor authentication.
+pub async fn is_two_factor_required(
+    db: &mut PgConnection,
+    user_id: Uuid,
+) -> Result<bool, sqlx::Error> {
+    sqlx::query_scalar!(
+        r#"
+        SELECT
+            (
+                EXISTS(
+                    SELECT 1 FROM organizations o
+                    JOIN users u ON u.organization_id = o.id
+                    WHERE u.id = $1 AND o.require_two_factor
+                )
+                OR EXISTS(
+                    SELECT 1 FROM teams t
+                    JOIN user_teams ut ON ut.team_id = t.id
+                    WHERE ut.user_id = $1 AND t.require_two_factor
+                )
+            ) AS "required!"
+        "#,
+        user_id
+    )
+    .fetch_one(&mut *db)
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_verify_totp_code() {
+        // Seed of the SHA1 test vectors of RFC 6238, appendix B
+        let secret = base32::encode(
+            base32::Alphabet::Rfc4648 { padding: false },
+            b"12345678901234567890",
+        );
+
+        assert_eq!(verify_totp_code(&secret, "287082", 59), Some(1));
+        assert_eq!(
+            verify_totp_code(&secret, "081804", 1111111109),
+            Some(37037036)
+        );
+        assert_eq!(
+            verify_totp_code(&secret, "081 804", 1111111109 + 30),
+            Some(37037036)
+        );
+        assert_eq!(verify_totp_code(&secret, "081804", 1111111109 + 90), None);
+        assert_eq!(verify_totp_code(&secret, "000000", 59), None);
+        assert_eq!(verify_totp_code("not base32!", "287082", 59), None);
+    }
+
+    #[test]
+    fn test_provisioning_uri() {
+        let secret = generate_totp_secret();
+        let url = url::Url::parse(&provisioning_uri(&secret, "alex@mail.com")).unwrap();
+
+        assert_eq!(url.scheme(), "otpauth");
+        assert_eq!(url.host_str(), Some("totp"));
+        assert_eq!(url.path(), "/Shallabuf:alex@mail.com");
+        assert!(url
+            .query_pairs()
+            .any(|(key, value)| key == "secret" && value == secret));
+    }
+
+    #[test]
+    fn test_recovery_codes() {
+        let codes = generate_recovery_codes();
+
+        assert_eq!(codes.len(), RECOVERY_CODE_COUNT);
+        assert_eq!(normalize_recovery_code(&codes[0].to_uppercase()), codes[0]);
+        assert_eq!(
+            normalize_recovery_code(&codes[0].replace('-', "")),
+            codes[0]
+        );
+    }
+}
@@ -0,0 +1,88 @@
+use db::dtos::UserTokenKind;
+use sqlx::PgConnection;
+use uuid::Uuid;
+
+use crate::lib::token::{generate_token, hash_token};
+
+pub const EMAIL_VERIFICATION_TTL: chrono::Duration = chrono::Duration::hours(24);
+pub const PASSWORD_RESET_TTL: chrono::Duration = chrono::Duration::hours(1);
+
+/// Issues a single use token of the given kind to the user.
+pub async fn create_user_token(
+    db: &mut PgConnection,
+    user_id: Uuid,
+    kind: UserTokenKind,
+    ttl: chrono::Duration,
+) -> Result<String, sqlx::Error> {
+    let token = generate_token();
+
+    sqlx::query!(
+        r#"
+        INSERT INTO
+            user_tokens (user_id, kind, token_hash, expires_at)
+        VALUES
+            ($1, $2, $3, $4)
+        "#,
+        user_id,
+        kind as UserTokenKind,
+        hash_token(&token),
+        (chrono::Utc::now() + ttl) as _
+    )
+    .execute(&mut *db)
+    .await?;
+
+    Ok(token)
+}
+
+/// Marks the token used and returns the user it was issued to,
+/// `None` when it's unknown, expired, already used or of another kind.
+pub async fn use_user_token(
+    db: &mut PgConnection,
+    token: &str,
+    kind: UserTokenKind,
+) -> Result<Option<Uuid>, sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE
+            user_tokens
+        SET
+            used_at = CURRENT_TIMESTAMP
+        WHERE
+            token_hash = $1
+            AND kind = $2
+            AND used_at IS NULL
+            AND expires_at > CURRENT_TIMESTAMP
+        RETURNING
+            user_id
+        "#,
+        hash_token(token),
+        kind as UserTokenKind
+    )
+    .fetch_optional(&mut *db)
+    .await
+    .map(|user_token| user_token.map(|user_token| user_token.user_id))
+}
+
+/// Expires the unused tokens of the given kind the user still has.
+pub async fn expire_user_tokens(
+    db: &mut PgConnection,
+    user_id: Uuid,
+    kind: UserTokenKind,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE
+            user_tokens
+        SET
+            expires_at = CURRENT_TIMESTAMP
+        WHERE
+            user_id = $1 AND kind = $2 AND used_at IS NULL AND expires_at > CURRENT_TIMESTAMP
+        "#,
+        user_id,
+        kind as UserTokenKind
+    )
+    .execute(&mut *db)
+    .await?;
+
+    Ok(())
+}
@@ -0,0 +1,177 @@
+use db::dtos::{PipelineExecPayloadParams, WebhookAuth, WebhookParamMapping, WebhookParamSource};
+use hmac::{Hmac, Mac};
+use hyper::HeaderMap;
+use rand::Rng;
+use sha2::Sha256;
+use std::collections::HashMap;
+use subtle::ConstantTimeEq;
+
+use crate::lib::pipeline_exec::insert_node_param;
+
+pub fn generate_webhook_key() -> String {
+    let mut bytes = [0u8; 20];
+    rand::rng().fill(&mut bytes);
+    base32::encode(base32::Alphabet::Rfc4648 { padding: false }, &bytes).to_lowercase()
+}
+
+pub fn generate_webhook_secret() -> String {
+    let mut bytes = [0u8; 32];
+    rand::rng().fill(&mut bytes);
+    hex::encode(bytes)
+}
+
+pub fn to_webhook_path(path_key: &str) -> String {
+    format!("/api/v0/hooks/{path_key}")
+}
+
+pub fn verify_webhook_request(
+    auth: &WebhookAuth,
+    secret: &str,
+    headers: &HeaderMap,
+    body: &[u8],
+) -> bool {
+    match auth {
+        WebhookAuth::HmacSha256 { header } => {
+            let Some(signature) = headers.get(header).and_then(|value| value.to_str().ok()) else {
+                return false;
+            };
+
+            let signature = signature.trim();
+            let signature = signature.strip_prefix("sha256=").unwrap_or(signature);
+
+            let Ok(signature) = hex::decode(signature) else {
+                return false;
+            };
+
+            let Ok(mut mac) = Hmac::<Sha256>::new_from_slice(secret.as_bytes()) else {
+                return false;
+            };
+
+            mac.update(body);
+            mac.verify_slice(&signature).is_ok()
+        }
+        WebhookAuth::SharedSecret { header } => headers
+            .get(header)
+            .is_some_and(|value| bool::from(value.as_bytes().ct_eq(secret.as_bytes()))),
+    }
+}
+
+/// Builds trigger params out of the request, mappings whose source is missing are skipped.
+pub fn map_webhook_params(
+    mappings: &[WebhookParamMapping],
+    headers: &HeaderMap,
+    query: &HashMap<String, String>,
+    body: &[u8],
+) -> PipelineExecPayloadParams {
+    let body = serde_json::from_slice::<serde_json::Value>(body)
+        .unwrap_or_else(|_| serde_json::Value::String(String::from_utf8_lossy(body).into_owned()));
+
+    let mut params = PipelineExecPayloadParams::new();
+
+    for mapping in mappings {
+        let value = match &mapping.source {
+            WebhookParamSource::Body { pointer } => body.pointer(pointer).cloned(),
+            WebhookParamSource::Header { name } => headers
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(|value| serde_json::Value::String(value.to_string())),
+            WebhookParamSource::Query { name } => query
+                .get(name)
+                .map(|value| serde_json::Value::String(value.clone())),
+        };
+
+        if let Some(value) = value {
+            insert_node_param(&mut params, mapping.pipeline_node_id, &mapping.input, value);
+        }
+    }
+
+    params
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::Uuid;
+
+    fn sign(secret: &str, body: &[u8]) -> String {
+        let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).unwrap();
+        mac.update(body);
+        format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+    }
+
+    #[test]
+    fn test_verify_hmac_signature() {
+        let auth = WebhookAuth::HmacSha256 {
+            header: "x-signature".to_string(),
+        };
+        let body = br#"{"message":"hello"}"#;
+
+        let mut headers = HeaderMap::new();
+        headers.insert("x-signature", sign("secret", body).parse().unwrap());
+        assert!(verify_webhook_request(&auth, "secret", &headers, body));
+        assert!(!verify_webhook_request(&auth, "other", &headers, body));
+        assert!(!verify_webhook_request(&auth, "secret", &headers, b"{}"));
+        assert!(!verify_webhook_request(
+            &auth,
+            "secret",
+            &HeaderMap::new(),
+            body
+        ));
+    }
+
+    #[test]
+    fn test_verify_shared_secret() {
+        let auth = WebhookAuth::SharedSecret {
+            header: "x-secret".to_string(),
+        };
+
+        let mut headers = HeaderMap::new();
+        headers.insert("x-secret", "secret".parse().unwrap());
+        assert!(verify_webhook_request(&auth, "secret", &headers, b""));
+        assert!(!verify_webhook_request(&auth, "secre", &headers, b""));
+    }
+
+    #[test]
+    fn test_map_webhook_params() {
+        let pipeline_node_id = Uuid::new_v4();
+        let mappings = vec![
+            WebhookParamMapping {
+                pipeline_node_id,
+                input: "message".to_string(),
+                source: WebhookParamSource::Body {
+                    pointer: "/data/text".to_string(),
+                },
+            },
+            WebhookParamMapping {
+                pipeline_node_id,
+                input: "transformation".to_string(),
+                source: WebhookParamSource::Query {
+                    name: "mode".to_string(),
+                },
+            },
+            WebhookParamMapping {
+                pipeline_node_id,
+                input: "missing".to_string(),
+                source: WebhookParamSource::Header {
+                    name: "x-missing".to_string(),
+                },
+            },
+        ];
+
+        let query = HashMap::from([("mode".to_string(), "uppercase".to_string())]);
+        let params = map_webhook_params(
+            &mappings,
+            &HeaderMap::new(),
+            &query,
+            br#"{"data":{"text":"hello"}}"#,
+        );
+
+        assert_eq!(
+            params.get(&pipeline_node_id),
+            Some(&serde_json::json!({
+                "message": "hello",
+                "transformation": "uppercase",
+            }))
+        );
+    }
+}
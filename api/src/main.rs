@@ -1,16 +1,21 @@
-use app_state::{AppState, BroadcastEvent, ExecEventsConsumer, WsMessagesBroadcast};
+#![allow(special_module_name)]
+
+use app_state::{
+    AppState, AuthConfig, BroadcastEvent, ExecEventsBroadcast, ExecEventsConsumer,
+    WsMessagesBroadcast,
+};
 use async_nats::{
     self,
     jetstream::{self},
 };
 use axum::{
-    routing::{get, post, delete},
+    routing::{delete, get, patch, post, put},
     Router,
 };
 use db::seed::seed_database;
 use dotenvy::dotenv;
 use sqlx::postgres::{PgPool, PgPoolOptions};
-use std::{env, process};
+use std::{env, net::SocketAddr, process};
 use tokio::{io, sync::broadcast};
 use tower_http::cors::CorsLayer;
 use tracing::{error, info};
@@ -101,11 +106,13 @@ async fn main() -> io::Result<()> {
 
     let jetstream_events = jetstream::new(nats_client.clone());
 
+    let exec_events_stream = jetstream_events
+        .get_stream(event_bridge::JETSTREAM_NAME.to_string())
+        .await
+        .expect("Failed to get or create JetStream");
+
     let exec_events_consumer = ExecEventsConsumer(
-        jetstream_events
-            .get_stream(event_bridge::JETSTREAM_NAME.to_string())
-            .await
-            .expect("Failed to get or create JetStream")
+        exec_events_stream
             .create_consumer(async_nats::jetstream::consumer::pull::Config {
                 durable_name: "EXEC_EVENTS_CONSUMER".to_string().into(),
                 ack_policy: async_nats::jetstream::consumer::AckPolicy::Explicit,
@@ -116,42 +123,256 @@ async fn main() -> io::Result<()> {
             .expect("Failed to create JetStream consumer"),
     );
 
+    let event_triggers_consumer = exec_events_stream
+        .create_consumer(async_nats::jetstream::consumer::pull::Config {
+            durable_name: "EVENT_TRIGGERS_CONSUMER".to_string().into(),
+            ack_policy: async_nats::jetstream::consumer::AckPolicy::Explicit,
+            filter_subject: "exec.events".to_string(),
+            ..Default::default()
+        })
+        .await
+        .expect("Failed to create JetStream consumer");
+
     let database_url = std::env::var("DATABASE_URL").expect("DATABASE_URL must be set");
     let pg_pool = PgPoolOptions::new()
         .connect(&database_url)
         .await
         .map_err(|error| {
             error!("Failed to connect to database: {error:?}");
-            io::Error::new(io::ErrorKind::Other, "Failed to connect to database")
+            io::Error::other("Failed to connect to database")
         })?;
 
     run_migrations(pg_pool.clone()).await;
 
+    let (exec_events_tx, _exec_events_rx) = broadcast::channel(1024);
+    tokio::spawn(lib::exec_events::forward(
+        nats_client.clone(),
+        exec_events_tx.clone(),
+    ));
+
     let redis_url = std::env::var("REDIS_URL").expect("REDIS_URL must be set");
     let redis_client = redis::Client::open(redis_url).expect("Failed to create Redis client");
     let redis_connection_manager = redis::aio::ConnectionManager::new(redis_client)
         .await
         .expect("Failed to create Redis connection manager");
 
+    let rate_limit_config = lib::rate_limit::RateLimitConfig::from_env();
+    let rate_limiter = lib::rate_limit::RateLimiter {
+        redis: redis_connection_manager.clone(),
+        config: rate_limit_config.clone(),
+    };
+
+    tokio::spawn(lib::event_trigger::listen(
+        pg_pool.clone(),
+        jetstream_actions.clone(),
+        rate_limiter.clone(),
+        event_triggers_consumer,
+    ));
+
+    tokio::spawn(lib::nats_trigger::supervise(
+        pg_pool.clone(),
+        nats_client.clone(),
+        jetstream_actions.clone(),
+        rate_limiter,
+    ));
+
     let (tx, _rx) = broadcast::channel::<BroadcastEvent>(100);
     let ws_messages_broadcast = WsMessagesBroadcast(tx);
 
+    let auth_config = AuthConfig::from_env();
+
     let app_state = AppState {
         db: pg_pool,
         redis: redis_connection_manager,
         jetstream: jetstream_actions,
         ws_messages_broadcast,
         exec_events_consumer,
+        exec_events_broadcast: ExecEventsBroadcast(exec_events_tx),
+        mailer: lib::mailer::mailer_from_env(),
+        oauth_config: lib::oauth::OAuthConfig::from_env(&auth_config.app_url),
+        auth_config,
+        rate_limit_config,
     };
 
     let api_v0 = Router::new()
         .route("/auth/login", post(routes::api::v0::auth::login))
         .route("/auth/logout", post(routes::api::v0::auth::logout))
+        .route("/auth/register", post(routes::api::v0::auth::register))
+        .route(
+            "/auth/verify-email",
+            post(routes::api::v0::auth::verify_email),
+        )
+        .route(
+            "/auth/verify-email/resend",
+            post(routes::api::v0::auth::resend_email_verification),
+        )
+        .route(
+            "/auth/oauth/:provider/authorize",
+            get(routes::api::v0::oauth::authorize),
+        )
+        .route(
+            "/auth/oauth/:provider/callback",
+            post(routes::api::v0::oauth::callback),
+        )
+        .route(
+            "/auth/password/forgot",
+            post(routes::api::v0::auth::forgot_password),
+        )
+        .route(
+            "/auth/password/reset",
+            post(routes::api::v0::auth::reset_password),
+        )
+        .route(
+            "/auth/password/change",
+            post(routes::api::v0::auth::change_password),
+        )
+        .route("/auth/login/2fa", post(routes::api::v0::two_factor::login))
+        .route(
+            "/auth/2fa/enroll",
+            post(routes::api::v0::two_factor::enroll),
+        )
+        .route(
+            "/auth/2fa/confirm",
+            post(routes::api::v0::two_factor::confirm),
+        )
+        .route(
+            "/auth/2fa/disable",
+            post(routes::api::v0::two_factor::disable),
+        )
+        .route(
+            "/auth/2fa/recovery-codes",
+            post(routes::api::v0::two_factor::regenerate_recovery_codes),
+        )
         .route("/auth/session", get(routes::api::v0::auth::session))
+        .route("/auth/sessions", get(routes::api::v0::auth::list_sessions))
+        .route(
+            "/auth/sessions",
+            delete(routes::api::v0::auth::revoke_sessions),
+        )
+        .route(
+            "/auth/sessions/:id",
+            delete(routes::api::v0::auth::revoke_session),
+        )
+        .route("/api-tokens", get(routes::api::v0::api_tokens::list))
+        .route("/api-tokens", post(routes::api::v0::api_tokens::create))
+        .route(
+            "/api-tokens/:id",
+            delete(routes::api::v0::api_tokens::revoke),
+        )
         .route("/teams", get(routes::api::v0::teams::list))
+        .route("/teams", post(routes::api::v0::teams::create))
+        .route("/teams/:id", patch(routes::api::v0::teams::update))
+        .route(
+            "/teams/:id/roles",
+            post(routes::api::v0::teams::assign_role),
+        )
+        .route(
+            "/teams/:id/roles/:user_id",
+            delete(routes::api::v0::teams::revoke_role),
+        )
+        .route(
+            "/teams/:id/two-factor-policy",
+            put(routes::api::v0::teams::update_two_factor_policy),
+        )
+        .route(
+            "/teams/:id/trigger-quota",
+            put(routes::api::v0::teams::update_trigger_quota),
+        )
+        .route(
+            "/teams/:id/service-accounts",
+            get(routes::api::v0::service_accounts::list),
+        )
+        .route(
+            "/teams/:id/service-accounts",
+            post(routes::api::v0::service_accounts::create),
+        )
+        .route(
+            "/teams/:id/service-accounts/:service_account_id",
+            delete(routes::api::v0::service_accounts::delete),
+        )
+        .route(
+            "/teams/:id/service-accounts/:service_account_id/tokens",
+            get(routes::api::v0::service_accounts::list_tokens),
+        )
+        .route(
+            "/teams/:id/service-accounts/:service_account_id/tokens",
+            post(routes::api::v0::service_accounts::create_token),
+        )
+        .route(
+            "/teams/:id/service-accounts/:service_account_id/tokens/:token_id",
+            delete(routes::api::v0::service_accounts::revoke_token),
+        )
+        .route(
+            "/organizations",
+            post(routes::api::v0::organizations::create),
+        )
+        .route(
+            "/organizations/:id",
+            patch(routes::api::v0::organizations::update),
+        )
+        .route(
+            "/organizations/:id/members/:user_id",
+            delete(routes::api::v0::organizations::remove_member),
+        )
+        .route(
+            "/organizations/:id/members/:user_id/sessions",
+            delete(routes::api::v0::organizations::revoke_member_sessions),
+        )
+        .route(
+            "/organizations/:id/two-factor-policy",
+            put(routes::api::v0::organizations::update_two_factor_policy),
+        )
+        .route(
+            "/organizations/:id/transfer",
+            post(routes::api::v0::organizations::transfer_ownership),
+        )
+        .route(
+            "/organizations/:id/invitations",
+            get(routes::api::v0::organizations::list_invitations),
+        )
+        .route(
+            "/organizations/:id/invitations",
+            post(routes::api::v0::organizations::create_invitation),
+        )
+        .route(
+            "/organizations/:id/invitations/:invitation_id",
+            delete(routes::api::v0::organizations::delete_invitation),
+        )
+        .route(
+            "/invitations/accept",
+            post(routes::api::v0::invitations::accept),
+        )
+        .route(
+            "/organizations/:id/roles",
+            post(routes::api::v0::organizations::assign_role),
+        )
+        .route(
+            "/organizations/:id/roles/:user_id",
+            delete(routes::api::v0::organizations::revoke_role),
+        )
         .route("/pipelines", get(routes::api::v0::pipelines::list))
         .route("/pipelines", post(routes::api::v0::pipelines::create))
         .route("/pipelines/:id", get(routes::api::v0::pipelines::details))
+        .route(
+            "/pipelines/:id/validate",
+            get(routes::api::v0::pipelines::validate),
+        )
+        .route(
+            "/pipelines/:id/contract",
+            post(routes::api::v0::pipelines::update_contract),
+        )
+        .route(
+            "/pipelines/:id/triggers",
+            get(routes::api::v0::pipeline_triggers::list),
+        )
+        .route(
+            "/pipelines/:id/triggers",
+            post(routes::api::v0::pipeline_triggers::create),
+        )
+        .route(
+            "/pipelines/:id/execs",
+            get(routes::api::v0::pipeline_execs::list),
+        )
         .route(
             "/trigger/pipelines/:id",
             post(routes::api::v0::pipelines::trigger),
@@ -160,6 +381,39 @@ async fn main() -> io::Result<()> {
             "/pipeline-triggers/:id",
             post(routes::api::v0::pipeline_triggers::update),
         )
+        .route(
+            "/pipeline-triggers/:id",
+            delete(routes::api::v0::pipeline_triggers::delete),
+        )
+        .route(
+            "/pipeline-triggers/:id/webhook",
+            post(routes::api::v0::pipeline_triggers::configure_webhook),
+        )
+        .route(
+            "/pipeline-triggers/:id/webhook",
+            delete(routes::api::v0::pipeline_triggers::delete_webhook),
+        )
+        .route(
+            "/pipeline-triggers/:id/webhook/secret",
+            post(routes::api::v0::pipeline_triggers::rotate_webhook_secret),
+        )
+        .route(
+            "/pipeline-triggers/:id/event",
+            post(routes::api::v0::pipeline_triggers::configure_event),
+        )
+        .route(
+            "/pipeline-triggers/:id/event",
+            delete(routes::api::v0::pipeline_triggers::delete_event),
+        )
+        .route(
+            "/pipeline-triggers/:id/nats",
+            post(routes::api::v0::pipeline_triggers::configure_nats),
+        )
+        .route(
+            "/pipeline-triggers/:id/nats",
+            delete(routes::api::v0::pipeline_triggers::delete_nats),
+        )
+        .route("/hooks/:key", post(routes::api::v0::webhooks::handle))
         .route("/nodes", get(routes::api::v0::nodes::list))
         .route(
             "/pipeline-nodes",
@@ -171,7 +425,11 @@ async fn main() -> io::Result<()> {
         )
         .route(
             "/pipeline-nodes/:id",
-            delete(routes::api::v0::pipeline_nodes::delete)
+            patch(routes::api::v0::pipeline_nodes::update),
+        )
+        .route(
+            "/pipeline-nodes/:id",
+            delete(routes::api::v0::pipeline_nodes::delete),
         )
         .route(
             "/pipeline-node-connections",
@@ -179,9 +437,29 @@ async fn main() -> io::Result<()> {
         )
         .route(
             "/pipeline-execs/:id",
+            get(routes::api::v0::pipeline_execs::details),
+        )
+        .route(
+            "/pipeline-execs/:id/retry",
+            post(routes::api::v0::pipeline_execs::retry),
+        )
+        .route(
+            "/pipeline-execs/:id/replay",
+            post(routes::api::v0::pipeline_execs::replay),
+        )
+        .route(
+            "/pipeline-execs/:id/diff",
+            get(routes::api::v0::pipeline_execs::diff),
+        )
+        .route(
+            "/pipeline-execs/:id/events",
             get(routes::api::v0::pipeline_execs::subscribe),
         )
-        .route("/ws", get(routes::api::v0::events::ws_events));
+        .route("/ws", get(routes::api::v0::events::ws_events))
+        .layer(axum::middleware::from_fn_with_state(
+            app_state.clone(),
+            lib::rate_limit::rate_limit,
+        ));
 
     let app = Router::new()
         .nest("/api/v0", api_v0)
@@ -189,7 +467,11 @@ async fn main() -> io::Result<()> {
         .layer(CorsLayer::permissive());
 
     let listener = tokio::net::TcpListener::bind("0.0.0.0:8000").await?;
-    axum::serve(listener, app).await?;
+    axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .await?;
 
     Ok(())
 }
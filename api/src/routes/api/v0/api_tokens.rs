@@ -0,0 +1,200 @@
+use axum::{extract::Path, Json};
+use db::dtos::ApiTokenScope;
+use hyper::StatusCode;
+use serde::{Deserialize, Serialize};
+use sqlx::PgConnection;
+use uuid::Uuid;
+
+use crate::{
+    app_state::DatabaseConnection,
+    extractors::session::Session,
+    lib::{
+        api_error::{api_error, ApiError},
+        api_token::create_api_token,
+    },
+    routes::api::v0::organizations::check_name,
+};
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiToken {
+    pub id: Uuid,
+    pub name: String,
+    pub scopes: Vec<ApiTokenScope>,
+    /// `None` for tokens that don't expire.
+    pub expires_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub last_used_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiTokenCreate {
+    name: String,
+    scopes: Vec<ApiTokenScope>,
+    expires_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CreatedApiToken {
+    /// Only returned on creation, the token can't be retrieved afterwards.
+    pub token: String,
+    #[serde(flatten)]
+    pub api_token: ApiToken,
+}
+
+/// Tokens of the user that can still be used.
+pub async fn fetch_api_tokens(
+    db: &mut PgConnection,
+    user_id: Uuid,
+) -> Result<Vec<ApiToken>, (StatusCode, Json<ApiError>)> {
+    sqlx::query_as!(
+        ApiToken,
+        r#"
+        SELECT
+            id,
+            name,
+            scopes AS "scopes: Vec<ApiTokenScope>",
+            expires_at AS "expires_at: chrono::DateTime<chrono::Utc>",
+            last_used_at AS "last_used_at: chrono::DateTime<chrono::Utc>",
+            created_at AS "created_at!: chrono::DateTime<chrono::Utc>"
+        FROM
+            api_tokens
+        WHERE
+            user_id = $1
+            AND revoked_at IS NULL
+            AND (expires_at IS NULL OR expires_at > CURRENT_TIMESTAMP)
+        ORDER BY
+            created_at
+        "#,
+        user_id
+    )
+    .fetch_all(&mut *db)
+    .await
+    .map_err(api_error)
+}
+
+/// Issues a token to the user, on behalf of `created_by_user_id`.
+pub async fn issue_api_token(
+    db: &mut PgConnection,
+    user_id: Uuid,
+    created_by_user_id: Uuid,
+    params: ApiTokenCreate,
+) -> Result<CreatedApiToken, (StatusCode, Json<ApiError>)> {
+    let name = check_name(&params.name)?;
+
+    let mut scopes = Vec::new();
+
+    for scope in params.scopes {
+        if !scopes.contains(&scope) {
+            scopes.push(scope);
+        }
+    }
+
+    if scopes.is_empty() {
+        return Err((
+            StatusCode::UNPROCESSABLE_ENTITY,
+            Json(ApiError {
+                error: "The token needs at least one scope".to_string(),
+            }),
+        ));
+    }
+
+    if params
+        .expires_at
+        .is_some_and(|expires_at| expires_at <= chrono::Utc::now())
+    {
+        return Err((
+            StatusCode::UNPROCESSABLE_ENTITY,
+            Json(ApiError {
+                error: "The expiry date must be in the future".to_string(),
+            }),
+        ));
+    }
+
+    let (token, id, created_at) = create_api_token(
+        db,
+        user_id,
+        created_by_user_id,
+        name,
+        &scopes,
+        params.expires_at,
+    )
+    .await
+    .map_err(api_error)?;
+
+    Ok(CreatedApiToken {
+        token,
+        api_token: ApiToken {
+            id,
+            name: name.to_string(),
+            scopes,
+            expires_at: params.expires_at,
+            last_used_at: None,
+            created_at,
+        },
+    })
+}
+
+/// Revokes a token of the user, `404` when it isn't theirs or was already revoked.
+pub async fn revoke_api_token(
+    db: &mut PgConnection,
+    user_id: Uuid,
+    id: Uuid,
+) -> Result<StatusCode, (StatusCode, Json<ApiError>)> {
+    let revoked = sqlx::query!(
+        r#"
+        UPDATE
+            api_tokens
+        SET
+            revoked_at = CURRENT_TIMESTAMP
+        WHERE
+            id = $1 AND user_id = $2 AND revoked_at IS NULL
+        "#,
+        id,
+        user_id
+    )
+    .execute(&mut *db)
+    .await
+    .map_err(api_error)?
+    .rows_affected();
+
+    if revoked == 0 {
+        return Err((
+            StatusCode::NOT_FOUND,
+            Json(ApiError {
+                error: "Not found".to_string(),
+            }),
+        ));
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Personal tokens of the caller.
+pub async fn list(
+    DatabaseConnection(mut conn): DatabaseConnection,
+    Session(session): Session,
+) -> Result<Json<Vec<ApiToken>>, (StatusCode, Json<ApiError>)> {
+    fetch_api_tokens(&mut conn, session.user_id).await.map(Json)
+}
+
+/// Issues a personal token acting as the caller, within its scopes.
+pub async fn create(
+    DatabaseConnection(mut conn): DatabaseConnection,
+    Session(session): Session,
+    Json(params): Json<ApiTokenCreate>,
+) -> Result<(StatusCode, Json<CreatedApiToken>), (StatusCode, Json<ApiError>)> {
+    let api_token = issue_api_token(&mut conn, session.user_id, session.user_id, params).await?;
+
+    Ok((StatusCode::CREATED, Json(api_token)))
+}
+
+pub async fn revoke(
+    DatabaseConnection(mut conn): DatabaseConnection,
+    Session(session): Session,
+    Path(id): Path<Uuid>,
+) -> Result<StatusCode, (StatusCode, Json<ApiError>)> {
+    revoke_api_token(&mut conn, session.user_id, id).await
+}
@@ -1,14 +1,39 @@
 use crate::{
-    app_state::{DatabaseConnection, RedisConnection},
-    lib::session::{create_session, generate_session_token, invalidate_session},
+    app_state::{AppMailer, AuthConfig, DatabaseConnection, RedisConnection},
+    lib::{
+        api_error::{api_error, ApiError},
+        invitation::{invitation_rejection, join_invitation, lock_invitation},
+        mailer::{Mail, Mailer},
+        password::{hash_password, password_rejection, verify_password},
+        rate_limit::{
+            check_login_lockout, clear_login_failures, record_login_failure, RateLimitConfig,
+        },
+        session::{
+            create_session, generate_session_token, invalidate_session, invalidate_user_sessions,
+            list_user_sessions, to_redis_user_sessions_key, ClientInfo,
+        },
+        totp::create_login_challenge,
+        user_token::{
+            create_user_token, expire_user_tokens, use_user_token, EMAIL_VERIFICATION_TTL,
+            PASSWORD_RESET_TTL,
+        },
+    },
     extractors::session::Session,
+    routes::api::v0::organizations::check_name,
     utils::internal_error,
 };
-use argon2::{password_hash::PasswordHash, Argon2, PasswordVerifier};
-use axum::Json;
-use db::dtos::KeyProviderType;
+use axum::{
+    extract::Path,
+    response::{IntoResponse, Response},
+    Json,
+};
+use db::dtos::{KeyProviderType, MemberRole, UserTokenKind};
 use hyper::StatusCode;
+use redis::AsyncCommands;
 use serde::{Deserialize, Serialize};
+use sqlx::{Acquire, PgConnection};
+use tracing::error;
+use uuid::Uuid;
 use crate::lib::session::Session as SessionValue;
 
 #[derive(Deserialize)]
@@ -24,15 +49,76 @@ pub struct LoginResponse {
     pub expires_at: chrono::DateTime<chrono::Utc>,
 }
 
-pub async fn login(
-    DatabaseConnection(mut conn): DatabaseConnection,
-    RedisConnection(redis): RedisConnection,
-    Json(LoginRequest { email, password }): Json<LoginRequest>,
-) -> Result<Json<LoginResponse>, StatusCode> {
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TwoFactorChallengeResponse {
+    pub two_factor_required: bool,
+    /// Completes the login along with a code, see `two_factor::login`.
+    pub two_factor_token: String,
+    pub expires_at: chrono::DateTime<chrono::Utc>,
+}
+
+#[derive(Serialize)]
+#[serde(untagged)]
+pub enum LoginOutcome {
+    Session(LoginResponse),
+    TwoFactorRequired(TwoFactorChallengeResponse),
+}
+
+/// Creates a session for the authenticated user, or a challenge for the second step of the login
+/// when they enabled two-factor authentication.
+pub async fn start_login(
+    conn: &mut PgConnection,
+    redis: redis::aio::ConnectionManager,
+    user_id: Uuid,
+    username: &str,
+    client: ClientInfo,
+) -> Result<LoginOutcome, StatusCode> {
+    let two_factor_enabled = sqlx::query_scalar!(
+        r#"
+        SELECT
+            totp_enabled_at IS NOT NULL AS "enabled!"
+        FROM
+            users
+        WHERE
+            id = $1
+        "#,
+        user_id
+    )
+    .fetch_one(&mut *conn)
+    .await
+    .map_err(internal_error)?;
+
+    if two_factor_enabled {
+        let (token, expires_at) = create_login_challenge(redis, user_id, username).await?;
+
+        return Ok(LoginOutcome::TwoFactorRequired(TwoFactorChallengeResponse {
+            two_factor_required: true,
+            two_factor_token: token,
+            expires_at,
+        }));
+    }
+
+    let token = generate_session_token();
+    let session = create_session(redis, &token, user_id, username, client).await?;
+
+    Ok(LoginOutcome::Session(LoginResponse {
+        token,
+        expires_at: session.expires_at,
+    }))
+}
+
+/// User the password logs in as, `401` when it doesn't match.
+async fn check_credentials(
+    conn: &mut PgConnection,
+    auth_config: &AuthConfig,
+    email: &str,
+    password: &str,
+) -> Result<(Uuid, String), StatusCode> {
     let user = sqlx::query!(
         r#"
         SELECT
-            users.id, users.name, users.password_hash
+            users.id, users.name, users.password_hash, users.email_verified
         FROM
             users
         JOIN
@@ -57,19 +143,61 @@ pub async fn login(
         return Err(StatusCode::UNAUTHORIZED);
     };
 
-    let parsed_password_hash = PasswordHash::new(&password_hash).map_err(internal_error)?;
+    if !verify_password(password, &password_hash).map_err(internal_error)? {
+        return Err(StatusCode::UNAUTHORIZED);
+    }
 
-    Argon2::default()
-        .verify_password(password.as_bytes(), &parsed_password_hash)
-        .map_err(internal_error)?;
+    if auth_config.require_email_verification && !user.email_verified {
+        return Err(StatusCode::FORBIDDEN);
+    }
 
-    let token = generate_session_token();
-    let session = create_session(redis, &token, user.id, &user.name).await?;
+    Ok((user.id, user.name))
+}
 
-    Ok(Json(LoginResponse {
-        token,
-        expires_at: session.expires_at,
-    }))
+/// Accounts and addresses with too many failed logins lately are locked out with a `429`, a
+/// lockout ends once the oldest failures expire.
+pub async fn login(
+    DatabaseConnection(mut conn): DatabaseConnection,
+    RedisConnection(mut redis): RedisConnection,
+    auth_config: AuthConfig,
+    rate_limit_config: RateLimitConfig,
+    client: ClientInfo,
+    Json(LoginRequest { email, password }): Json<LoginRequest>,
+) -> Result<Json<LoginOutcome>, Response> {
+    match check_login_lockout(&mut redis, &rate_limit_config, &email, &client).await {
+        Ok(Ok(())) => {}
+        Ok(Err(rate_limited)) => return Err(rate_limited.into_response()),
+        Err(error) => error!("Failed to check the login lockout: {error}"),
+    }
+
+    let credentials = check_credentials(&mut conn, &auth_config, &email, &password).await;
+
+    let (user_id, username) = match credentials {
+        Ok(user) => user,
+        Err(StatusCode::UNAUTHORIZED) => {
+            if let Err(error) =
+                record_login_failure(&mut redis, &rate_limit_config, &email, &client).await
+            {
+                error!("Failed to record the login failure: {error}");
+            }
+
+            return Err(StatusCode::UNAUTHORIZED.into_response());
+        }
+        Err(status) => return Err(status.into_response()),
+    };
+
+    let outcome = start_login(&mut conn, redis.clone(), user_id, &username, client)
+        .await
+        .map_err(IntoResponse::into_response)?;
+
+    // Logins with a second step succeed once it's completed, see `two_factor::login`
+    if let LoginOutcome::Session(_) = outcome {
+        if let Err(error) = clear_login_failures(&mut redis, &email).await {
+            error!("Failed to clear the login failures: {error}");
+        }
+    }
+
+    Ok(Json(outcome))
 }
 
 pub async fn session(Session(session): Session) -> Result<Json<SessionValue>, StatusCode>  {
@@ -86,9 +214,569 @@ pub async fn logout(
     Session(session): Session,
     RedisConnection(redis): RedisConnection,
 ) -> Result<Json<LogoutResponse>, StatusCode> {
-    invalidate_session(redis, &session.id).await.map_err(internal_error)?;
+    invalidate_session(redis, session.user_id, &session.id)
+        .await
+        .map_err(internal_error)?;
 
     Ok(Json(LogoutResponse {
         success: true,
     }))
 }
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionListItem {
+    pub id: String,
+    pub user_agent: Option<String>,
+    pub ip: Option<String>,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub last_seen_at: chrono::DateTime<chrono::Utc>,
+    pub expires_at: chrono::DateTime<chrono::Utc>,
+    /// Whether it's the session making the request.
+    pub current: bool,
+}
+
+/// Active sessions of the caller, the oldest first.
+pub async fn list_sessions(
+    Session(session): Session,
+    RedisConnection(redis): RedisConnection,
+) -> Result<Json<Vec<SessionListItem>>, StatusCode> {
+    let sessions = list_user_sessions(redis, session.user_id)
+        .await?
+        .into_iter()
+        .map(|user_session| SessionListItem {
+            current: user_session.id == session.id,
+            id: user_session.id,
+            user_agent: user_session.user_agent,
+            ip: user_session.ip,
+            created_at: user_session.created_at,
+            last_seen_at: user_session.last_seen_at,
+            expires_at: user_session.expires_at,
+        })
+        .collect();
+
+    Ok(Json(sessions))
+}
+
+/// Logs one of the caller's sessions out, the current one included.
+pub async fn revoke_session(
+    Session(session): Session,
+    RedisConnection(mut redis): RedisConnection,
+    Path(id): Path<String>,
+) -> Result<StatusCode, StatusCode> {
+    let is_member: bool = redis
+        .sismember(to_redis_user_sessions_key(session.user_id), &id)
+        .await
+        .map_err(internal_error)?;
+
+    if !is_member {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    invalidate_session(redis, session.user_id, &id).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Logs the caller out everywhere.
+pub async fn revoke_sessions(
+    Session(session): Session,
+    RedisConnection(redis): RedisConnection,
+) -> Result<StatusCode, StatusCode> {
+    invalidate_user_sessions(redis, session.user_id, None).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RegisterRequest {
+    pub name: String,
+    pub email: String,
+    pub password: String,
+    /// Creates an organization owned by the new user.
+    pub organization_name: Option<String>,
+    /// Joins the organization or team of the invitation instead.
+    pub invitation_token: Option<String>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RegisterResponse {
+    pub user_id: Uuid,
+    pub organization_id: Uuid,
+    /// Whether logging in waits for the email address to be verified.
+    pub email_verification_required: bool,
+}
+
+fn unprocessable(error: impl Into<String>) -> (StatusCode, Json<ApiError>) {
+    (
+        StatusCode::UNPROCESSABLE_ENTITY,
+        Json(ApiError {
+            error: error.into(),
+        }),
+    )
+}
+
+/// Mails the email verification link, failing to deliver it isn't fatal as the user can ask for
+/// another one.
+async fn send_email_verification(
+    mailer: &dyn Mailer,
+    auth_config: &AuthConfig,
+    user_id: Uuid,
+    email: &str,
+    token: &str,
+) {
+    let mail = Mail {
+        to: email.to_string(),
+        subject: "Verify your email address".to_string(),
+        body: format!(
+            "Open {}/verify-email?token={token} to verify your email address, the link expires in {} hours.",
+            auth_config.app_url,
+            EMAIL_VERIFICATION_TTL.num_hours()
+        ),
+    };
+
+    if let Err(error) = mailer.send(mail).await {
+        error!("Failed to send the email verification mail of user {user_id}: {error:?}");
+    }
+}
+
+/// Creates an account along with a new organization it owns, or in the organization of an invitation.
+pub async fn register(
+    DatabaseConnection(mut conn): DatabaseConnection,
+    AppMailer(mailer): AppMailer,
+    auth_config: AuthConfig,
+    Json(params): Json<RegisterRequest>,
+) -> Result<(StatusCode, Json<RegisterResponse>), (StatusCode, Json<ApiError>)> {
+    let name = params.name.trim();
+    let email = params.email.trim();
+
+    if name.is_empty() {
+        return Err(unprocessable("The name can't be empty"));
+    }
+
+    if !email.contains('@') {
+        return Err(unprocessable("Invalid email address"));
+    }
+
+    if let Some(error) = password_rejection(&params.password) {
+        return Err(unprocessable(error));
+    }
+
+    let password_hash = hash_password(&params.password).map_err(|error| {
+        (
+            internal_error(error),
+            Json(ApiError {
+                error: error.to_string(),
+            }),
+        )
+    })?;
+
+    let mut tx = conn.begin().await.map_err(api_error)?;
+
+    let (organization_id, organization_role, invitation) =
+        match (&params.organization_name, &params.invitation_token) {
+            (Some(organization_name), None) => {
+                let organization = sqlx::query!(
+                    r#"
+                    INSERT INTO
+                        organizations (name)
+                    VALUES
+                        ($1)
+                    RETURNING
+                        id
+                    "#,
+                    check_name(organization_name)?
+                )
+                .fetch_one(&mut *tx)
+                .await
+                .map_err(api_error)?;
+
+                (organization.id, Some(MemberRole::Owner), None)
+            }
+            (None, Some(invitation_token)) => {
+                let Some(invitation) = lock_invitation(&mut tx, invitation_token).await? else {
+                    return Err(unprocessable("Invitation not found"));
+                };
+
+                if let Some(error) = invitation_rejection(&invitation, email, chrono::Utc::now()) {
+                    return Err(unprocessable(error));
+                }
+
+                (invitation.organization_id, None, Some(invitation))
+            }
+            _ => {
+                return Err(unprocessable(
+                    "Either an organization name or an invitation token is required",
+                ))
+            }
+        };
+
+    let user = sqlx::query!(
+        r#"
+        INSERT INTO
+            users (name, email, password_hash, organization_id, organization_role)
+        VALUES
+            ($1, $2, $3, $4, $5)
+        RETURNING
+            id
+        "#,
+        name,
+        email,
+        password_hash,
+        organization_id,
+        organization_role as Option<MemberRole>
+    )
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(|error| match error {
+        sqlx::Error::Database(ref database_error) if database_error.is_unique_violation() => (
+            StatusCode::CONFLICT,
+            Json(ApiError {
+                error: "The name or the email address is already taken".to_string(),
+            }),
+        ),
+        error => api_error(error),
+    })?;
+
+    sqlx::query!(
+        r#"
+        INSERT INTO
+            keys (user_id, provider, provider_key)
+        VALUES
+            ($1, $2, $3)
+        "#,
+        user.id,
+        KeyProviderType::Password as KeyProviderType,
+        email
+    )
+    .execute(&mut *tx)
+    .await
+    .map_err(api_error)?;
+
+    if let Some(invitation) = invitation {
+        join_invitation(&mut tx, &invitation, user.id).await?;
+    }
+
+    let token = create_user_token(
+        &mut tx,
+        user.id,
+        UserTokenKind::EmailVerification,
+        EMAIL_VERIFICATION_TTL,
+    )
+    .await
+    .map_err(api_error)?;
+
+    tx.commit().await.map_err(api_error)?;
+
+    send_email_verification(mailer.as_ref(), &auth_config, user.id, email, &token).await;
+
+    Ok((
+        StatusCode::CREATED,
+        Json(RegisterResponse {
+            user_id: user.id,
+            organization_id,
+            email_verification_required: auth_config.require_email_verification,
+        }),
+    ))
+}
+
+#[derive(Deserialize)]
+pub struct VerifyEmailRequest {
+    pub token: String,
+}
+
+pub async fn verify_email(
+    DatabaseConnection(mut conn): DatabaseConnection,
+    Json(params): Json<VerifyEmailRequest>,
+) -> Result<StatusCode, (StatusCode, Json<ApiError>)> {
+    let mut tx = conn.begin().await.map_err(api_error)?;
+
+    let Some(user_id) = use_user_token(&mut tx, &params.token, UserTokenKind::EmailVerification)
+        .await
+        .map_err(api_error)?
+    else {
+        return Err(unprocessable("The verification link is invalid or expired"));
+    };
+
+    sqlx::query!(
+        r#"
+        UPDATE
+            users
+        SET
+            email_verified = TRUE
+        WHERE
+            id = $1
+        "#,
+        user_id
+    )
+    .execute(&mut *tx)
+    .await
+    .map_err(api_error)?;
+
+    tx.commit().await.map_err(api_error)?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[derive(Deserialize)]
+pub struct ResendEmailVerificationRequest {
+    pub email: String,
+}
+
+/// Mails a new verification link, the response doesn't tell whether the account exists.
+pub async fn resend_email_verification(
+    DatabaseConnection(mut conn): DatabaseConnection,
+    AppMailer(mailer): AppMailer,
+    auth_config: AuthConfig,
+    Json(params): Json<ResendEmailVerificationRequest>,
+) -> Result<StatusCode, (StatusCode, Json<ApiError>)> {
+    let user = sqlx::query!(
+        r#"
+        SELECT
+            id, email
+        FROM
+            users
+        WHERE
+            email = $1 AND NOT email_verified
+        "#,
+        params.email.trim()
+    )
+    .fetch_optional(&mut *conn)
+    .await
+    .map_err(api_error)?;
+
+    if let Some(user) = user {
+        let token = create_user_token(
+            &mut conn,
+            user.id,
+            UserTokenKind::EmailVerification,
+            EMAIL_VERIFICATION_TTL,
+        )
+        .await
+        .map_err(api_error)?;
+
+        send_email_verification(mailer.as_ref(), &auth_config, user.id, &user.email, &token).await;
+    }
+
+    Ok(StatusCode::ACCEPTED)
+}
+
+#[derive(Deserialize)]
+pub struct ForgotPasswordRequest {
+    pub email: String,
+}
+
+/// Mails a password reset link, the response doesn't tell whether the account exists.
+pub async fn forgot_password(
+    DatabaseConnection(mut conn): DatabaseConnection,
+    AppMailer(mailer): AppMailer,
+    auth_config: AuthConfig,
+    Json(params): Json<ForgotPasswordRequest>,
+) -> Result<StatusCode, (StatusCode, Json<ApiError>)> {
+    let user = sqlx::query!(
+        r#"
+        SELECT
+            users.id, users.email
+        FROM
+            users
+        JOIN
+            keys ON keys.user_id = users.id
+        WHERE
+            users.email = $1
+        AND
+            keys.provider = $2
+        "#,
+        params.email.trim(),
+        KeyProviderType::Password as KeyProviderType,
+    )
+    .fetch_optional(&mut *conn)
+    .await
+    .map_err(api_error)?;
+
+    let Some(user) = user else {
+        return Ok(StatusCode::ACCEPTED);
+    };
+
+    let token = create_user_token(
+        &mut conn,
+        user.id,
+        UserTokenKind::PasswordReset,
+        PASSWORD_RESET_TTL,
+    )
+    .await
+    .map_err(api_error)?;
+
+    let mail = Mail {
+        to: user.email,
+        subject: "Reset your password".to_string(),
+        body: format!(
+            "Open {}/reset-password?token={token} to choose a new password, the link expires in {} minutes. Ignore this mail if you didn't ask for it.",
+            auth_config.app_url,
+            PASSWORD_RESET_TTL.num_minutes()
+        ),
+    };
+
+    if let Err(error) = mailer.send(mail).await {
+        error!(
+            "Failed to send the password reset mail of user {}: {error:?}",
+            user.id
+        );
+    }
+
+    Ok(StatusCode::ACCEPTED)
+}
+
+async fn update_password_hash(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    password: &str,
+) -> Result<(), (StatusCode, Json<ApiError>)> {
+    if let Some(error) = password_rejection(password) {
+        return Err(unprocessable(error));
+    }
+
+    let password_hash = hash_password(password).map_err(|error| {
+        (
+            internal_error(error),
+            Json(ApiError {
+                error: error.to_string(),
+            }),
+        )
+    })?;
+
+    sqlx::query!(
+        r#"
+        UPDATE
+            users
+        SET
+            password_hash = $1
+        WHERE
+            id = $2
+        "#,
+        password_hash,
+        user_id
+    )
+    .execute(&mut *conn)
+    .await
+    .map_err(api_error)?;
+
+    Ok(())
+}
+
+#[derive(Deserialize)]
+pub struct ResetPasswordRequest {
+    pub token: String,
+    pub password: String,
+}
+
+/// Sets a new password with a reset token, every session of the user is invalidated.
+pub async fn reset_password(
+    DatabaseConnection(mut conn): DatabaseConnection,
+    RedisConnection(redis): RedisConnection,
+    Json(params): Json<ResetPasswordRequest>,
+) -> Result<StatusCode, (StatusCode, Json<ApiError>)> {
+    // Checked before using the token, which would be spent otherwise
+    if let Some(error) = password_rejection(&params.password) {
+        return Err(unprocessable(error));
+    }
+
+    let mut tx = conn.begin().await.map_err(api_error)?;
+
+    let Some(user_id) = use_user_token(&mut tx, &params.token, UserTokenKind::PasswordReset)
+        .await
+        .map_err(api_error)?
+    else {
+        return Err(unprocessable("The reset link is invalid or expired"));
+    };
+
+    update_password_hash(&mut tx, user_id, &params.password).await?;
+
+    expire_user_tokens(&mut tx, user_id, UserTokenKind::PasswordReset)
+        .await
+        .map_err(api_error)?;
+
+    tx.commit().await.map_err(api_error)?;
+
+    invalidate_user_sessions(redis, user_id, None)
+        .await
+        .map_err(|status| {
+            (
+                status,
+                Json(ApiError {
+                    error: "Failed to invalidate the sessions".to_string(),
+                }),
+            )
+        })?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChangePasswordRequest {
+    pub current_password: String,
+    pub new_password: String,
+}
+
+/// Changes the caller's password, every other session of theirs is invalidated.
+pub async fn change_password(
+    DatabaseConnection(mut conn): DatabaseConnection,
+    RedisConnection(redis): RedisConnection,
+    Session(session): Session,
+    Json(params): Json<ChangePasswordRequest>,
+) -> Result<StatusCode, (StatusCode, Json<ApiError>)> {
+    let user = sqlx::query!(
+        r#"
+        SELECT
+            password_hash
+        FROM
+            users
+        WHERE
+            id = $1
+        "#,
+        session.user_id
+    )
+    .fetch_one(&mut *conn)
+    .await
+    .map_err(api_error)?;
+
+    let current_password_matches = match user.password_hash {
+        Some(password_hash) => {
+            verify_password(&params.current_password, &password_hash).map_err(|error| {
+                (
+                    internal_error(error),
+                    Json(ApiError {
+                        error: error.to_string(),
+                    }),
+                )
+            })?
+        }
+        None => false,
+    };
+
+    if !current_password_matches {
+        return Err((
+            StatusCode::FORBIDDEN,
+            Json(ApiError {
+                error: "The current password is wrong".to_string(),
+            }),
+        ));
+    }
+
+    update_password_hash(&mut conn, session.user_id, &params.new_password).await?;
+
+    invalidate_user_sessions(redis, session.user_id, Some(&session.id))
+        .await
+        .map_err(|status| {
+            (
+                status,
+                Json(ApiError {
+                    error: "Failed to invalidate the sessions".to_string(),
+                }),
+            )
+        })?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
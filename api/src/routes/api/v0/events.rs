@@ -5,8 +5,10 @@ use axum::{
     },
     response::IntoResponse,
 };
+use db::dtos::MemberRole;
 use redis::AsyncCommands;
 use sqlx::{pool::PoolConnection, Postgres};
+use std::collections::HashMap;
 use tokio::sync::broadcast;
 use tracing::{debug, error, warn};
 use uuid::Uuid;
@@ -19,7 +21,7 @@ use crate::{
         UpdatePipelineEditorParticipantCursorPositionPayload,
         UpdatePipelineEditorParticipantNodePositionPayload, WsClientAction, WsMessagesBroadcast,
     },
-    lib::session::validate_session_token,
+    lib::{authorization::authorize_pipeline, session::validate_session_token},
 };
 
 pub async fn ws_events(
@@ -39,6 +41,8 @@ async fn handle_ws_events(
     sender: broadcast::Sender<BroadcastEvent>,
 ) {
     let mut user_id = None::<Uuid>;
+    // Pipelines the socket entered the editor of, along with the user's role in them
+    let mut pipelines = HashMap::<Uuid, MemberRole>::new();
     let mut receiver = sender.subscribe();
 
     loop {
@@ -53,6 +57,11 @@ async fn handle_ws_events(
                                 WsClientAction::Authenticate(ref mut update) => {
                                     if let Ok(Some(session)) = validate_session_token(redis.clone(), &update.payload.token).await {
                                         debug!("Authenticated WebSocket connection for user_id: {:?}", session.user_id);
+
+                                        if user_id != Some(session.user_id) {
+                                            pipelines.clear();
+                                        }
+
                                         user_id = Some(session.user_id);
 
                                         match serde_json::to_string(&BroadcastEventAction::AuthState(BroadcastEventActionPayload {
@@ -92,6 +101,11 @@ async fn handle_ws_events(
                                 }
                                 WsClientAction::UpdateNodePosition(action) => {
                                     if let Some(user_id) = user_id {
+                                        if pipelines.get(&action.payload.pipeline_id).is_none_or(|role| *role < MemberRole::Editor) {
+                                            warn!("WsAction::UpdateNodePosition: User can't edit the pipeline");
+                                            continue;
+                                        }
+
                                         let event = BroadcastEvent {
                                             sender_id: user_id,
                                             action: BroadcastEventAction::UpdateNodePosition(BroadcastEventActionPayload {
@@ -118,6 +132,16 @@ async fn handle_ws_events(
                                 }
                                 WsClientAction::EnterPipelineEditor(action) => {
                                     if let Some(user_id) = user_id {
+                                        match authorize_pipeline(&mut conn, user_id, action.payload.pipeline_id, MemberRole::Viewer).await {
+                                            Ok(role) => {
+                                                pipelines.insert(action.payload.pipeline_id, role);
+                                            }
+                                            Err(error) => {
+                                                warn!("WsAction::EnterPipelineEditor: User can't access the pipeline: {error:?}");
+                                                continue;
+                                            }
+                                        }
+
                                         let user = match sqlx::query!(
                                             r#"
                                             SELECT
@@ -188,6 +212,11 @@ async fn handle_ws_events(
                                 }
                                 WsClientAction::LeavePipelineEditor(action) => {
                                     if let Some(user_id) = user_id {
+                                        if pipelines.remove(&action.payload.pipeline_id).is_none() {
+                                            warn!("WsAction::LeavePipelineEditor: User isn't in the pipeline editor");
+                                            continue;
+                                        }
+
                                         match redis.hdel(
                                             to_pipeline_participant_redis_key(action.payload.pipeline_id),
                                             user_id.to_string(),
@@ -245,6 +274,11 @@ async fn handle_ws_events(
                                 }
                                 WsClientAction::UpdateCursorPosition(action) => {
                                     if let Some(user_id) = user_id {
+                                        if !pipelines.contains_key(&action.payload.pipeline_id) {
+                                            warn!("WsAction::UpdateCursorPosition: User isn't in the pipeline editor");
+                                            continue;
+                                        }
+
                                         let cursor_position = match serde_json::to_string(&action.payload.cursor_position) {
                                             Ok(cursor_position_value) => cursor_position_value,
                                             Err(error) => {
@@ -325,6 +359,10 @@ async fn handle_ws_events(
                             continue;
                         }
 
+                        if !event.action.pipeline_id().is_some_and(|pipeline_id| pipelines.contains_key(&pipeline_id)) {
+                            continue;
+                        }
+
                         match serde_json::to_string(&event.action) {
                             Ok(text) => {
                                 if let Err(error) = socket.send(Message::Text(text)).await {
@@ -0,0 +1,84 @@
+use axum::Json;
+use hyper::StatusCode;
+use serde::{Deserialize, Serialize};
+use sqlx::Acquire;
+use uuid::Uuid;
+
+use crate::{
+    app_state::DatabaseConnection,
+    extractors::session::Session,
+    lib::{
+        api_error::{api_error, ApiError},
+        invitation::{has_memberships, invitation_rejection, join_invitation, lock_invitation},
+    },
+};
+
+#[derive(Deserialize)]
+pub struct InvitationAccept {
+    token: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InvitationAccepted {
+    pub organization_id: Uuid,
+    pub team_id: Option<Uuid>,
+}
+
+/// Joins the organization or team the caller was invited to.
+/// Users belong to a single organization, joining another one requires having no role left in
+/// the current one.
+pub async fn accept(
+    DatabaseConnection(mut conn): DatabaseConnection,
+    Session(session): Session,
+    Json(params): Json<InvitationAccept>,
+) -> Result<Json<InvitationAccepted>, (StatusCode, Json<ApiError>)> {
+    let mut tx = conn.begin().await.map_err(api_error)?;
+
+    let Some(invitation) = lock_invitation(&mut tx, &params.token).await? else {
+        return Err((
+            StatusCode::NOT_FOUND,
+            Json(ApiError {
+                error: "Invitation not found".to_string(),
+            }),
+        ));
+    };
+
+    let user = sqlx::query!(
+        "SELECT email, organization_id FROM users WHERE id = $1 FOR UPDATE",
+        session.user_id
+    )
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(api_error)?;
+
+    if let Some(error) = invitation_rejection(&invitation, &user.email, chrono::Utc::now()) {
+        return Err((
+            StatusCode::UNPROCESSABLE_ENTITY,
+            Json(ApiError {
+                error: error.to_string(),
+            }),
+        ));
+    }
+
+    if user.organization_id != invitation.organization_id
+        && has_memberships(&mut tx, session.user_id).await?
+    {
+        return Err((
+            StatusCode::CONFLICT,
+            Json(ApiError {
+                error: "Leave the teams and the role of your current organization first"
+                    .to_string(),
+            }),
+        ));
+    }
+
+    join_invitation(&mut tx, &invitation, session.user_id).await?;
+
+    tx.commit().await.map_err(api_error)?;
+
+    Ok(Json(InvitationAccepted {
+        organization_id: invitation.organization_id,
+        team_id: invitation.team_id,
+    }))
+}
@@ -1,9 +1,16 @@
+pub mod api_tokens;
 pub mod auth;
 pub mod events;
+pub mod invitations;
 pub mod nodes;
+pub mod oauth;
+pub mod organizations;
 pub mod pipeline_execs;
 pub mod pipeline_node_connections;
 pub mod pipeline_nodes;
 pub mod pipeline_triggers;
 pub mod pipelines;
+pub mod service_accounts;
 pub mod teams;
+pub mod two_factor;
+pub mod webhooks;
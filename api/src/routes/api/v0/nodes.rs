@@ -2,7 +2,7 @@ use axum::Json;
 use hyper::StatusCode;
 use uuid::Uuid;
 
-use crate::{app_state::DatabaseConnection, utils::internal_error};
+use crate::{app_state::DatabaseConnection, extractors::session::Session, utils::internal_error};
 
 #[derive(Debug, serde::Serialize)]
 #[serde(rename_all = "camelCase")]
@@ -15,6 +15,7 @@ pub struct Node {
 
 pub async fn list(
     DatabaseConnection(mut conn): DatabaseConnection,
+    Session(_): Session,
 ) -> Result<Json<Vec<Node>>, StatusCode> {
     let nodes = sqlx::query!(
         r#"
@@ -0,0 +1,300 @@
+use axum::{
+    extract::Path,
+    http::{header, HeaderMap},
+    Json,
+};
+use db::dtos::KeyProviderType;
+use hyper::StatusCode;
+use redis::AsyncCommands;
+use serde::{Deserialize, Serialize};
+use sqlx::Acquire;
+use subtle::ConstantTimeEq;
+use tracing::warn;
+
+use crate::{
+    app_state::{DatabaseConnection, RedisConnection},
+    lib::{
+        api_error::{api_error, ApiError},
+        oauth::{
+            code_challenge, fetch_identity, find_cookie, generate_code_verifier,
+            oauth_state_cookie, to_redis_oauth_state_key, OAuthConfig, OAuthProvider, OAuthState,
+            OAUTH_STATE_COOKIE, OAUTH_STATE_TTL_SECS,
+        },
+        session::ClientInfo,
+        token::generate_token,
+    },
+    routes::api::v0::auth::{start_login, LoginOutcome},
+    utils::internal_error,
+};
+
+fn find_provider<'a>(
+    oauth_config: &'a OAuthConfig,
+    provider: &str,
+) -> Result<&'a OAuthProvider, (StatusCode, Json<ApiError>)> {
+    oauth_config.providers.get(provider).ok_or_else(|| {
+        (
+            StatusCode::NOT_FOUND,
+            Json(ApiError {
+                error: format!("The {provider} login isn't configured"),
+            }),
+        )
+    })
+}
+
+fn redis_error(error: impl std::fmt::Debug) -> (StatusCode, Json<ApiError>) {
+    (
+        internal_error(error),
+        Json(ApiError {
+            error: "Failed to store the login attempt".to_string(),
+        }),
+    )
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OAuthAuthorizeResponse {
+    pub authorization_url: String,
+}
+
+/// Starts a login with the provider, the client sends the user to the returned url. The state is
+/// also set in a cookie, the callback has to come from the same browser.
+pub async fn authorize(
+    Path(provider_name): Path<String>,
+    RedisConnection(mut redis): RedisConnection,
+    oauth_config: OAuthConfig,
+) -> Result<
+    (
+        [(header::HeaderName, String); 1],
+        Json<OAuthAuthorizeResponse>,
+    ),
+    (StatusCode, Json<ApiError>),
+> {
+    let provider = find_provider(&oauth_config, &provider_name)?;
+
+    let state = generate_token();
+    let code_verifier = generate_code_verifier();
+
+    let oauth_state = serde_json::to_string(&OAuthState {
+        provider: provider_name.clone(),
+        code_verifier: code_verifier.clone(),
+    })
+    .map_err(redis_error)?;
+
+    let _: () = redis
+        .set_ex(
+            to_redis_oauth_state_key(&state),
+            oauth_state,
+            OAUTH_STATE_TTL_SECS,
+        )
+        .await
+        .map_err(redis_error)?;
+
+    let authorization_url = provider
+        .authorization_url(
+            &oauth_config.redirect_uri(&provider_name),
+            &state,
+            &code_challenge(&code_verifier),
+        )
+        .map_err(|error| {
+            (
+                internal_error(error),
+                Json(ApiError {
+                    error: format!("Invalid authorization url of the {provider_name} login"),
+                }),
+            )
+        })?;
+
+    Ok((
+        [(
+            header::SET_COOKIE,
+            oauth_state_cookie(&state, oauth_config.is_secure()),
+        )],
+        Json(OAuthAuthorizeResponse { authorization_url }),
+    ))
+}
+
+#[derive(Deserialize)]
+pub struct OAuthCallbackRequest {
+    pub code: String,
+    pub state: String,
+}
+
+/// Completes a login with the provider. The provider identity logs into the user it's linked to,
+/// or gets linked to the user with the same email address when both the provider and the user
+/// verified it. An unverified account could have been registered by anyone with that address.
+pub async fn callback(
+    Path(provider_name): Path<String>,
+    DatabaseConnection(mut conn): DatabaseConnection,
+    RedisConnection(mut redis): RedisConnection,
+    oauth_config: OAuthConfig,
+    client: ClientInfo,
+    headers: HeaderMap,
+    Json(params): Json<OAuthCallbackRequest>,
+) -> Result<([(header::HeaderName, String); 1], Json<LoginOutcome>), (StatusCode, Json<ApiError>)> {
+    let provider = find_provider(&oauth_config, &provider_name)?;
+
+    let cookie_state = headers
+        .get(header::COOKIE)
+        .and_then(|cookie| cookie.to_str().ok())
+        .and_then(|cookie| find_cookie(cookie, OAUTH_STATE_COOKIE));
+
+    // Login CSRF, the attempt was started in another browser
+    if !cookie_state.is_some_and(|cookie_state| {
+        bool::from(cookie_state.as_bytes().ct_eq(params.state.as_bytes()))
+    }) {
+        return Err((
+            StatusCode::UNPROCESSABLE_ENTITY,
+            Json(ApiError {
+                error: "The login attempt is invalid or expired".to_string(),
+            }),
+        ));
+    }
+
+    // Each login attempt can only be completed once
+    let oauth_state: Option<String> = redis
+        .get_del(to_redis_oauth_state_key(&params.state))
+        .await
+        .map_err(redis_error)?;
+
+    let Some(oauth_state) = oauth_state
+        .and_then(|oauth_state| serde_json::from_str::<OAuthState>(&oauth_state).ok())
+        .filter(|oauth_state| oauth_state.provider == provider_name)
+    else {
+        return Err((
+            StatusCode::UNPROCESSABLE_ENTITY,
+            Json(ApiError {
+                error: "The login attempt is invalid or expired".to_string(),
+            }),
+        ));
+    };
+
+    let identity = fetch_identity(
+        &oauth_config.http,
+        provider,
+        &params.code,
+        &oauth_state.code_verifier,
+        &oauth_config.redirect_uri(&provider_name),
+    )
+    .await
+    .map_err(|error| {
+        warn!("The {provider_name} login failed: {error:?}");
+        (
+            StatusCode::BAD_GATEWAY,
+            Json(ApiError {
+                error: format!("The {provider_name} login failed"),
+            }),
+        )
+    })?;
+
+    let provider_key = format!("{provider_name}:{}", identity.subject);
+
+    let mut tx = conn.begin().await.map_err(api_error)?;
+
+    let linked_user = sqlx::query!(
+        r#"
+        SELECT
+            users.id, users.name
+        FROM
+            users
+        JOIN
+            keys ON keys.user_id = users.id
+        WHERE
+            keys.provider = $1 AND keys.provider_key = $2
+        "#,
+        provider.kind as KeyProviderType,
+        provider_key
+    )
+    .fetch_optional(&mut *tx)
+    .await
+    .map_err(api_error)?;
+
+    let (user_id, username) = match linked_user {
+        Some(user) => (user.id, user.name),
+        None => {
+            let Some(email) = identity.email.filter(|_| identity.email_verified) else {
+                return Err((
+                    StatusCode::FORBIDDEN,
+                    Json(ApiError {
+                        error: format!(
+                            "{provider_name} didn't confirm the email address of the account"
+                        ),
+                    }),
+                ));
+            };
+
+            let Some(user) = sqlx::query!(
+                r#"
+                SELECT
+                    id, name, email_verified
+                FROM
+                    users
+                WHERE
+                    email = $1
+                FOR UPDATE
+                "#,
+                email
+            )
+            .fetch_optional(&mut *tx)
+            .await
+            .map_err(api_error)?
+            else {
+                return Err((
+                    StatusCode::NOT_FOUND,
+                    Json(ApiError {
+                        error: format!("No account uses {email}, register first"),
+                    }),
+                ));
+            };
+
+            if !user.email_verified {
+                return Err((
+                    StatusCode::CONFLICT,
+                    Json(ApiError {
+                        error: format!(
+                            "The account using {email} didn't verify it, verify it before logging in with {provider_name}"
+                        ),
+                    }),
+                ));
+            }
+
+            sqlx::query!(
+                r#"
+                INSERT INTO
+                    keys (user_id, provider, provider_key)
+                VALUES
+                    ($1, $2, $3)
+                "#,
+                user.id,
+                provider.kind as KeyProviderType,
+                provider_key
+            )
+            .execute(&mut *tx)
+            .await
+            .map_err(api_error)?;
+
+            (user.id, user.name)
+        }
+    };
+
+    tx.commit().await.map_err(api_error)?;
+
+    start_login(&mut conn, redis, user_id, &username, client)
+        .await
+        .map(|outcome| {
+            (
+                [(
+                    header::SET_COOKIE,
+                    oauth_state_cookie("", oauth_config.is_secure()),
+                )],
+                Json(outcome),
+            )
+        })
+        .map_err(|status| {
+            (
+                status,
+                Json(ApiError {
+                    error: "Failed to create the session".to_string(),
+                }),
+            )
+        })
+}
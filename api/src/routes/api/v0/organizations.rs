@@ -0,0 +1,773 @@
+use axum::{extract::Path, Json};
+use db::dtos::MemberRole;
+use hyper::StatusCode;
+use serde::{Deserialize, Serialize};
+use sqlx::{Acquire, PgConnection};
+use tracing::error;
+use uuid::Uuid;
+
+use crate::{
+    app_state::{AppMailer, AuthConfig, DatabaseConnection, RedisConnection},
+    extractors::session::Session,
+    lib::{
+        api_error::{api_error, ApiError},
+        authorization::{authorize_organization, authorize_team, check_role_change, AccessError},
+        invitation::{has_memberships, INVITATION_TTL},
+        mailer::Mail,
+        session::invalidate_user_sessions,
+        token::{generate_token, hash_token},
+    },
+};
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OrganizationRoleAssign {
+    user_id: Uuid,
+    role: MemberRole,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OrganizationMember {
+    pub user_id: Uuid,
+    pub organization_id: Uuid,
+    pub role: Option<MemberRole>,
+}
+
+/// Locks the user's row and returns their organization role, `None` when they aren't part of the
+/// organization.
+async fn lock_organization_member(
+    db: &mut PgConnection,
+    organization_id: Uuid,
+    user_id: Uuid,
+) -> Result<Option<Option<MemberRole>>, (StatusCode, Json<ApiError>)> {
+    sqlx::query!(
+        r#"
+        SELECT
+            organization_role AS "role: MemberRole"
+        FROM
+            users
+        WHERE
+            id = $1 AND organization_id = $2
+        FOR UPDATE
+        "#,
+        user_id,
+        organization_id
+    )
+    .fetch_optional(&mut *db)
+    .await
+    .map(|member| member.map(|member| member.role))
+    .map_err(api_error)
+}
+
+/// Highest role of the user among their teams.
+async fn highest_team_role(
+    db: &mut PgConnection,
+    user_id: Uuid,
+) -> Result<Option<MemberRole>, (StatusCode, Json<ApiError>)> {
+    sqlx::query!(
+        r#"
+        SELECT
+            MAX(role) AS "role: MemberRole"
+        FROM
+            user_teams
+        WHERE
+            user_id = $1
+        "#,
+        user_id
+    )
+    .fetch_one(&mut *db)
+    .await
+    .map(|member| member.role)
+    .map_err(api_error)
+}
+
+/// An organization can't be left without an owner.
+async fn check_remaining_owner(
+    db: &mut PgConnection,
+    organization_id: Uuid,
+    user_id: Uuid,
+) -> Result<(), (StatusCode, Json<ApiError>)> {
+    let owners = sqlx::query!(
+        r#"
+        SELECT
+            COUNT(*) AS "count!"
+        FROM
+            users
+        WHERE
+            organization_id = $1 AND organization_role = 'owner' AND id <> $2
+        "#,
+        organization_id,
+        user_id
+    )
+    .fetch_one(&mut *db)
+    .await
+    .map_err(api_error)?;
+
+    if owners.count == 0 {
+        return Err((
+            StatusCode::CONFLICT,
+            Json(ApiError {
+                error: "The organization must keep at least one owner".to_string(),
+            }),
+        ));
+    }
+
+    Ok(())
+}
+
+/// Sets the organization role of one of its users, which applies to every team of the organization.
+pub async fn assign_role(
+    DatabaseConnection(mut conn): DatabaseConnection,
+    Session(session): Session,
+    Path(id): Path<Uuid>,
+    Json(params): Json<OrganizationRoleAssign>,
+) -> Result<Json<OrganizationMember>, (StatusCode, Json<ApiError>)> {
+    let caller_role =
+        authorize_organization(&mut conn, session.user_id, id, MemberRole::Admin).await?;
+
+    let mut tx = conn.begin().await.map_err(api_error)?;
+
+    let Some(current_role) = lock_organization_member(&mut tx, id, params.user_id).await? else {
+        return Err((
+            StatusCode::UNPROCESSABLE_ENTITY,
+            Json(ApiError {
+                error: "The user isn't part of the organization".to_string(),
+            }),
+        ));
+    };
+
+    check_role_change(caller_role, current_role, Some(params.role))?;
+
+    if current_role == Some(MemberRole::Owner) && params.role != MemberRole::Owner {
+        check_remaining_owner(&mut tx, id, params.user_id).await?;
+    }
+
+    sqlx::query!(
+        r#"
+        UPDATE
+            users
+        SET
+            organization_role = $1
+        WHERE
+            id = $2
+        "#,
+        params.role as MemberRole,
+        params.user_id
+    )
+    .execute(&mut *tx)
+    .await
+    .map_err(api_error)?;
+
+    tx.commit().await.map_err(api_error)?;
+
+    Ok(Json(OrganizationMember {
+        user_id: params.user_id,
+        organization_id: id,
+        role: Some(params.role),
+    }))
+}
+
+/// Clears the organization role of one of its users, their team roles still apply.
+pub async fn revoke_role(
+    DatabaseConnection(mut conn): DatabaseConnection,
+    Session(session): Session,
+    Path((id, user_id)): Path<(Uuid, Uuid)>,
+) -> Result<StatusCode, (StatusCode, Json<ApiError>)> {
+    let caller_role =
+        authorize_organization(&mut conn, session.user_id, id, MemberRole::Admin).await?;
+
+    let mut tx = conn.begin().await.map_err(api_error)?;
+
+    let Some(current_role) = lock_organization_member(&mut tx, id, user_id).await? else {
+        return Err((
+            StatusCode::NOT_FOUND,
+            Json(ApiError {
+                error: "The user isn't part of the organization".to_string(),
+            }),
+        ));
+    };
+
+    check_role_change(caller_role, current_role, None)?;
+
+    if current_role == Some(MemberRole::Owner) {
+        check_remaining_owner(&mut tx, id, user_id).await?;
+    }
+
+    sqlx::query!(
+        r#"
+        UPDATE
+            users
+        SET
+            organization_role = NULL
+        WHERE
+            id = $1
+        "#,
+        user_id
+    )
+    .execute(&mut *tx)
+    .await
+    .map_err(api_error)?;
+
+    tx.commit().await.map_err(api_error)?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[derive(Deserialize)]
+pub struct OrganizationUpsert {
+    name: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Organization {
+    pub id: Uuid,
+    pub name: String,
+}
+
+/// Trimmed name of an organization or a team, which can't be blank.
+pub fn check_name(name: &str) -> Result<&str, (StatusCode, Json<ApiError>)> {
+    let name = name.trim();
+
+    if name.is_empty() {
+        return Err((
+            StatusCode::UNPROCESSABLE_ENTITY,
+            Json(ApiError {
+                error: "The name can't be empty".to_string(),
+            }),
+        ));
+    }
+
+    Ok(name)
+}
+
+/// Creates an organization owned by the caller, who moves into it.
+/// Users belong to a single organization, so the caller can't have roles left in their current one.
+pub async fn create(
+    DatabaseConnection(mut conn): DatabaseConnection,
+    Session(session): Session,
+    Json(params): Json<OrganizationUpsert>,
+) -> Result<(StatusCode, Json<Organization>), (StatusCode, Json<ApiError>)> {
+    let name = check_name(&params.name)?;
+
+    let mut tx = conn.begin().await.map_err(api_error)?;
+
+    if has_memberships(&mut tx, session.user_id).await? {
+        return Err((
+            StatusCode::CONFLICT,
+            Json(ApiError {
+                error: "Leave the teams and the role of your current organization first"
+                    .to_string(),
+            }),
+        ));
+    }
+
+    let organization = sqlx::query_as!(
+        Organization,
+        r#"
+        INSERT INTO
+            organizations (name)
+        VALUES
+            ($1)
+        RETURNING
+            id, name
+        "#,
+        name
+    )
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(api_error)?;
+
+    sqlx::query!(
+        r#"
+        UPDATE
+            users
+        SET
+            organization_id = $1, organization_role = 'owner'
+        WHERE
+            id = $2
+        "#,
+        organization.id,
+        session.user_id
+    )
+    .execute(&mut *tx)
+    .await
+    .map_err(api_error)?;
+
+    tx.commit().await.map_err(api_error)?;
+
+    Ok((StatusCode::CREATED, Json(organization)))
+}
+
+pub async fn update(
+    DatabaseConnection(mut conn): DatabaseConnection,
+    Session(session): Session,
+    Path(id): Path<Uuid>,
+    Json(params): Json<OrganizationUpsert>,
+) -> Result<Json<Organization>, (StatusCode, Json<ApiError>)> {
+    authorize_organization(&mut conn, session.user_id, id, MemberRole::Admin).await?;
+
+    let name = check_name(&params.name)?;
+
+    let organization = sqlx::query_as!(
+        Organization,
+        r#"
+        UPDATE
+            organizations
+        SET
+            name = $1
+        WHERE
+            id = $2
+        RETURNING
+            id, name
+        "#,
+        name,
+        id
+    )
+    .fetch_one(&mut *conn)
+    .await
+    .map_err(api_error)?;
+
+    Ok(Json(organization))
+}
+
+/// Removes the user from every team of the organization along with their organization role.
+/// They stay attached to the organization without access until they join another one.
+pub async fn remove_member(
+    DatabaseConnection(mut conn): DatabaseConnection,
+    Session(session): Session,
+    Path((id, user_id)): Path<(Uuid, Uuid)>,
+) -> Result<StatusCode, (StatusCode, Json<ApiError>)> {
+    let caller_role =
+        authorize_organization(&mut conn, session.user_id, id, MemberRole::Admin).await?;
+
+    let mut tx = conn.begin().await.map_err(api_error)?;
+
+    let Some(current_role) = lock_organization_member(&mut tx, id, user_id).await? else {
+        return Err((
+            StatusCode::NOT_FOUND,
+            Json(ApiError {
+                error: "The user isn't part of the organization".to_string(),
+            }),
+        ));
+    };
+
+    let team_role = highest_team_role(&mut tx, user_id).await?;
+
+    check_role_change(caller_role, current_role.max(team_role), None)?;
+
+    if current_role == Some(MemberRole::Owner) {
+        check_remaining_owner(&mut tx, id, user_id).await?;
+    }
+
+    sqlx::query!(
+        r#"
+        DELETE FROM
+            user_teams
+        WHERE
+            user_id = $1
+        "#,
+        user_id
+    )
+    .execute(&mut *tx)
+    .await
+    .map_err(api_error)?;
+
+    sqlx::query!(
+        r#"
+        UPDATE
+            users
+        SET
+            organization_role = NULL
+        WHERE
+            id = $1
+        "#,
+        user_id
+    )
+    .execute(&mut *tx)
+    .await
+    .map_err(api_error)?;
+
+    tx.commit().await.map_err(api_error)?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Logs a user of the organization out everywhere, their roles must not be above the caller's.
+pub async fn revoke_member_sessions(
+    DatabaseConnection(mut conn): DatabaseConnection,
+    RedisConnection(redis): RedisConnection,
+    Session(session): Session,
+    Path((id, user_id)): Path<(Uuid, Uuid)>,
+) -> Result<StatusCode, (StatusCode, Json<ApiError>)> {
+    let caller_role =
+        authorize_organization(&mut conn, session.user_id, id, MemberRole::Admin).await?;
+
+    let Some(current_role) = lock_organization_member(&mut conn, id, user_id).await? else {
+        return Err((
+            StatusCode::NOT_FOUND,
+            Json(ApiError {
+                error: "The user isn't part of the organization".to_string(),
+            }),
+        ));
+    };
+
+    let team_role = highest_team_role(&mut conn, user_id).await?;
+
+    check_role_change(caller_role, current_role.max(team_role), None)?;
+
+    invalidate_user_sessions(redis, user_id, None)
+        .await
+        .map_err(|status| {
+            (
+                status,
+                Json(ApiError {
+                    error: "Failed to revoke the sessions".to_string(),
+                }),
+            )
+        })?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct TwoFactorPolicy {
+    pub required: bool,
+}
+
+/// Requiring two-factor authentication would lock the caller out if they hadn't enabled it.
+pub async fn check_two_factor_policy_change(
+    db: &mut PgConnection,
+    user_id: Uuid,
+    policy: &TwoFactorPolicy,
+) -> Result<(), (StatusCode, Json<ApiError>)> {
+    if !policy.required {
+        return Ok(());
+    }
+
+    let enabled = sqlx::query_scalar!(
+        r#"
+        SELECT
+            totp_enabled_at IS NOT NULL AS "enabled!"
+        FROM
+            users
+        WHERE
+            id = $1
+        "#,
+        user_id
+    )
+    .fetch_one(&mut *db)
+    .await
+    .map_err(api_error)?;
+
+    if !enabled {
+        return Err((
+            StatusCode::CONFLICT,
+            Json(ApiError {
+                error: "Enable two-factor authentication before requiring it".to_string(),
+            }),
+        ));
+    }
+
+    Ok(())
+}
+
+/// Requires every member of the organization, and so of its teams, to enable two-factor
+/// authentication before reaching its resources.
+pub async fn update_two_factor_policy(
+    DatabaseConnection(mut conn): DatabaseConnection,
+    Session(session): Session,
+    Path(id): Path<Uuid>,
+    Json(params): Json<TwoFactorPolicy>,
+) -> Result<Json<TwoFactorPolicy>, (StatusCode, Json<ApiError>)> {
+    authorize_organization(&mut conn, session.user_id, id, MemberRole::Admin).await?;
+    check_two_factor_policy_change(&mut conn, session.user_id, &params).await?;
+
+    let required = sqlx::query_scalar!(
+        r#"
+        UPDATE
+            organizations
+        SET
+            require_two_factor = $1
+        WHERE
+            id = $2
+        RETURNING
+            require_two_factor
+        "#,
+        params.required,
+        id
+    )
+    .fetch_one(&mut *conn)
+    .await
+    .map_err(api_error)?;
+
+    Ok(Json(TwoFactorPolicy { required }))
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OwnershipTransfer {
+    user_id: Uuid,
+}
+
+/// Makes another user of the organization its owner, the caller stays on as an admin.
+pub async fn transfer_ownership(
+    DatabaseConnection(mut conn): DatabaseConnection,
+    Session(session): Session,
+    Path(id): Path<Uuid>,
+    Json(params): Json<OwnershipTransfer>,
+) -> Result<Json<OrganizationMember>, (StatusCode, Json<ApiError>)> {
+    authorize_organization(&mut conn, session.user_id, id, MemberRole::Owner).await?;
+
+    if params.user_id == session.user_id {
+        return Err((
+            StatusCode::UNPROCESSABLE_ENTITY,
+            Json(ApiError {
+                error: "The organization is already owned by the caller".to_string(),
+            }),
+        ));
+    }
+
+    let mut tx = conn.begin().await.map_err(api_error)?;
+
+    if lock_organization_member(&mut tx, id, session.user_id).await?
+        != Some(Some(MemberRole::Owner))
+    {
+        return Err(AccessError::Forbidden(MemberRole::Owner).into());
+    }
+
+    if lock_organization_member(&mut tx, id, params.user_id)
+        .await?
+        .is_none()
+    {
+        return Err((
+            StatusCode::UNPROCESSABLE_ENTITY,
+            Json(ApiError {
+                error: "The user isn't part of the organization".to_string(),
+            }),
+        ));
+    }
+
+    sqlx::query!(
+        r#"
+        UPDATE
+            users
+        SET
+            organization_role = CASE WHEN id = $1 THEN 'owner'::member_role ELSE 'admin'::member_role END
+        WHERE
+            id IN ($1, $2)
+        "#,
+        params.user_id,
+        session.user_id
+    )
+    .execute(&mut *tx)
+    .await
+    .map_err(api_error)?;
+
+    tx.commit().await.map_err(api_error)?;
+
+    Ok(Json(OrganizationMember {
+        user_id: params.user_id,
+        organization_id: id,
+        role: Some(MemberRole::Owner),
+    }))
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InvitationCreate {
+    email: String,
+    /// Team the user is invited to, the organization itself when omitted.
+    team_id: Option<Uuid>,
+    role: MemberRole,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InvitationItem {
+    pub id: Uuid,
+    pub organization_id: Uuid,
+    pub team_id: Option<Uuid>,
+    pub email: String,
+    pub role: MemberRole,
+    pub expires_at: chrono::DateTime<chrono::Utc>,
+    pub accepted_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InvitationCreated {
+    #[serde(flatten)]
+    pub invitation: InvitationItem,
+    /// Only returned once, the invitation is accepted with it.
+    pub token: String,
+}
+
+/// Invites a user by email to the organization, or to one of its teams.
+pub async fn create_invitation(
+    DatabaseConnection(mut conn): DatabaseConnection,
+    Session(session): Session,
+    AppMailer(mailer): AppMailer,
+    auth_config: AuthConfig,
+    Path(id): Path<Uuid>,
+    Json(params): Json<InvitationCreate>,
+) -> Result<(StatusCode, Json<InvitationCreated>), (StatusCode, Json<ApiError>)> {
+    let caller_role = match params.team_id {
+        Some(team_id) => {
+            let team = sqlx::query!("SELECT organization_id FROM teams WHERE id = $1", team_id)
+                .fetch_optional(&mut *conn)
+                .await
+                .map_err(api_error)?;
+
+            if team.map(|team| team.organization_id) != Some(id) {
+                return Err((
+                    StatusCode::UNPROCESSABLE_ENTITY,
+                    Json(ApiError {
+                        error: "The team isn't part of the organization".to_string(),
+                    }),
+                ));
+            }
+
+            authorize_team(&mut conn, session.user_id, team_id, MemberRole::Admin).await?
+        }
+        None => authorize_organization(&mut conn, session.user_id, id, MemberRole::Admin).await?,
+    };
+
+    check_role_change(caller_role, None, Some(params.role))?;
+
+    let email = params.email.trim();
+
+    if !email.contains('@') {
+        return Err((
+            StatusCode::UNPROCESSABLE_ENTITY,
+            Json(ApiError {
+                error: "Invalid email address".to_string(),
+            }),
+        ));
+    }
+
+    let token = generate_token();
+
+    let invitation = sqlx::query_as!(
+        InvitationItem,
+        r#"
+        INSERT INTO
+            invitations (organization_id, team_id, email, role, token_hash, invited_by_user_id, expires_at)
+        VALUES
+            ($1, $2, $3, $4, $5, $6, $7)
+        RETURNING
+            id,
+            organization_id,
+            team_id,
+            email,
+            role AS "role: MemberRole",
+            expires_at AS "expires_at: chrono::DateTime<chrono::Utc>",
+            accepted_at AS "accepted_at: chrono::DateTime<chrono::Utc>"
+        "#,
+        id,
+        params.team_id,
+        email,
+        params.role as MemberRole,
+        hash_token(&token),
+        session.user_id,
+        (chrono::Utc::now() + INVITATION_TTL) as _
+    )
+    .fetch_one(&mut *conn)
+    .await
+    .map_err(api_error)?;
+
+    let mail = Mail {
+        to: invitation.email.clone(),
+        subject: format!("{} invited you to join their organization", session.username),
+        body: format!(
+            "Open {}/invitations/accept?token={token} to accept the invitation, the link expires in {} days.",
+            auth_config.app_url,
+            INVITATION_TTL.num_days()
+        ),
+    };
+
+    // The invitation can still be shared with its token
+    if let Err(error) = mailer.send(mail).await {
+        error!(
+            "Failed to send the mail of invitation {}: {error:?}",
+            invitation.id
+        );
+    }
+
+    Ok((
+        StatusCode::CREATED,
+        Json(InvitationCreated { invitation, token }),
+    ))
+}
+
+/// Pending invitations of the organization, most recent first.
+pub async fn list_invitations(
+    DatabaseConnection(mut conn): DatabaseConnection,
+    Session(session): Session,
+    Path(id): Path<Uuid>,
+) -> Result<Json<Vec<InvitationItem>>, (StatusCode, Json<ApiError>)> {
+    authorize_organization(&mut conn, session.user_id, id, MemberRole::Admin).await?;
+
+    let invitations = sqlx::query_as!(
+        InvitationItem,
+        r#"
+        SELECT
+            id,
+            organization_id,
+            team_id,
+            email,
+            role AS "role: MemberRole",
+            expires_at AS "expires_at: chrono::DateTime<chrono::Utc>",
+            accepted_at AS "accepted_at: chrono::DateTime<chrono::Utc>"
+        FROM
+            invitations
+        WHERE
+            organization_id = $1 AND accepted_at IS NULL AND expires_at > CURRENT_TIMESTAMP
+        ORDER BY
+            created_at DESC
+        "#,
+        id
+    )
+    .fetch_all(&mut *conn)
+    .await
+    .map_err(api_error)?;
+
+    Ok(Json(invitations))
+}
+
+/// Withdraws a pending invitation, its token can no longer be accepted.
+pub async fn delete_invitation(
+    DatabaseConnection(mut conn): DatabaseConnection,
+    Session(session): Session,
+    Path((id, invitation_id)): Path<(Uuid, Uuid)>,
+) -> Result<StatusCode, (StatusCode, Json<ApiError>)> {
+    let caller_role =
+        authorize_organization(&mut conn, session.user_id, id, MemberRole::Admin).await?;
+
+    let deleted = sqlx::query!(
+        r#"
+        DELETE FROM
+            invitations
+        WHERE
+            id = $1 AND organization_id = $2 AND accepted_at IS NULL AND role <= $3
+        "#,
+        invitation_id,
+        id,
+        caller_role as MemberRole
+    )
+    .execute(&mut *conn)
+    .await
+    .map_err(api_error)?;
+
+    if deleted.rows_affected() == 0 {
+        return Err((
+            StatusCode::NOT_FOUND,
+            Json(ApiError {
+                error: "Invitation not found".to_string(),
+            }),
+        ));
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
@@ -1,20 +1,858 @@
-use axum::response::Sse;
-use axum::{extract::Path, response::sse::Event};
-use db::dtos::{ExecStatus, PipelineExecEvent};
+use axum::response::{IntoResponse, Response, Sse};
+use axum::{extract::Path, response::sse::Event, Json};
+use axum_extra::extract::Query;
+use db::dtos::{ExecStatus, MemberRole, PipelineExecEvent, PipelineExecPayloadParams};
 use futures::stream::Stream;
 use futures::stream::StreamExt;
+use hyper::StatusCode;
+use serde::{Deserialize, Serialize};
 use serde_json::json;
-use std::error::Error;
+use sqlx::PgConnection;
+use std::{collections::HashMap, error::Error};
 use tracing::{debug, error, info};
 use uuid::Uuid;
 
-use crate::app_state::ExecEventsConsumer;
+use crate::{
+    app_state::{DatabaseConnection, ExecEventsConsumer, JetStream},
+    extractors::session::Session,
+    lib::{
+        api_error::{api_error, ApiError},
+        authorization::{authorize_pipeline, authorize_pipeline_exec},
+        pipeline_exec::{
+            find_manual_trigger, insert_pipeline_exec, publish_pipeline_exec, PipelineExecOrigin,
+        },
+        pipeline_exec_diff::{diff_node_execs, DiffedNodeExec, PipelineNodeExecDiff},
+        pipeline_params::{
+            check_pipeline_exec_params, fetch_node_configs, redact_pipeline_exec_params,
+            restore_exec_params,
+        },
+        rate_limit::RateLimiter,
+    },
+    routes::api::v0::pipelines::PipelineParamsRejection,
+    utils::internal_error,
+};
+
+const DEFAULT_PAGE_SIZE: i64 = 20;
+const MAX_PAGE_SIZE: i64 = 100;
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PipelineExecListQuery {
+    /// Only executions in one of these statuses, all of them when empty.
+    #[serde(default)]
+    pub status: Vec<ExecStatus>,
+    /// Only executions created at or after this instant.
+    pub from: Option<chrono::DateTime<chrono::Utc>>,
+    /// Only executions created before this instant.
+    pub to: Option<chrono::DateTime<chrono::Utc>>,
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PipelineExecListItem {
+    id: Uuid,
+    pipeline_trigger_id: Option<Uuid>,
+    status: ExecStatus,
+    created_at: Option<chrono::DateTime<chrono::Utc>>,
+    started_at: Option<chrono::DateTime<chrono::Utc>>,
+    finished_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PipelineExecList {
+    items: Vec<PipelineExecListItem>,
+    /// Number of executions matching the filters, regardless of the page.
+    total: i64,
+    limit: i64,
+    offset: i64,
+}
+
+/// Executions of a pipeline, most recent first.
+pub async fn list(
+    DatabaseConnection(mut conn): DatabaseConnection,
+    Session(session): Session,
+    Path(id): Path<Uuid>,
+    Query(query): Query<PipelineExecListQuery>,
+) -> Result<Json<PipelineExecList>, StatusCode> {
+    authorize_pipeline(&mut conn, session.user_id, id, MemberRole::Viewer).await?;
+
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_PAGE_SIZE)
+        .clamp(1, MAX_PAGE_SIZE);
+    let offset = query.offset.unwrap_or(0).max(0);
+    let statuses = query
+        .status
+        .iter()
+        .map(|status| status.as_str().to_string())
+        .collect::<Vec<_>>();
+
+    let total = sqlx::query!(
+        r#"
+        SELECT
+            COUNT(*) AS "count!"
+        FROM
+            pipeline_execs
+        WHERE
+            pipeline_id = $1
+            AND (CARDINALITY($2::TEXT[]) = 0 OR status::TEXT = ANY($2))
+            AND ($3::TIMESTAMPTZ IS NULL OR created_at >= $3)
+            AND ($4::TIMESTAMPTZ IS NULL OR created_at < $4)
+        "#,
+        id,
+        &statuses,
+        query.from as _,
+        query.to as _
+    )
+    .fetch_one(&mut *conn)
+    .await
+    .map_err(internal_error)?
+    .count;
+
+    let items = sqlx::query_as!(
+        PipelineExecListItem,
+        r#"
+        SELECT
+            id,
+            pipeline_trigger_id,
+            status AS "status: ExecStatus",
+            created_at AS "created_at: chrono::DateTime<chrono::Utc>",
+            started_at AS "started_at: chrono::DateTime<chrono::Utc>",
+            finished_at AS "finished_at: chrono::DateTime<chrono::Utc>"
+        FROM
+            pipeline_execs
+        WHERE
+            pipeline_id = $1
+            AND (CARDINALITY($2::TEXT[]) = 0 OR status::TEXT = ANY($2))
+            AND ($3::TIMESTAMPTZ IS NULL OR created_at >= $3)
+            AND ($4::TIMESTAMPTZ IS NULL OR created_at < $4)
+        ORDER BY
+            created_at DESC, id
+        LIMIT $5
+        OFFSET $6
+        "#,
+        id,
+        &statuses,
+        query.from as _,
+        query.to as _,
+        limit,
+        offset
+    )
+    .fetch_all(&mut *conn)
+    .await
+    .map_err(internal_error)?;
+
+    Ok(Json(PipelineExecList {
+        items,
+        total,
+        limit,
+        offset,
+    }))
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PipelineNodeExecDetails {
+    id: Uuid,
+    pipeline_node_id: Uuid,
+    node_version: Option<String>,
+    status: ExecStatus,
+    /// Params the node was dispatched with, secret inputs redacted.
+    params: Option<serde_json::Value>,
+    result: Option<serde_json::Value>,
+    error: Option<String>,
+    created_at: Option<chrono::DateTime<chrono::Utc>>,
+    started_at: Option<chrono::DateTime<chrono::Utc>>,
+    finished_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PipelineExecDetails {
+    id: Uuid,
+    pipeline_id: Uuid,
+    pipeline_trigger_id: Option<Uuid>,
+    parent_pipeline_exec_id: Option<Uuid>,
+    /// Failed execution this one retries, reusing its successful node results.
+    retry_of_pipeline_exec_id: Option<Uuid>,
+    /// Execution this one replays with the same trigger params.
+    replay_of_pipeline_exec_id: Option<Uuid>,
+    /// Node versions the replay is pinned to, by pipeline node.
+    pinned_node_versions: Option<serde_json::Value>,
+    status: ExecStatus,
+    /// Trigger params the execution was started with, keyed by pipeline node, secret inputs redacted.
+    params: serde_json::Value,
+    /// Output document built from the named outputs of the pipeline once the execution finished.
+    output: Option<serde_json::Value>,
+    created_at: Option<chrono::DateTime<chrono::Utc>>,
+    started_at: Option<chrono::DateTime<chrono::Utc>>,
+    finished_at: Option<chrono::DateTime<chrono::Utc>>,
+    /// Every node execution of the run, in the order they were scheduled.
+    node_execs: Vec<PipelineNodeExecDetails>,
+}
+
+pub async fn details(
+    DatabaseConnection(mut conn): DatabaseConnection,
+    Session(session): Session,
+    Path(id): Path<Uuid>,
+) -> Result<Json<PipelineExecDetails>, StatusCode> {
+    authorize_pipeline_exec(&mut conn, session.user_id, id, MemberRole::Viewer).await?;
+
+    let pipeline_exec = sqlx::query!(
+        r#"
+        SELECT
+            id,
+            pipeline_id,
+            pipeline_trigger_id,
+            parent_pipeline_exec_id,
+            retry_of_pipeline_exec_id,
+            replay_of_pipeline_exec_id,
+            pinned_node_versions,
+            status AS "status: ExecStatus",
+            params,
+            output,
+            created_at AS "created_at: chrono::DateTime<chrono::Utc>",
+            started_at AS "started_at: chrono::DateTime<chrono::Utc>",
+            finished_at AS "finished_at: chrono::DateTime<chrono::Utc>"
+        FROM
+            pipeline_execs
+        WHERE
+            id = $1
+        "#,
+        id
+    )
+    .fetch_optional(&mut *conn)
+    .await
+    .map_err(internal_error)?
+    .ok_or(StatusCode::NOT_FOUND)?;
+
+    let node_execs = sqlx::query_as!(
+        PipelineNodeExecDetails,
+        r#"
+        SELECT
+            id,
+            pipeline_node_id,
+            node_version,
+            status AS "status: ExecStatus",
+            params,
+            result,
+            error,
+            created_at AS "created_at: chrono::DateTime<chrono::Utc>",
+            started_at AS "started_at: chrono::DateTime<chrono::Utc>",
+            finished_at AS "finished_at: chrono::DateTime<chrono::Utc>"
+        FROM
+            pipeline_node_execs
+        WHERE
+            pipeline_exec_id = $1
+        ORDER BY
+            created_at, started_at NULLS LAST, id
+        "#,
+        id
+    )
+    .fetch_all(&mut *conn)
+    .await
+    .map_err(internal_error)?;
+
+    let params =
+        redact_pipeline_exec_params(&mut conn, pipeline_exec.pipeline_id, &pipeline_exec.params)
+            .await
+            .map_err(internal_error)?;
+
+    Ok(Json(PipelineExecDetails {
+        id: pipeline_exec.id,
+        pipeline_id: pipeline_exec.pipeline_id,
+        pipeline_trigger_id: pipeline_exec.pipeline_trigger_id,
+        parent_pipeline_exec_id: pipeline_exec.parent_pipeline_exec_id,
+        retry_of_pipeline_exec_id: pipeline_exec.retry_of_pipeline_exec_id,
+        replay_of_pipeline_exec_id: pipeline_exec.replay_of_pipeline_exec_id,
+        pinned_node_versions: pipeline_exec.pinned_node_versions,
+        status: pipeline_exec.status,
+        params,
+        output: pipeline_exec.output,
+        created_at: pipeline_exec.created_at,
+        started_at: pipeline_exec.started_at,
+        finished_at: pipeline_exec.finished_at,
+        node_execs,
+    }))
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PipelineExecStartResponse {
+    pipeline_exec_id: Uuid,
+}
+
+/// Checks a new execution started from a past one goes through the same checks as a manual
+/// trigger: the trigger must still allow manual execution and the params must satisfy it.
+/// Returns the trigger to start the execution with, or the rejected params.
+async fn check_manual_restart(
+    conn: &mut PgConnection,
+    pipeline_id: Uuid,
+    pipeline_trigger_id: Option<Uuid>,
+    params: &PipelineExecPayloadParams,
+) -> Result<Result<Uuid, PipelineParamsRejection>, (StatusCode, Json<ApiError>)> {
+    let trigger_id = find_manual_trigger(conn, pipeline_id, pipeline_trigger_id)
+        .await
+        .map_err(|status| {
+            let error = match status {
+                StatusCode::FORBIDDEN => "The pipeline trigger doesn't allow manual execution",
+                StatusCode::NOT_FOUND => "The pipeline trigger no longer exists",
+                _ => "Failed to find the pipeline trigger",
+            };
+
+            (
+                status,
+                Json(ApiError {
+                    error: error.to_string(),
+                }),
+            )
+        })?;
+
+    let errors = check_pipeline_exec_params(conn, pipeline_id, Some(trigger_id), params)
+        .await
+        .map_err(|error| {
+            (
+                internal_error(&error),
+                Json(ApiError {
+                    error: error.to_string(),
+                }),
+            )
+        })?;
+
+    if !errors.is_empty() {
+        return Ok(Err(PipelineParamsRejection { errors }));
+    }
+
+    Ok(Ok(trigger_id))
+}
+
+fn start_error(status: StatusCode) -> (StatusCode, Json<ApiError>) {
+    (
+        status,
+        Json(ApiError {
+            error: "Failed to start the pipeline exec".to_string(),
+        }),
+    )
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PipelineExecRetry {
+    /// Params overriding the ones of the retried execution, keyed by pipeline node. Secret params
+    /// aren't stored, they have to be supplied again.
+    #[serde(default)]
+    pub params: PipelineExecPayloadParams,
+}
+
+/// Starts a new execution of a failed one, with the same trigger and params.
+/// Nodes which completed are not executed again, the scheduler reuses their results.
+pub async fn retry(
+    DatabaseConnection(mut conn): DatabaseConnection,
+    JetStream(jetstream): JetStream,
+    mut rate_limiter: RateLimiter,
+    Session(session): Session,
+    Path(id): Path<Uuid>,
+    retry: Option<Json<PipelineExecRetry>>,
+) -> Result<Response, (StatusCode, Json<ApiError>)> {
+    let Json(retry) = retry.unwrap_or_default();
+
+    authorize_pipeline_exec(&mut conn, session.user_id, id, MemberRole::Runner).await?;
+
+    let pipeline_exec = sqlx::query!(
+        r#"
+        SELECT
+            pipeline_id,
+            pipeline_trigger_id,
+            status AS "status: ExecStatus",
+            params
+        FROM
+            pipeline_execs
+        WHERE
+            id = $1
+        "#,
+        id
+    )
+    .fetch_optional(&mut *conn)
+    .await
+    .map_err(api_error)?
+    .ok_or_else(|| {
+        (
+            StatusCode::NOT_FOUND,
+            Json(ApiError {
+                error: "Pipeline exec not found".to_string(),
+            }),
+        )
+    })?;
+
+    if pipeline_exec.status != ExecStatus::Failed {
+        return Err((
+            StatusCode::CONFLICT,
+            Json(ApiError {
+                error: format!(
+                    "Only failed pipeline execs can be retried, this one is {}",
+                    pipeline_exec.status.as_str()
+                ),
+            }),
+        ));
+    }
+
+    let mut params = serde_json::from_value::<PipelineExecPayloadParams>(
+        pipeline_exec.params.clone(),
+    )
+    .map_err(|error| {
+        (
+            internal_error(&error),
+            Json(ApiError {
+                error: error.to_string(),
+            }),
+        )
+    })?;
+
+    let configs = fetch_node_configs(&mut conn, pipeline_exec.pipeline_id)
+        .await
+        .map_err(|error| {
+            (
+                internal_error(&error),
+                Json(ApiError {
+                    error: error.to_string(),
+                }),
+            )
+        })?;
+
+    let errors = restore_exec_params(&mut params, retry.params, &configs);
+
+    if !errors.is_empty() {
+        return Ok((
+            StatusCode::UNPROCESSABLE_ENTITY,
+            Json(PipelineParamsRejection { errors }),
+        )
+            .into_response());
+    }
+
+    let trigger_id = match check_manual_restart(
+        &mut conn,
+        pipeline_exec.pipeline_id,
+        pipeline_exec.pipeline_trigger_id,
+        &params,
+    )
+    .await?
+    {
+        Ok(trigger_id) => trigger_id,
+        Err(rejection) => {
+            return Ok((StatusCode::UNPROCESSABLE_ENTITY, Json(rejection)).into_response())
+        }
+    };
+
+    let retry_id = match insert_pipeline_exec(
+        &mut conn,
+        &mut rate_limiter,
+        pipeline_exec.pipeline_id,
+        Some(trigger_id),
+        &PipelineExecOrigin {
+            retry_of_pipeline_exec_id: Some(id),
+            ..Default::default()
+        },
+        &params,
+    )
+    .await
+    .map_err(start_error)?
+    {
+        Ok(retry_id) => retry_id,
+        Err(rate_limited) => return Ok(rate_limited.into_response()),
+    };
+
+    publish_pipeline_exec(
+        &jetstream,
+        pipeline_exec.pipeline_id,
+        retry_id,
+        Some(trigger_id),
+        params,
+    )
+    .await
+    .map_err(|error| {
+        (
+            internal_error(&error),
+            Json(ApiError {
+                error: error.to_string(),
+            }),
+        )
+    })?;
+
+    info!("Pipeline exec {retry_id} started as a retry of {id}");
+
+    Ok(Json(PipelineExecStartResponse {
+        pipeline_exec_id: retry_id,
+    })
+    .into_response())
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PipelineExecReplay {
+    /// Run the nodes with the versions they had in the replayed execution instead of the current ones.
+    #[serde(default)]
+    pub pin_node_versions: bool,
+    /// Params overriding the ones of the replayed execution, keyed by pipeline node. Secret params
+    /// aren't stored, they have to be supplied again.
+    #[serde(default)]
+    pub params: PipelineExecPayloadParams,
+}
+
+/// Starts a new execution with the trigger and params of a finished one.
+pub async fn replay(
+    DatabaseConnection(mut conn): DatabaseConnection,
+    JetStream(jetstream): JetStream,
+    mut rate_limiter: RateLimiter,
+    Session(session): Session,
+    Path(id): Path<Uuid>,
+    Json(replay): Json<PipelineExecReplay>,
+) -> Result<Response, (StatusCode, Json<ApiError>)> {
+    authorize_pipeline_exec(&mut conn, session.user_id, id, MemberRole::Runner).await?;
+
+    let pipeline_exec = sqlx::query!(
+        r#"
+        SELECT
+            pipeline_id,
+            pipeline_trigger_id,
+            status AS "status: ExecStatus",
+            params
+        FROM
+            pipeline_execs
+        WHERE
+            id = $1
+        "#,
+        id
+    )
+    .fetch_optional(&mut *conn)
+    .await
+    .map_err(api_error)?
+    .ok_or_else(|| {
+        (
+            StatusCode::NOT_FOUND,
+            Json(ApiError {
+                error: "Pipeline exec not found".to_string(),
+            }),
+        )
+    })?;
+
+    if !pipeline_exec.status.is_terminal() {
+        return Err((
+            StatusCode::CONFLICT,
+            Json(ApiError {
+                error: format!(
+                    "Only finished pipeline execs can be replayed, this one is {}",
+                    pipeline_exec.status.as_str()
+                ),
+            }),
+        ));
+    }
+
+    let mut params = serde_json::from_value::<PipelineExecPayloadParams>(
+        pipeline_exec.params.clone(),
+    )
+    .map_err(|error| {
+        (
+            internal_error(&error),
+            Json(ApiError {
+                error: error.to_string(),
+            }),
+        )
+    })?;
+
+    let configs = fetch_node_configs(&mut conn, pipeline_exec.pipeline_id)
+        .await
+        .map_err(|error| {
+            (
+                internal_error(&error),
+                Json(ApiError {
+                    error: error.to_string(),
+                }),
+            )
+        })?;
+
+    let errors = restore_exec_params(&mut params, replay.params, &configs);
+
+    if !errors.is_empty() {
+        return Ok((
+            StatusCode::UNPROCESSABLE_ENTITY,
+            Json(PipelineParamsRejection { errors }),
+        )
+            .into_response());
+    }
+
+    let trigger_id = match check_manual_restart(
+        &mut conn,
+        pipeline_exec.pipeline_id,
+        pipeline_exec.pipeline_trigger_id,
+        &params,
+    )
+    .await?
+    {
+        Ok(trigger_id) => trigger_id,
+        Err(rejection) => {
+            return Ok((StatusCode::UNPROCESSABLE_ENTITY, Json(rejection)).into_response())
+        }
+    };
+
+    let pinned_node_versions = if replay.pin_node_versions {
+        let node_execs = sqlx::query!(
+            r#"
+            SELECT
+                pne.pipeline_node_id,
+                pne.node_version AS "node_version!",
+                n.identifier_name,
+                EXISTS(
+                    SELECT 1
+                    FROM nodes v
+                    WHERE
+                        v.publisher_name = n.publisher_name
+                        AND v.identifier_name = n.identifier_name
+                        AND v.version_name = pne.node_version
+                ) AS "available!"
+            FROM
+                pipeline_node_execs pne
+            JOIN
+                pipeline_nodes pn ON pn.id = pne.pipeline_node_id
+            JOIN
+                nodes n ON n.id = pn.node_id
+            WHERE
+                pne.pipeline_exec_id = $1 AND pne.node_version IS NOT NULL
+            "#,
+            id
+        )
+        .fetch_all(&mut *conn)
+        .await
+        .map_err(api_error)?;
+
+        let unavailable = node_execs
+            .iter()
+            .filter(|node_exec| !node_exec.available)
+            .map(|node_exec| format!("{}@{}", node_exec.identifier_name, node_exec.node_version))
+            .collect::<Vec<_>>();
+
+        if !unavailable.is_empty() {
+            return Err((
+                StatusCode::UNPROCESSABLE_ENTITY,
+                Json(ApiError {
+                    error: format!(
+                        "The pinned node versions are no longer available: {}",
+                        unavailable.join(", ")
+                    ),
+                }),
+            ));
+        }
+
+        let node_versions = node_execs
+            .into_iter()
+            .map(|node_exec| {
+                (
+                    node_exec.pipeline_node_id.to_string(),
+                    serde_json::Value::String(node_exec.node_version),
+                )
+            })
+            .collect::<serde_json::Map<_, _>>();
+
+        Some(serde_json::Value::Object(node_versions))
+    } else {
+        None
+    };
+
+    let replay_id = match insert_pipeline_exec(
+        &mut conn,
+        &mut rate_limiter,
+        pipeline_exec.pipeline_id,
+        Some(trigger_id),
+        &PipelineExecOrigin {
+            replay_of_pipeline_exec_id: Some(id),
+            pinned_node_versions,
+            ..Default::default()
+        },
+        &params,
+    )
+    .await
+    .map_err(start_error)?
+    {
+        Ok(replay_id) => replay_id,
+        Err(rate_limited) => return Ok(rate_limited.into_response()),
+    };
+
+    publish_pipeline_exec(
+        &jetstream,
+        pipeline_exec.pipeline_id,
+        replay_id,
+        Some(trigger_id),
+        params,
+    )
+    .await
+    .map_err(|error| {
+        (
+            internal_error(&error),
+            Json(ApiError {
+                error: error.to_string(),
+            }),
+        )
+    })?;
+
+    info!("Pipeline exec {replay_id} started as a replay of {id}");
+
+    Ok(Json(PipelineExecStartResponse {
+        pipeline_exec_id: replay_id,
+    })
+    .into_response())
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PipelineExecDiffQuery {
+    /// Execution to compare against, defaults to the one replayed.
+    pub against: Option<Uuid>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PipelineExecDiff {
+    original_pipeline_exec_id: Uuid,
+    pipeline_exec_id: Uuid,
+    nodes: Vec<PipelineNodeExecDiff>,
+}
+
+/// Node outputs of an execution compared to the ones of an earlier execution of the same pipeline.
+pub async fn diff(
+    DatabaseConnection(mut conn): DatabaseConnection,
+    Session(session): Session,
+    Path(id): Path<Uuid>,
+    Query(query): Query<PipelineExecDiffQuery>,
+) -> Result<Json<PipelineExecDiff>, (StatusCode, Json<ApiError>)> {
+    authorize_pipeline_exec(&mut conn, session.user_id, id, MemberRole::Viewer).await?;
+
+    let pipeline_exec = sqlx::query!(
+        r#"
+        SELECT
+            pipeline_id, replay_of_pipeline_exec_id
+        FROM
+            pipeline_execs
+        WHERE
+            id = $1
+        "#,
+        id
+    )
+    .fetch_optional(&mut *conn)
+    .await
+    .map_err(api_error)?
+    .ok_or_else(|| {
+        (
+            StatusCode::NOT_FOUND,
+            Json(ApiError {
+                error: "Pipeline exec not found".to_string(),
+            }),
+        )
+    })?;
+
+    let Some(original_pipeline_exec_id) =
+        query.against.or(pipeline_exec.replay_of_pipeline_exec_id)
+    else {
+        return Err((
+            StatusCode::UNPROCESSABLE_ENTITY,
+            Json(ApiError {
+                error: "Pipeline exec is not a replay, an execution to compare against is required"
+                    .to_string(),
+            }),
+        ));
+    };
+
+    let original = sqlx::query!(
+        r#"
+        SELECT
+            pipeline_id
+        FROM
+            pipeline_execs
+        WHERE
+            id = $1
+        "#,
+        original_pipeline_exec_id
+    )
+    .fetch_optional(&mut *conn)
+    .await
+    .map_err(api_error)?;
+
+    if original.is_none_or(|original| original.pipeline_id != pipeline_exec.pipeline_id) {
+        return Err((
+            StatusCode::UNPROCESSABLE_ENTITY,
+            Json(ApiError {
+                error: format!(
+                    "Pipeline exec {original_pipeline_exec_id} is not an execution of the same pipeline"
+                ),
+            }),
+        ));
+    }
+
+    let mut node_execs = sqlx::query!(
+        r#"
+        SELECT
+            pipeline_exec_id,
+            pipeline_node_id,
+            node_version,
+            status AS "status: ExecStatus",
+            result
+        FROM
+            pipeline_node_execs
+        WHERE
+            pipeline_exec_id = ANY($1)
+        "#,
+        &[original_pipeline_exec_id, id]
+    )
+    .fetch_all(&mut *conn)
+    .await
+    .map_err(api_error)?
+    .into_iter()
+    .map(|node_exec| {
+        (
+            node_exec.pipeline_exec_id,
+            DiffedNodeExec {
+                pipeline_node_id: node_exec.pipeline_node_id,
+                node_version: node_exec.node_version,
+                status: node_exec.status,
+                result: node_exec.result,
+            },
+        )
+    })
+    .fold(
+        HashMap::<Uuid, Vec<DiffedNodeExec>>::new(),
+        |mut node_execs, (pipeline_exec_id, node_exec)| {
+            node_execs
+                .entry(pipeline_exec_id)
+                .or_default()
+                .push(node_exec);
+            node_execs
+        },
+    );
+
+    let nodes = diff_node_execs(
+        node_execs
+            .remove(&original_pipeline_exec_id)
+            .unwrap_or_default(),
+        node_execs.remove(&id).unwrap_or_default(),
+    );
+
+    Ok(Json(PipelineExecDiff {
+        original_pipeline_exec_id,
+        pipeline_exec_id: id,
+        nodes,
+    }))
+}
 
 pub async fn subscribe(
+    DatabaseConnection(mut conn): DatabaseConnection,
+    Session(session): Session,
     Path(id): Path<Uuid>,
     ExecEventsConsumer(exec_events_consumer): ExecEventsConsumer,
 ) -> Result<Sse<impl Stream<Item = Result<Event, Box<dyn Error + Send + Sync>>>>, hyper::StatusCode>
 {
+    authorize_pipeline_exec(&mut conn, session.user_id, id, MemberRole::Viewer).await?;
+
     debug!("Subscribing directly to NATS for pipeline exec {id}");
 
     let stream = async_stream::stream! {
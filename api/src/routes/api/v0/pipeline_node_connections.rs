@@ -1,9 +1,19 @@
 use axum::Json;
+use db::dtos::{MemberRole, NodeConfig};
 use hyper::StatusCode;
 use serde::{Deserialize, Serialize};
+use sqlx::Acquire;
 use uuid::Uuid;
 
-use crate::{app_state::DatabaseConnection, utils::internal_error};
+use crate::{
+    app_state::DatabaseConnection,
+    extractors::session::Session,
+    lib::{
+        api_error::{api_error, ApiError},
+        authorization::{authorize_pipeline_node_input, authorize_pipeline_node_output},
+        pipeline_graph::{creates_cycle, fetch_graph_connections},
+    },
+};
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 #[serde(rename_all = "camelCase")]
@@ -20,10 +30,177 @@ pub struct PipelineNodeConnection {
     from_pipeline_node_output_id: Uuid,
 }
 
+fn rejected(status: StatusCode, error: &str) -> (StatusCode, Json<ApiError>) {
+    (
+        status,
+        Json(ApiError {
+            error: error.to_string(),
+        }),
+    )
+}
+
 pub async fn create(
     DatabaseConnection(mut conn): DatabaseConnection,
+    Session(session): Session,
     Json(payload): Json<PipelineNodeConnectionCreate>,
-) -> Result<Json<PipelineNodeConnection>, StatusCode> {
+) -> Result<Json<PipelineNodeConnection>, (StatusCode, Json<ApiError>)> {
+    authorize_pipeline_node_output(
+        &mut conn,
+        session.user_id,
+        payload.from_pipeline_node_output_id,
+        MemberRole::Editor,
+    )
+    .await?;
+    authorize_pipeline_node_input(
+        &mut conn,
+        session.user_id,
+        payload.to_pipeline_node_input_id,
+        MemberRole::Editor,
+    )
+    .await?;
+
+    let mut tx = conn.begin().await.map_err(api_error)?;
+
+    let Some(endpoints) = sqlx::query!(
+        r#"
+        SELECT
+            fpn.pipeline_id AS from_pipeline_id,
+            fpn.id AS from_pipeline_node_id,
+            tpn.pipeline_id AS to_pipeline_id,
+            tpn.id AS to_pipeline_node_id,
+            pno.key AS output_key,
+            fn.config AS from_node_config,
+            pni.key AS input_key,
+            tn.config AS to_node_config
+        FROM
+            pipeline_node_outputs pno
+        JOIN
+            pipeline_nodes fpn ON fpn.id = pno.pipeline_node_id
+        JOIN
+            nodes fn ON fn.id = fpn.node_id
+        CROSS JOIN
+            pipeline_node_inputs pni
+        JOIN
+            pipeline_nodes tpn ON tpn.id = pni.pipeline_node_id
+        JOIN
+            nodes tn ON tn.id = tpn.node_id
+        WHERE
+            pno.id = $1 AND pni.id = $2
+        "#,
+        payload.from_pipeline_node_output_id,
+        payload.to_pipeline_node_input_id
+    )
+    .fetch_optional(&mut *tx)
+    .await
+    .map_err(api_error)?
+    else {
+        return Err(rejected(
+            StatusCode::NOT_FOUND,
+            "Pipeline node output or input not found",
+        ));
+    };
+
+    if endpoints.from_pipeline_id != endpoints.to_pipeline_id {
+        return Err(rejected(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "Nodes of different pipelines can't be connected",
+        ));
+    }
+
+    if endpoints.from_pipeline_node_id == endpoints.to_pipeline_node_id {
+        return Err(rejected(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "A pipeline node can't be connected to itself",
+        ));
+    }
+
+    let from_node_config = serde_json::from_value::<NodeConfig>(endpoints.from_node_config)
+        .map(NodeConfig::into_v1)
+        .map_err(|error| {
+            rejected(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                &format!("Invalid node config: {error}"),
+            )
+        })?;
+    let to_node_config = serde_json::from_value::<NodeConfig>(endpoints.to_node_config)
+        .map(NodeConfig::into_v1)
+        .map_err(|error| {
+            rejected(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                &format!("Invalid node config: {error}"),
+            )
+        })?;
+
+    if let (Some(output), Some(input)) = (
+        from_node_config.output(&endpoints.output_key),
+        to_node_config.input(&endpoints.input_key),
+    ) {
+        if !input.input.accepts(&output.output) {
+            return Err(rejected(
+                StatusCode::UNPROCESSABLE_ENTITY,
+                &format!(
+                    "Output {} of type {} can't be connected to input {} of type {}",
+                    output.key,
+                    output.output.name(),
+                    input.key,
+                    input.input.name()
+                ),
+            ));
+        }
+    }
+
+    // Serializes graph changes of the pipeline, concurrent connections could close a cycle together
+    sqlx::query!(
+        r#"
+        SELECT
+            id
+        FROM
+            pipelines
+        WHERE
+            id = $1
+        FOR UPDATE
+        "#,
+        endpoints.to_pipeline_id
+    )
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(api_error)?;
+
+    let connections = fetch_graph_connections(&mut tx, endpoints.to_pipeline_id)
+        .await
+        .map_err(api_error)?;
+
+    if connections
+        .iter()
+        .any(|connection| connection.to_pipeline_node_input_id == payload.to_pipeline_node_input_id)
+    {
+        return Err(rejected(
+            StatusCode::CONFLICT,
+            "Pipeline node input is already connected",
+        ));
+    }
+
+    let edges = connections
+        .iter()
+        .map(|connection| {
+            (
+                connection.from_pipeline_node_id,
+                connection.to_pipeline_node_id,
+            )
+        })
+        .collect::<Vec<_>>();
+
+    if creates_cycle(
+        &edges,
+        endpoints.from_pipeline_node_id,
+        endpoints.to_pipeline_node_id,
+    ) {
+        return Err(rejected(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "Connection would create a cycle",
+        ));
+    }
+
     let connection = sqlx::query!(
         r#"
         INSERT INTO
@@ -36,9 +213,11 @@ pub async fn create(
         payload.to_pipeline_node_input_id,
         payload.from_pipeline_node_output_id
     )
-    .fetch_one(&mut *conn)
+    .fetch_one(&mut *tx)
     .await
-    .map_err(internal_error)?;
+    .map_err(api_error)?;
+
+    tx.commit().await.map_err(api_error)?;
 
     Ok(Json(PipelineNodeConnection {
         id: connection.id,
@@ -1,11 +1,26 @@
 use axum::{extract::Path, Json};
+use db::dtos::{
+    EventTriggerConfig, ExecStatus, MemberRole, NatsTriggerConfig, PipelineTriggerConfig,
+    PipelineTriggerConfigV1, PipelineTriggerSource, WebhookTriggerConfig,
+};
 use hyper::StatusCode;
 use serde::{Deserialize, Serialize};
+use sqlx::{Acquire, PgConnection};
+use std::collections::HashSet;
+use tracing::warn;
 use uuid::Uuid;
 
 use crate::{
-    app_state::{Coords, DatabaseConnection},
+    app_state::{Coords, DatabaseConnection, JetStream},
     extractors::session::Session,
+    lib::{
+        authorization::{authorize_pipeline, authorize_pipeline_trigger, AccessError},
+        nats_trigger::{
+            is_team_stream, is_team_subject, is_valid_durable_name, to_durable_consumer,
+        },
+        pipeline_graph::creates_cycle,
+        webhook::{generate_webhook_key, generate_webhook_secret, to_webhook_path},
+    },
     utils::internal_error,
 };
 
@@ -15,7 +30,16 @@ pub struct UpdatePipelineTriggerParams {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub coords: Option<Coords>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub config: Option<serde_json::Value>,
+    pub config: Option<PipelineTriggerConfig>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CreatePipelineTriggerParams {
+    pub coords: Coords,
+    /// Defaults to a trigger allowing manual execution only.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub config: Option<PipelineTriggerConfig>,
 }
 
 #[derive(Debug, Serialize)]
@@ -26,18 +50,154 @@ pub struct PipelineTrigger {
     pub config: serde_json::Value,
 }
 
+/// The upstream pipeline of an event trigger must be readable by the caller as well, an unknown
+/// pipeline is rejected the same way as one of another team so that its existence isn't revealed.
+async fn authorize_event_source(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    pipeline_id: Uuid,
+) -> Result<(), StatusCode> {
+    match authorize_pipeline(conn, user_id, pipeline_id, MemberRole::Viewer).await {
+        Ok(_) => Ok(()),
+        Err(AccessError::NotFound | AccessError::Forbidden(_)) => {
+            Err(StatusCode::UNPROCESSABLE_ENTITY)
+        }
+        Err(error) => Err(error.into()),
+    }
+}
+
+pub async fn list(
+    DatabaseConnection(mut conn): DatabaseConnection,
+    Session(session): Session,
+    Path(pipeline_id): Path<Uuid>,
+) -> Result<Json<Vec<PipelineTrigger>>, StatusCode> {
+    authorize_pipeline(&mut conn, session.user_id, pipeline_id, MemberRole::Viewer).await?;
+
+    let triggers = sqlx::query_as!(
+        PipelineTrigger,
+        r#"
+        SELECT
+            id, coords, config
+        FROM
+            pipeline_triggers
+        WHERE
+            pipeline_id = $1
+        ORDER BY
+            created_at
+        "#,
+        pipeline_id
+    )
+    .fetch_all(&mut *conn)
+    .await
+    .map_err(internal_error)?;
+
+    Ok(Json(triggers))
+}
+
+pub async fn create(
+    DatabaseConnection(mut conn): DatabaseConnection,
+    JetStream(jetstream): JetStream,
+    Session(session): Session,
+    Path(pipeline_id): Path<Uuid>,
+    Json(params): Json<CreatePipelineTriggerParams>,
+) -> Result<Json<PipelineTrigger>, StatusCode> {
+    authorize_pipeline(&mut conn, session.user_id, pipeline_id, MemberRole::Editor).await?;
+
+    if let Some(PipelineTriggerSource::Event(event_config)) = params
+        .config
+        .as_ref()
+        .and_then(PipelineTriggerConfig::source)
+    {
+        authorize_event_source(&mut conn, session.user_id, event_config.pipeline_id).await?;
+    }
+
+    let coords = serde_json::to_value(params.coords).map_err(internal_error)?;
+
+    let default_config = PipelineTriggerConfig::V1(PipelineTriggerConfigV1 {
+        allow_manual_execution: true,
+        source: None,
+    });
+
+    let mut tx = conn.begin().await.map_err(internal_error)?;
+
+    let trigger = sqlx::query_as!(
+        PipelineTrigger,
+        r#"
+        INSERT INTO
+            pipeline_triggers (pipeline_id, coords, config)
+        VALUES
+            ($1, $2, $3)
+        RETURNING
+            id, coords, config
+        "#,
+        pipeline_id,
+        coords,
+        serde_json::to_value(&default_config).map_err(internal_error)?
+    )
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(internal_error)?;
+
+    let trigger = match params.config {
+        Some(config) => {
+            save_pipeline_trigger_config(
+                &mut tx,
+                &jetstream,
+                trigger.id,
+                pipeline_id,
+                &default_config,
+                config,
+            )
+            .await?
+            .0
+        }
+        None => trigger,
+    };
+
+    tx.commit().await.map_err(internal_error)?;
+
+    Ok(Json(trigger))
+}
+
 pub async fn update(
     DatabaseConnection(mut conn): DatabaseConnection,
-    Session(_): Session,
+    JetStream(jetstream): JetStream,
+    Session(session): Session,
     Path(id): Path<Uuid>,
     Json(params): Json<UpdatePipelineTriggerParams>,
 ) -> Result<Json<PipelineTrigger>, StatusCode> {
+    authorize_pipeline_trigger(&mut conn, session.user_id, id, MemberRole::Editor).await?;
+
+    if let Some(PipelineTriggerSource::Event(event_config)) = params
+        .config
+        .as_ref()
+        .and_then(PipelineTriggerConfig::source)
+    {
+        authorize_event_source(&mut conn, session.user_id, event_config.pipeline_id).await?;
+    }
+
     let coords = params
         .coords
         .as_ref()
         .map(|c| serde_json::to_value(c).map_err(internal_error))
         .transpose()?;
 
+    let mut tx = conn.begin().await.map_err(internal_error)?;
+
+    let (pipeline_id, previous_config) = lock_pipeline_trigger(&mut tx, id).await?;
+
+    if let Some(config) = params.config {
+        save_pipeline_trigger_config(
+            &mut tx,
+            &jetstream,
+            id,
+            pipeline_id,
+            &previous_config,
+            config,
+        )
+        .await?;
+    }
+
     let trigger = sqlx::query!(
         r#"
         UPDATE
@@ -52,13 +212,620 @@ pub async fn update(
         coords,
         id
     )
-    .fetch_one(&mut *conn)
+    .fetch_one(&mut *tx)
     .await
     .map_err(internal_error)?;
 
+    tx.commit().await.map_err(internal_error)?;
+
     Ok(Json(PipelineTrigger {
         id: trigger.id,
         coords: trigger.coords,
         config: trigger.config,
     }))
 }
+
+pub async fn delete(
+    DatabaseConnection(mut conn): DatabaseConnection,
+    JetStream(jetstream): JetStream,
+    Session(session): Session,
+    Path(id): Path<Uuid>,
+) -> Result<StatusCode, StatusCode> {
+    authorize_pipeline_trigger(&mut conn, session.user_id, id, MemberRole::Editor).await?;
+
+    let mut tx = conn.begin().await.map_err(internal_error)?;
+
+    let (_, config) = lock_pipeline_trigger(&mut tx, id).await?;
+
+    // Webhooks are dropped along with the trigger, entry nodes are detached from it
+    sqlx::query!(
+        r#"
+        DELETE FROM
+            pipeline_triggers
+        WHERE
+            id = $1
+        "#,
+        id
+    )
+    .execute(&mut *tx)
+    .await
+    .map_err(internal_error)?;
+
+    tx.commit().await.map_err(internal_error)?;
+
+    if let Some(PipelineTriggerSource::Nats(nats_config)) = config.source() {
+        delete_durable_consumer(&jetstream, to_durable_consumer(id, nats_config)).await;
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PipelineTriggerWebhook {
+    pub trigger: PipelineTrigger,
+    pub url: String,
+    /// Only returned when the secret is generated, it can't be read back afterwards.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub secret: Option<String>,
+}
+
+pub async fn configure_webhook(
+    DatabaseConnection(mut conn): DatabaseConnection,
+    JetStream(jetstream): JetStream,
+    Session(session): Session,
+    Path(id): Path<Uuid>,
+    Json(params): Json<WebhookTriggerConfig>,
+) -> Result<Json<PipelineTriggerWebhook>, StatusCode> {
+    authorize_pipeline_trigger(&mut conn, session.user_id, id, MemberRole::Editor).await?;
+
+    let mut tx = conn.begin().await.map_err(internal_error)?;
+
+    let (pipeline_id, config) = lock_pipeline_trigger(&mut tx, id).await?;
+
+    let (trigger, secret) = save_pipeline_trigger_config(
+        &mut tx,
+        &jetstream,
+        id,
+        pipeline_id,
+        &config,
+        config.with_source(Some(PipelineTriggerSource::Webhook(params))),
+    )
+    .await?;
+
+    let webhook = sqlx::query!(
+        r#"
+        SELECT
+            path_key
+        FROM
+            pipeline_trigger_webhooks
+        WHERE
+            pipeline_trigger_id = $1
+        "#,
+        id
+    )
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(internal_error)?;
+
+    tx.commit().await.map_err(internal_error)?;
+
+    Ok(Json(PipelineTriggerWebhook {
+        trigger,
+        url: to_webhook_path(&webhook.path_key),
+        secret,
+    }))
+}
+
+pub async fn rotate_webhook_secret(
+    DatabaseConnection(mut conn): DatabaseConnection,
+    Session(session): Session,
+    Path(id): Path<Uuid>,
+) -> Result<Json<PipelineTriggerWebhook>, StatusCode> {
+    authorize_pipeline_trigger(&mut conn, session.user_id, id, MemberRole::Editor).await?;
+
+    let secret = generate_webhook_secret();
+
+    let Some(webhook) = sqlx::query!(
+        r#"
+        UPDATE
+            pipeline_trigger_webhooks ptw
+        SET
+            secret = $1
+        FROM
+            pipeline_triggers pt
+        WHERE
+            ptw.pipeline_trigger_id = $2 AND pt.id = ptw.pipeline_trigger_id
+        RETURNING
+            ptw.path_key, pt.id, pt.coords, pt.config
+        "#,
+        secret,
+        id
+    )
+    .fetch_optional(&mut *conn)
+    .await
+    .map_err(internal_error)?
+    else {
+        return Err(StatusCode::NOT_FOUND);
+    };
+
+    Ok(Json(PipelineTriggerWebhook {
+        trigger: PipelineTrigger {
+            id: webhook.id,
+            coords: webhook.coords,
+            config: webhook.config,
+        },
+        url: to_webhook_path(&webhook.path_key),
+        secret: Some(secret),
+    }))
+}
+
+pub async fn delete_webhook(
+    DatabaseConnection(mut conn): DatabaseConnection,
+    JetStream(jetstream): JetStream,
+    Session(session): Session,
+    Path(id): Path<Uuid>,
+) -> Result<Json<PipelineTrigger>, StatusCode> {
+    authorize_pipeline_trigger(&mut conn, session.user_id, id, MemberRole::Editor).await?;
+
+    clear_pipeline_trigger_source(conn, &jetstream, id, |source| {
+        matches!(source, PipelineTriggerSource::Webhook(_))
+    })
+    .await
+    .map(Json)
+}
+
+pub async fn configure_event(
+    DatabaseConnection(mut conn): DatabaseConnection,
+    JetStream(jetstream): JetStream,
+    Session(session): Session,
+    Path(id): Path<Uuid>,
+    Json(params): Json<EventTriggerConfig>,
+) -> Result<Json<PipelineTrigger>, StatusCode> {
+    authorize_pipeline_trigger(&mut conn, session.user_id, id, MemberRole::Editor).await?;
+    authorize_event_source(&mut conn, session.user_id, params.pipeline_id).await?;
+
+    let mut tx = conn.begin().await.map_err(internal_error)?;
+
+    let (pipeline_id, config) = lock_pipeline_trigger(&mut tx, id).await?;
+
+    let (trigger, _) = save_pipeline_trigger_config(
+        &mut tx,
+        &jetstream,
+        id,
+        pipeline_id,
+        &config,
+        config.with_source(Some(PipelineTriggerSource::Event(params))),
+    )
+    .await?;
+
+    tx.commit().await.map_err(internal_error)?;
+
+    Ok(Json(trigger))
+}
+
+pub async fn delete_event(
+    DatabaseConnection(mut conn): DatabaseConnection,
+    JetStream(jetstream): JetStream,
+    Session(session): Session,
+    Path(id): Path<Uuid>,
+) -> Result<Json<PipelineTrigger>, StatusCode> {
+    authorize_pipeline_trigger(&mut conn, session.user_id, id, MemberRole::Editor).await?;
+
+    clear_pipeline_trigger_source(conn, &jetstream, id, |source| {
+        matches!(source, PipelineTriggerSource::Event(_))
+    })
+    .await
+    .map(Json)
+}
+
+pub async fn configure_nats(
+    DatabaseConnection(mut conn): DatabaseConnection,
+    JetStream(jetstream): JetStream,
+    Session(session): Session,
+    Path(id): Path<Uuid>,
+    Json(params): Json<NatsTriggerConfig>,
+) -> Result<Json<PipelineTrigger>, StatusCode> {
+    authorize_pipeline_trigger(&mut conn, session.user_id, id, MemberRole::Editor).await?;
+
+    let mut tx = conn.begin().await.map_err(internal_error)?;
+
+    let (pipeline_id, config) = lock_pipeline_trigger(&mut tx, id).await?;
+
+    let (trigger, _) = save_pipeline_trigger_config(
+        &mut tx,
+        &jetstream,
+        id,
+        pipeline_id,
+        &config,
+        config.with_source(Some(PipelineTriggerSource::Nats(params))),
+    )
+    .await?;
+
+    tx.commit().await.map_err(internal_error)?;
+
+    Ok(Json(trigger))
+}
+
+pub async fn delete_nats(
+    DatabaseConnection(mut conn): DatabaseConnection,
+    JetStream(jetstream): JetStream,
+    Session(session): Session,
+    Path(id): Path<Uuid>,
+) -> Result<Json<PipelineTrigger>, StatusCode> {
+    authorize_pipeline_trigger(&mut conn, session.user_id, id, MemberRole::Editor).await?;
+
+    clear_pipeline_trigger_source(conn, &jetstream, id, |source| {
+        matches!(source, PipelineTriggerSource::Nats(_))
+    })
+    .await
+    .map(Json)
+}
+
+async fn clear_pipeline_trigger_source(
+    mut conn: sqlx::pool::PoolConnection<sqlx::Postgres>,
+    jetstream: &async_nats::jetstream::Context,
+    id: Uuid,
+    is_cleared_source: impl Fn(&PipelineTriggerSource) -> bool,
+) -> Result<PipelineTrigger, StatusCode> {
+    let mut tx = conn.begin().await.map_err(internal_error)?;
+
+    let (pipeline_id, config) = lock_pipeline_trigger(&mut tx, id).await?;
+
+    if !config.source().is_some_and(is_cleared_source) {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    let (trigger, _) = save_pipeline_trigger_config(
+        &mut tx,
+        jetstream,
+        id,
+        pipeline_id,
+        &config,
+        config.with_source(None),
+    )
+    .await?;
+
+    tx.commit().await.map_err(internal_error)?;
+
+    Ok(trigger)
+}
+
+/// Validates and stores the trigger config, keeping the webhook and the NATS durable consumer
+/// in sync with its source. Returns the webhook secret when one got generated.
+async fn save_pipeline_trigger_config(
+    tx: &mut PgConnection,
+    jetstream: &async_nats::jetstream::Context,
+    id: Uuid,
+    pipeline_id: Uuid,
+    previous_config: &PipelineTriggerConfig,
+    config: PipelineTriggerConfig,
+) -> Result<(PipelineTrigger, Option<String>), StatusCode> {
+    if let Some(source) = config.source() {
+        validate_pipeline_trigger_source(tx, jetstream, id, pipeline_id, source).await?;
+    }
+
+    let trigger = sqlx::query!(
+        r#"
+        UPDATE
+            pipeline_triggers
+        SET
+            config = $1
+        WHERE
+            id = $2
+        RETURNING
+            id, coords, config
+        "#,
+        serde_json::to_value(&config).map_err(internal_error)?,
+        id
+    )
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(internal_error)?;
+
+    let secret = if let Some(PipelineTriggerSource::Webhook(_)) = config.source() {
+        let secret = generate_webhook_secret();
+
+        sqlx::query!(
+            r#"
+            INSERT INTO
+                pipeline_trigger_webhooks (pipeline_trigger_id, path_key, secret)
+            VALUES
+                ($1, $2, $3)
+            ON CONFLICT (pipeline_trigger_id) DO NOTHING
+            RETURNING
+                path_key
+            "#,
+            id,
+            generate_webhook_key(),
+            secret
+        )
+        .fetch_optional(&mut *tx)
+        .await
+        .map_err(internal_error)?
+        .map(|_| secret)
+    } else {
+        sqlx::query!(
+            r#"
+            DELETE FROM
+                pipeline_trigger_webhooks
+            WHERE
+                pipeline_trigger_id = $1
+            "#,
+            id
+        )
+        .execute(&mut *tx)
+        .await
+        .map_err(internal_error)?;
+
+        None
+    };
+
+    let previous_consumer = match previous_config.source() {
+        Some(PipelineTriggerSource::Nats(nats_config)) => to_durable_consumer(id, nats_config),
+        _ => None,
+    };
+
+    let consumer = match config.source() {
+        Some(PipelineTriggerSource::Nats(nats_config)) => to_durable_consumer(id, nats_config),
+        _ => None,
+    };
+
+    if previous_consumer != consumer {
+        delete_durable_consumer(jetstream, previous_consumer).await;
+    }
+
+    Ok((
+        PipelineTrigger {
+            id: trigger.id,
+            coords: trigger.coords,
+            config: trigger.config,
+        },
+        secret,
+    ))
+}
+
+async fn validate_pipeline_trigger_source(
+    tx: &mut PgConnection,
+    jetstream: &async_nats::jetstream::Context,
+    id: Uuid,
+    pipeline_id: Uuid,
+    source: &PipelineTriggerSource,
+) -> Result<(), StatusCode> {
+    let pipeline_node_ids = fetch_pipeline_node_ids(tx, pipeline_id).await?;
+
+    let referenced_node_ids = match source {
+        PipelineTriggerSource::Webhook(webhook_config) => webhook_config
+            .params
+            .iter()
+            .map(|mapping| mapping.pipeline_node_id)
+            .chain(
+                webhook_config
+                    .response
+                    .iter()
+                    .map(|response| response.pipeline_node_id),
+            )
+            .collect::<Vec<_>>(),
+        PipelineTriggerSource::Event(event_config) => {
+            validate_event_trigger_config(tx, id, pipeline_id, event_config).await?;
+
+            event_config
+                .params
+                .iter()
+                .map(|mapping| mapping.pipeline_node_id)
+                .collect()
+        }
+        PipelineTriggerSource::Nats(nats_config) => {
+            let team_id = fetch_pipeline_team_id(tx, pipeline_id).await?;
+
+            if !is_team_subject(team_id, &nats_config.subject)
+                || nats_config.max_concurrency == Some(0)
+            {
+                return Err(StatusCode::UNPROCESSABLE_ENTITY);
+            }
+
+            if let Some(jetstream_config) = nats_config.jetstream.as_ref() {
+                if jetstream_config
+                    .durable_name
+                    .as_deref()
+                    .is_some_and(|durable_name| !is_valid_durable_name(durable_name))
+                {
+                    return Err(StatusCode::UNPROCESSABLE_ENTITY);
+                }
+
+                // Unknown streams and streams capturing subjects of other teams alike
+                if !is_team_stream(jetstream, team_id, &jetstream_config.stream)
+                    .await
+                    .unwrap_or(false)
+                {
+                    return Err(StatusCode::UNPROCESSABLE_ENTITY);
+                }
+            }
+
+            nats_config
+                .params
+                .iter()
+                .map(|mapping| mapping.pipeline_node_id)
+                .collect()
+        }
+    };
+
+    if referenced_node_ids
+        .iter()
+        .all(|pipeline_node_id| pipeline_node_ids.contains(pipeline_node_id))
+    {
+        Ok(())
+    } else {
+        Err(StatusCode::UNPROCESSABLE_ENTITY)
+    }
+}
+
+async fn validate_event_trigger_config(
+    tx: &mut PgConnection,
+    id: Uuid,
+    pipeline_id: Uuid,
+    event_config: &EventTriggerConfig,
+) -> Result<(), StatusCode> {
+    if event_config.statuses.is_empty()
+        || !event_config.statuses.iter().all(ExecStatus::is_terminal)
+    {
+        return Err(StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    let upstream_pipeline = sqlx::query!(
+        r#"
+        SELECT
+            id
+        FROM
+            pipelines
+        WHERE
+            id = $1
+        "#,
+        event_config.pipeline_id
+    )
+    .fetch_optional(&mut *tx)
+    .await
+    .map_err(internal_error)?;
+
+    if upstream_pipeline.is_none() {
+        return Err(StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    let upstream_pipeline_node_ids = fetch_pipeline_node_ids(tx, event_config.pipeline_id).await?;
+
+    if !event_config
+        .params
+        .iter()
+        .all(|mapping| upstream_pipeline_node_ids.contains(&mapping.source_pipeline_node_id))
+    {
+        return Err(StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    let edges = sqlx::query!(
+        r#"
+        SELECT
+            pipeline_id, config
+        FROM
+            pipeline_triggers
+        WHERE
+            id <> $1 AND config->'source'->>'type' = 'event'
+        "#,
+        id
+    )
+    .fetch_all(&mut *tx)
+    .await
+    .map_err(internal_error)?
+    .into_iter()
+    .filter_map(|row| {
+        let config = serde_json::from_value::<PipelineTriggerConfig>(row.config).ok()?;
+
+        match config.source() {
+            Some(PipelineTriggerSource::Event(event_config)) => {
+                Some((event_config.pipeline_id, row.pipeline_id))
+            }
+            _ => None,
+        }
+    })
+    .collect::<Vec<_>>();
+
+    if creates_cycle(&edges, event_config.pipeline_id, pipeline_id) {
+        return Err(StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    Ok(())
+}
+
+/// A durable consumer left behind keeps retaining the stream messages, removal is best effort.
+async fn delete_durable_consumer(
+    jetstream: &async_nats::jetstream::Context,
+    consumer: Option<(String, String)>,
+) {
+    let Some((stream, durable_name)) = consumer else {
+        return;
+    };
+
+    let result = match jetstream.get_stream(&stream).await {
+        Ok(stream) => stream
+            .delete_consumer(&durable_name)
+            .await
+            .map(|_| ())
+            .map_err(|error| error.to_string()),
+        Err(error) => Err(error.to_string()),
+    };
+
+    if let Err(error) = result {
+        warn!("Failed to delete durable consumer {durable_name} of stream {stream}: {error}");
+    }
+}
+
+/// Locks the trigger row for the rest of the transaction, returns its pipeline and config.
+async fn lock_pipeline_trigger(
+    tx: &mut PgConnection,
+    id: Uuid,
+) -> Result<(Uuid, PipelineTriggerConfig), StatusCode> {
+    let Some(trigger) = sqlx::query!(
+        r#"
+        SELECT
+            pipeline_id, config
+        FROM
+            pipeline_triggers
+        WHERE
+            id = $1
+        FOR UPDATE
+        "#,
+        id
+    )
+    .fetch_optional(&mut *tx)
+    .await
+    .map_err(internal_error)?
+    else {
+        return Err(StatusCode::NOT_FOUND);
+    };
+
+    let config = serde_json::from_value(trigger.config).map_err(internal_error)?;
+
+    Ok((trigger.pipeline_id, config))
+}
+
+async fn fetch_pipeline_team_id(
+    tx: &mut PgConnection,
+    pipeline_id: Uuid,
+) -> Result<Uuid, StatusCode> {
+    sqlx::query_scalar!(
+        r#"
+        SELECT
+            team_id
+        FROM
+            pipelines
+        WHERE
+            id = $1
+        "#,
+        pipeline_id
+    )
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(internal_error)
+}
+
+async fn fetch_pipeline_node_ids(
+    tx: &mut PgConnection,
+    pipeline_id: Uuid,
+) -> Result<HashSet<Uuid>, StatusCode> {
+    Ok(sqlx::query!(
+        r#"
+        SELECT
+            id
+        FROM
+            pipeline_nodes
+        WHERE
+            pipeline_id = $1
+        "#,
+        pipeline_id
+    )
+    .fetch_all(&mut *tx)
+    .await
+    .map_err(internal_error)?
+    .into_iter()
+    .map(|row| row.id)
+    .collect())
+}
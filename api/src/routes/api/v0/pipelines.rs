@@ -1,20 +1,39 @@
 use super::{
     events::to_pipeline_participant_redis_key,
-    pipeline_nodes::{Input, Output, PipelineConnection, PipelineNode},
+    pipeline_nodes::{redact_input_values, Input, Output, PipelineConnection, PipelineNode},
 };
 use crate::routes::api::v0::pipeline_triggers::PipelineTrigger;
 use crate::{
     app_state::{DatabaseConnection, JetStream, RedisConnection},
     extractors::session::Session,
+    lib::{
+        api_error::{api_error, ApiError},
+        authorization::{authorize_pipeline, authorize_team},
+        pipeline_exec::{find_manual_trigger, start_pipeline_exec},
+        pipeline_graph::{
+            fetch_graph_connections, fetch_graph_inputs, find_graph_problems, PipelineGraphProblem,
+        },
+        pipeline_params::{
+            check_pipeline_contract, check_pipeline_exec_params, map_pipeline_inputs,
+            PipelineParamError,
+        },
+        rate_limit::RateLimiter,
+    },
     utils::internal_error,
 };
-use axum::{extract::Path, Json};
+use axum::{
+    extract::Path,
+    response::{IntoResponse, Response},
+    Json,
+};
 use axum_extra::extract::Query;
-use db::dtos::{self, PipelineExecPayloadParams, PipelineTriggerConfig, PipelineTriggerConfigV0};
+use db::dtos::{
+    MemberRole, NodeConfig, PipelineContract, PipelineTriggerConfig, PipelineTriggerConfigV0,
+};
 use hyper::StatusCode;
 use redis::AsyncCommands;
 use serde::Serialize;
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use tracing::{error, info};
 use uuid::Uuid;
 
@@ -25,16 +44,28 @@ pub struct PipelineListItem {
     description: Option<String>,
 }
 
+/// Pipelines of the teams the caller has a role in.
 pub async fn list(
     DatabaseConnection(mut conn): DatabaseConnection,
+    Session(session): Session,
 ) -> Result<Json<Vec<PipelineListItem>>, StatusCode> {
     let pipelines = sqlx::query!(
         r#"
         SELECT
-            id, name, description
+            p.id, p.name, p.description
         FROM
-            pipelines
-        "#
+            pipelines p
+        JOIN
+            teams t ON t.id = p.team_id
+        WHERE
+            t.id IN (SELECT ut.team_id FROM user_teams ut WHERE ut.user_id = $1)
+            OR t.organization_id = (
+                SELECT u.organization_id FROM users u WHERE u.id = $1 AND u.organization_role IS NOT NULL
+            )
+        ORDER BY
+            p.created_at
+        "#,
+        session.user_id
     )
     .fetch_all(&mut *conn)
     .await
@@ -71,9 +102,17 @@ pub struct PipelineCreateResponse {
 
 pub async fn create(
     DatabaseConnection(mut conn): DatabaseConnection,
-    Session(_): Session,
+    Session(session): Session,
     Json(params): Json<PipelineCreateParams>,
 ) -> Result<Json<PipelineCreateResponse>, StatusCode> {
+    authorize_team(
+        &mut conn,
+        session.user_id,
+        params.team_id,
+        MemberRole::Editor,
+    )
+    .await?;
+
     let pipeline = sqlx::query!(
         r#"
         INSERT INTO
@@ -129,7 +168,8 @@ pub struct PipelineDetails {
     id: Uuid,
     name: String,
     description: Option<String>,
-    trigger: PipelineTrigger,
+    contract: PipelineContract,
+    triggers: Vec<PipelineTrigger>,
     nodes: Vec<PipelineNode>,
     connections: Vec<PipelineConnection>,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -168,17 +208,16 @@ pub async fn details(
     Path(id): Path<Uuid>,
     Query(query): Query<PipelineDetailsQuery>,
 ) -> Result<Json<PipelineDetails>, StatusCode> {
+    authorize_pipeline(&mut conn, session.user_id, id, MemberRole::Viewer).await?;
+
     let pipeline = sqlx::query!(
         r#"
         SELECT
-            p.id, p.name, p.description,
-            pt.id AS trigger_id, pt.coords as trigger_coords, pt.config AS trigger_config
+            id, name, description, contract
         FROM
-            pipelines p
-        LEFT JOIN
-            pipeline_triggers pt ON pt.pipeline_id = p.id
+            pipelines
         WHERE
-            p.id = $1
+            id = $1
         "#,
         id
     )
@@ -186,14 +225,37 @@ pub async fn details(
     .await
     .map_err(internal_error)?;
 
+    let contract =
+        serde_json::from_value::<PipelineContract>(pipeline.contract).map_err(internal_error)?;
+
+    let triggers = sqlx::query_as!(
+        PipelineTrigger,
+        r#"
+        SELECT
+            id, coords, config
+        FROM
+            pipeline_triggers
+        WHERE
+            pipeline_id = $1
+        ORDER BY
+            created_at
+        "#,
+        pipeline.id
+    )
+    .fetch_all(&mut *conn)
+    .await
+    .map_err(internal_error)?;
+
     let pipeline_nodes = sqlx::query!(
         r#"
         SELECT
-            pn.id, pn.node_id, pn.node_version, pn.trigger_id, pn.coords,
+            pn.id, pn.node_id, pn.node_version, pn.trigger_id, pn.coords, pn.input_values, n.config,
             pni.id AS "input_id?", pni.key as "input_key?", pno.id AS "output_id?", pno.key AS "output_key?",
             pc.id AS "connection_id?", pc.to_pipeline_node_input_id AS "to_pipeline_node_input_id?", pc.from_pipeline_node_output_id AS "from_pipeline_node_output_id?"
         FROM
             pipeline_nodes pn
+        JOIN
+            nodes n ON n.id = pn.node_id
         LEFT JOIN
             pipeline_node_inputs pni ON pni.pipeline_node_id = pn.id
         LEFT JOIN
@@ -222,6 +284,8 @@ pub async fn details(
                     node_version: row.node_version.clone(),
                     trigger_id: row.trigger_id,
                     coords: row.coords.clone(),
+                    input_values: redact_input_values(row.config.clone(), &row.input_values)
+                        .map_err(internal_error)?,
                     inputs: Vec::new(),
                     outputs: Vec::new(),
                 },
@@ -321,11 +385,8 @@ pub async fn details(
         id: pipeline.id,
         name: pipeline.name.clone(),
         description: pipeline.description.clone(),
-        trigger: PipelineTrigger {
-            id: pipeline.trigger_id,
-            coords: pipeline.trigger_coords.clone(),
-            config: pipeline.trigger_config.clone(),
-        },
+        contract,
+        triggers,
         nodes,
         connections,
         participants,
@@ -340,45 +401,196 @@ pub struct PipelineTriggerResponse {
     pipeline_exec_id: Uuid,
 }
 
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PipelineTriggerQuery {
+    /// Defaults to the oldest trigger of the pipeline allowing manual execution.
+    pub trigger_id: Option<Uuid>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PipelineParamsRejection {
+    pub errors: Vec<PipelineParamError>,
+}
+
 pub async fn trigger(
     Path(id): Path<Uuid>,
     DatabaseConnection(mut db): DatabaseConnection,
     JetStream(jetstream): JetStream,
-    Session(_): Session,
-    Json(params): Json<PipelineExecPayloadParams>,
-) -> Result<Json<PipelineTriggerResponse>, StatusCode> {
+    mut rate_limiter: RateLimiter,
+    Session(session): Session,
+    Query(query): Query<PipelineTriggerQuery>,
+    Json(params): Json<serde_json::Map<String, serde_json::Value>>,
+) -> Result<Response, StatusCode> {
     info!("Received request to trigger pipeline with id: {id}");
 
-    let pipeline_exec = sqlx::query!(
-        r"
-        INSERT INTO pipeline_execs (pipeline_id)
-        VALUES ($1)
-        RETURNING id
-        ",
-        id,
+    authorize_pipeline(&mut db, session.user_id, id, MemberRole::Runner).await?;
+
+    let Some(pipeline) = sqlx::query!(
+        r#"
+        SELECT
+            contract
+        FROM
+            pipelines
+        WHERE
+            id = $1
+        "#,
+        id
     )
-    .fetch_one(&mut *db)
+    .fetch_optional(&mut *db)
     .await
-    .map_err(internal_error)?;
+    .map_err(internal_error)?
+    else {
+        return Err(StatusCode::NOT_FOUND);
+    };
+
+    let trigger_id = find_manual_trigger(&mut db, id, query.trigger_id).await?;
 
-    info!(
-        "Pipeline execution record created with id: {} and params: {params:?}",
-        pipeline_exec.id
+    let contract =
+        serde_json::from_value::<PipelineContract>(pipeline.contract).map_err(internal_error)?;
+
+    let (params, mut errors) = map_pipeline_inputs(&contract, params);
+
+    errors.extend(
+        check_pipeline_exec_params(&mut db, id, Some(trigger_id), &params)
+            .await
+            .map_err(internal_error)?,
     );
 
-    let payload = serde_json::to_string(&dtos::PipelineExecPayload {
-        pipeline_id: id,
-        pipeline_exec_id: pipeline_exec.id,
+    if !errors.is_empty() {
+        return Ok((
+            StatusCode::UNPROCESSABLE_ENTITY,
+            Json(PipelineParamsRejection { errors }),
+        )
+            .into_response());
+    }
+
+    match start_pipeline_exec(
+        &mut db,
+        &jetstream,
+        &mut rate_limiter,
+        id,
+        Some(trigger_id),
         params,
+    )
+    .await?
+    {
+        Ok(pipeline_exec_id) => {
+            Ok(Json(PipelineTriggerResponse { pipeline_exec_id }).into_response())
+        }
+        Err(rate_limited) => Ok(rate_limited.into_response()),
+    }
+}
+
+pub async fn update_contract(
+    DatabaseConnection(mut conn): DatabaseConnection,
+    Session(session): Session,
+    Path(id): Path<Uuid>,
+    Json(contract): Json<PipelineContract>,
+) -> Result<Json<PipelineContract>, (StatusCode, Json<ApiError>)> {
+    authorize_pipeline(&mut conn, session.user_id, id, MemberRole::Editor).await?;
+
+    let configs = sqlx::query!(
+        r#"
+        SELECT
+            pn.id, n.config
+        FROM
+            pipeline_nodes pn
+        JOIN
+            nodes n ON n.id = pn.node_id
+        WHERE
+            pn.pipeline_id = $1
+        "#,
+        id
+    )
+    .fetch_all(&mut *conn)
+    .await
+    .map_err(api_error)?
+    .into_iter()
+    .map(|node| {
+        Ok((
+            node.id,
+            serde_json::from_value::<NodeConfig>(node.config)?.into_v1(),
+        ))
     })
-    .map_err(internal_error)?;
+    .collect::<Result<HashMap<_, _>, serde_json::Error>>()
+    .map_err(|error| {
+        (
+            internal_error(&error),
+            Json(ApiError {
+                error: error.to_string(),
+            }),
+        )
+    })?;
+
+    check_pipeline_contract(&contract, &configs)
+        .map_err(|error| (StatusCode::UNPROCESSABLE_ENTITY, Json(ApiError { error })))?;
 
-    jetstream
-        .publish("pipeline.exec", payload.into())
+    let contract_value = serde_json::to_value(&contract).map_err(|error| {
+        (
+            internal_error(&error),
+            Json(ApiError {
+                error: error.to_string(),
+            }),
+        )
+    })?;
+
+    let updated = sqlx::query!(
+        r#"
+        UPDATE
+            pipelines
+        SET
+            contract = $1
+        WHERE
+            id = $2
+        RETURNING
+            id
+        "#,
+        contract_value,
+        id
+    )
+    .fetch_optional(&mut *conn)
+    .await
+    .map_err(api_error)?;
+
+    if updated.is_none() {
+        return Err((
+            StatusCode::NOT_FOUND,
+            Json(ApiError {
+                error: "Pipeline not found".to_string(),
+            }),
+        ));
+    }
+
+    Ok(Json(contract))
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PipelineValidation {
+    valid: bool,
+    problems: Vec<PipelineGraphProblem>,
+}
+
+pub async fn validate(
+    DatabaseConnection(mut conn): DatabaseConnection,
+    Session(session): Session,
+    Path(id): Path<Uuid>,
+) -> Result<Json<PipelineValidation>, StatusCode> {
+    authorize_pipeline(&mut conn, session.user_id, id, MemberRole::Viewer).await?;
+
+    let connections = fetch_graph_connections(&mut conn, id)
         .await
         .map_err(internal_error)?;
+    let inputs = fetch_graph_inputs(&mut conn, id)
+        .await
+        .map_err(internal_error)?;
+
+    let problems = find_graph_problems(id, &connections, &inputs);
 
-    Ok(Json(PipelineTriggerResponse {
-        pipeline_exec_id: pipeline_exec.id,
+    Ok(Json(PipelineValidation {
+        valid: problems.is_empty(),
+        problems,
     }))
 }
@@ -0,0 +1,284 @@
+use axum::{extract::Path, Json};
+use db::dtos::MemberRole;
+use hyper::StatusCode;
+use serde::{Deserialize, Serialize};
+use sqlx::{Acquire, PgConnection};
+use uuid::Uuid;
+
+use crate::{
+    app_state::DatabaseConnection,
+    extractors::session::Session,
+    lib::{
+        api_error::{api_error, ApiError},
+        authorization::{authorize_team, check_role_change},
+    },
+    routes::api::v0::{
+        api_tokens::{
+            fetch_api_tokens, issue_api_token, revoke_api_token, ApiToken, ApiTokenCreate,
+            CreatedApiToken,
+        },
+        organizations::check_name,
+    },
+};
+
+/// Service accounts have no email address, this domain can't receive any.
+const SERVICE_ACCOUNT_EMAIL_DOMAIN: &str = "service-accounts.invalid";
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ServiceAccount {
+    pub id: Uuid,
+    pub team_id: Uuid,
+    pub name: String,
+    pub role: MemberRole,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+#[derive(Deserialize)]
+pub struct ServiceAccountCreate {
+    name: String,
+    role: MemberRole,
+}
+
+/// Service account of the team, `404` when it belongs to another one.
+async fn find_service_account(
+    db: &mut PgConnection,
+    team_id: Uuid,
+    id: Uuid,
+) -> Result<ServiceAccount, (StatusCode, Json<ApiError>)> {
+    sqlx::query_as!(
+        ServiceAccount,
+        r#"
+        SELECT
+            u.id,
+            ut.team_id,
+            u.name,
+            ut.role AS "role: MemberRole",
+            u.created_at AS "created_at!: chrono::DateTime<chrono::Utc>"
+        FROM
+            users u
+        JOIN
+            user_teams ut ON ut.user_id = u.id AND ut.team_id = u.service_account_team_id
+        WHERE
+            u.id = $1 AND u.service_account_team_id = $2
+        "#,
+        id,
+        team_id
+    )
+    .fetch_optional(&mut *db)
+    .await
+    .map_err(api_error)?
+    .ok_or_else(|| {
+        (
+            StatusCode::NOT_FOUND,
+            Json(ApiError {
+                error: "The service account doesn't belong to the team".to_string(),
+            }),
+        )
+    })
+}
+
+pub async fn list(
+    DatabaseConnection(mut conn): DatabaseConnection,
+    Session(session): Session,
+    Path(id): Path<Uuid>,
+) -> Result<Json<Vec<ServiceAccount>>, (StatusCode, Json<ApiError>)> {
+    authorize_team(&mut conn, session.user_id, id, MemberRole::Admin).await?;
+
+    let service_accounts = sqlx::query_as!(
+        ServiceAccount,
+        r#"
+        SELECT
+            u.id,
+            ut.team_id,
+            u.name,
+            ut.role AS "role: MemberRole",
+            u.created_at AS "created_at!: chrono::DateTime<chrono::Utc>"
+        FROM
+            users u
+        JOIN
+            user_teams ut ON ut.user_id = u.id AND ut.team_id = u.service_account_team_id
+        WHERE
+            u.service_account_team_id = $1
+        ORDER BY
+            u.created_at
+        "#,
+        id
+    )
+    .fetch_all(&mut *conn)
+    .await
+    .map_err(api_error)?;
+
+    Ok(Json(service_accounts))
+}
+
+/// Creates a user owned by the team that only acts through api tokens, with the given role in the
+/// team. Service accounts are meant for automation, so they can't be admins.
+pub async fn create(
+    DatabaseConnection(mut conn): DatabaseConnection,
+    Session(session): Session,
+    Path(id): Path<Uuid>,
+    Json(params): Json<ServiceAccountCreate>,
+) -> Result<(StatusCode, Json<ServiceAccount>), (StatusCode, Json<ApiError>)> {
+    let caller_role = authorize_team(&mut conn, session.user_id, id, MemberRole::Admin).await?;
+
+    let name = check_name(&params.name)?;
+
+    if params.role > MemberRole::Editor {
+        return Err((
+            StatusCode::UNPROCESSABLE_ENTITY,
+            Json(ApiError {
+                error: "Service accounts can have the viewer, runner or editor role".to_string(),
+            }),
+        ));
+    }
+
+    check_role_change(caller_role, None, Some(params.role))?;
+
+    let mut tx = conn.begin().await.map_err(api_error)?;
+
+    let user_id = Uuid::new_v4();
+
+    let user = sqlx::query!(
+        r#"
+        INSERT INTO
+            users (id, organization_id, name, email, email_verified, service_account_team_id)
+        SELECT
+            $1, t.organization_id, $2, $3, TRUE, t.id
+        FROM
+            teams t
+        WHERE
+            t.id = $4
+        RETURNING
+            created_at AS "created_at!: chrono::DateTime<chrono::Utc>"
+        "#,
+        user_id,
+        name,
+        format!("{user_id}@{SERVICE_ACCOUNT_EMAIL_DOMAIN}"),
+        id
+    )
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(|error| match error {
+        sqlx::Error::Database(ref database_error) if database_error.is_unique_violation() => (
+            StatusCode::CONFLICT,
+            Json(ApiError {
+                error: "The name is already taken".to_string(),
+            }),
+        ),
+        error => api_error(error),
+    })?;
+
+    sqlx::query!(
+        r#"
+        INSERT INTO
+            user_teams (user_id, team_id, role)
+        VALUES
+            ($1, $2, $3)
+        "#,
+        user_id,
+        id,
+        params.role as MemberRole
+    )
+    .execute(&mut *tx)
+    .await
+    .map_err(api_error)?;
+
+    tx.commit().await.map_err(api_error)?;
+
+    Ok((
+        StatusCode::CREATED,
+        Json(ServiceAccount {
+            id: user_id,
+            team_id: id,
+            name: name.to_string(),
+            role: params.role,
+            created_at: user.created_at,
+        }),
+    ))
+}
+
+/// Deletes the service account along with its api tokens.
+pub async fn delete(
+    DatabaseConnection(mut conn): DatabaseConnection,
+    Session(session): Session,
+    Path((id, service_account_id)): Path<(Uuid, Uuid)>,
+) -> Result<StatusCode, (StatusCode, Json<ApiError>)> {
+    let caller_role = authorize_team(&mut conn, session.user_id, id, MemberRole::Admin).await?;
+    let service_account = find_service_account(&mut conn, id, service_account_id).await?;
+
+    check_role_change(caller_role, Some(service_account.role), None)?;
+
+    let mut tx = conn.begin().await.map_err(api_error)?;
+
+    sqlx::query!(
+        r#"
+        DELETE FROM
+            user_teams
+        WHERE
+            user_id = $1
+        "#,
+        service_account.id
+    )
+    .execute(&mut *tx)
+    .await
+    .map_err(api_error)?;
+
+    sqlx::query!(
+        r#"
+        DELETE FROM
+            users
+        WHERE
+            id = $1
+        "#,
+        service_account.id
+    )
+    .execute(&mut *tx)
+    .await
+    .map_err(api_error)?;
+
+    tx.commit().await.map_err(api_error)?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn list_tokens(
+    DatabaseConnection(mut conn): DatabaseConnection,
+    Session(session): Session,
+    Path((id, service_account_id)): Path<(Uuid, Uuid)>,
+) -> Result<Json<Vec<ApiToken>>, (StatusCode, Json<ApiError>)> {
+    authorize_team(&mut conn, session.user_id, id, MemberRole::Admin).await?;
+    let service_account = find_service_account(&mut conn, id, service_account_id).await?;
+
+    fetch_api_tokens(&mut conn, service_account.id)
+        .await
+        .map(Json)
+}
+
+/// Issues a token acting as the service account, within its scopes.
+pub async fn create_token(
+    DatabaseConnection(mut conn): DatabaseConnection,
+    Session(session): Session,
+    Path((id, service_account_id)): Path<(Uuid, Uuid)>,
+    Json(params): Json<ApiTokenCreate>,
+) -> Result<(StatusCode, Json<CreatedApiToken>), (StatusCode, Json<ApiError>)> {
+    let caller_role = authorize_team(&mut conn, session.user_id, id, MemberRole::Admin).await?;
+    let service_account = find_service_account(&mut conn, id, service_account_id).await?;
+
+    check_role_change(caller_role, Some(service_account.role), None)?;
+
+    let api_token = issue_api_token(&mut conn, service_account.id, session.user_id, params).await?;
+
+    Ok((StatusCode::CREATED, Json(api_token)))
+}
+
+pub async fn revoke_token(
+    DatabaseConnection(mut conn): DatabaseConnection,
+    Session(session): Session,
+    Path((id, service_account_id, token_id)): Path<(Uuid, Uuid, Uuid)>,
+) -> Result<StatusCode, (StatusCode, Json<ApiError>)> {
+    authorize_team(&mut conn, session.user_id, id, MemberRole::Admin).await?;
+    let service_account = find_service_account(&mut conn, id, service_account_id).await?;
+
+    revoke_api_token(&mut conn, service_account.id, token_id).await
+}
@@ -1,17 +1,31 @@
-use axum::Json;
+use axum::{extract::Path, Json};
+use db::dtos::MemberRole;
 use hyper::StatusCode;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
-use crate::{app_state::DatabaseConnection, extractors::session::Session, utils::internal_error};
+use crate::{
+    app_state::DatabaseConnection,
+    extractors::session::Session,
+    lib::{
+        api_error::{api_error, ApiError},
+        authorization::{authorize_organization, authorize_team, check_role_change},
+    },
+    routes::api::v0::organizations::{check_name, check_two_factor_policy_change, TwoFactorPolicy},
+    utils::internal_error,
+};
 
 #[derive(Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct TeamListItem {
     pub id: Uuid,
     pub name: String,
+    /// Role of the caller in the team, the highest of their team and organization roles.
+    pub role: MemberRole,
 }
 
+/// Teams the caller is a member of, along with every team of their organization when they have an
+/// organization role.
 pub async fn list(
     DatabaseConnection(mut conn): DatabaseConnection,
     Session(session): Session,
@@ -19,13 +33,16 @@ pub async fn list(
     let teams = sqlx::query!(
         r#"
             SELECT
-                teams.id, teams.name
+                t.id, t.name, team_member_role($1, t.id) AS "role!: MemberRole"
             FROM
-                user_teams
-            JOIN
-                teams ON user_teams.team_id = teams.id
+                teams t
             WHERE
-                user_teams.user_id = $1
+                t.id IN (SELECT ut.team_id FROM user_teams ut WHERE ut.user_id = $1)
+                OR t.organization_id = (
+                    SELECT u.organization_id FROM users u WHERE u.id = $1 AND u.organization_role IS NOT NULL
+                )
+            ORDER BY
+                t.created_at
         "#,
         session.user_id
     )
@@ -36,6 +53,7 @@ pub async fn list(
             .map(|row| TeamListItem {
                 id: row.id,
                 name: row.name.clone(),
+                role: row.role,
             })
             .collect::<Vec<TeamListItem>>()
     })
@@ -47,3 +65,301 @@ pub async fn list(
 
     Ok(Json(teams))
 }
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TeamRoleAssign {
+    user_id: Uuid,
+    role: MemberRole,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TeamMember {
+    pub user_id: Uuid,
+    pub team_id: Uuid,
+    pub role: MemberRole,
+}
+
+/// Adds a user of the team's organization to the team with the given role, or changes their role
+/// when they are already a member.
+pub async fn assign_role(
+    DatabaseConnection(mut conn): DatabaseConnection,
+    Session(session): Session,
+    Path(id): Path<Uuid>,
+    Json(params): Json<TeamRoleAssign>,
+) -> Result<Json<TeamMember>, (StatusCode, Json<ApiError>)> {
+    let caller_role = authorize_team(&mut conn, session.user_id, id, MemberRole::Admin).await?;
+
+    let Some(member) = sqlx::query!(
+        r#"
+        SELECT
+            ut.role AS "role?: MemberRole"
+        FROM
+            users u
+        JOIN
+            teams t ON t.organization_id = u.organization_id
+        LEFT JOIN
+            user_teams ut ON ut.user_id = u.id AND ut.team_id = t.id
+        WHERE
+            u.id = $1 AND t.id = $2
+        "#,
+        params.user_id,
+        id
+    )
+    .fetch_optional(&mut *conn)
+    .await
+    .map_err(api_error)?
+    else {
+        return Err((
+            StatusCode::UNPROCESSABLE_ENTITY,
+            Json(ApiError {
+                error: "The user isn't part of the team's organization".to_string(),
+            }),
+        ));
+    };
+
+    check_role_change(caller_role, member.role, Some(params.role))?;
+
+    let member = sqlx::query!(
+        r#"
+        INSERT INTO
+            user_teams (user_id, team_id, role)
+        VALUES
+            ($1, $2, $3)
+        ON CONFLICT (user_id, team_id) DO UPDATE SET
+            role = EXCLUDED.role
+        RETURNING
+            user_id, team_id, role AS "role: MemberRole"
+        "#,
+        params.user_id,
+        id,
+        params.role as MemberRole
+    )
+    .fetch_one(&mut *conn)
+    .await
+    .map_err(api_error)?;
+
+    Ok(Json(TeamMember {
+        user_id: member.user_id,
+        team_id: member.team_id,
+        role: member.role,
+    }))
+}
+
+/// Removes the user from the team, their organization role still applies.
+pub async fn revoke_role(
+    DatabaseConnection(mut conn): DatabaseConnection,
+    Session(session): Session,
+    Path((id, user_id)): Path<(Uuid, Uuid)>,
+) -> Result<StatusCode, (StatusCode, Json<ApiError>)> {
+    let caller_role = authorize_team(&mut conn, session.user_id, id, MemberRole::Admin).await?;
+
+    let Some(member) = sqlx::query!(
+        r#"
+        SELECT
+            role AS "role: MemberRole"
+        FROM
+            user_teams
+        WHERE
+            user_id = $1 AND team_id = $2
+        "#,
+        user_id,
+        id
+    )
+    .fetch_optional(&mut *conn)
+    .await
+    .map_err(api_error)?
+    else {
+        return Err((
+            StatusCode::NOT_FOUND,
+            Json(ApiError {
+                error: "The user isn't a member of the team".to_string(),
+            }),
+        ));
+    };
+
+    check_role_change(caller_role, Some(member.role), None)?;
+
+    sqlx::query!(
+        r#"
+        DELETE FROM
+            user_teams
+        WHERE
+            user_id = $1 AND team_id = $2
+        "#,
+        user_id,
+        id
+    )
+    .execute(&mut *conn)
+    .await
+    .map_err(api_error)?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TeamCreate {
+    organization_id: Uuid,
+    name: String,
+}
+
+#[derive(Deserialize)]
+pub struct TeamUpdate {
+    name: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Team {
+    pub id: Uuid,
+    pub organization_id: Uuid,
+    pub name: String,
+}
+
+/// Creates a team in the organization, its admins reach it through their organization role.
+pub async fn create(
+    DatabaseConnection(mut conn): DatabaseConnection,
+    Session(session): Session,
+    Json(params): Json<TeamCreate>,
+) -> Result<(StatusCode, Json<Team>), (StatusCode, Json<ApiError>)> {
+    authorize_organization(
+        &mut conn,
+        session.user_id,
+        params.organization_id,
+        MemberRole::Admin,
+    )
+    .await?;
+
+    let name = check_name(&params.name)?;
+
+    let team = sqlx::query_as!(
+        Team,
+        r#"
+        INSERT INTO
+            teams (organization_id, name)
+        VALUES
+            ($1, $2)
+        RETURNING
+            id, organization_id, name
+        "#,
+        params.organization_id,
+        name
+    )
+    .fetch_one(&mut *conn)
+    .await
+    .map_err(api_error)?;
+
+    Ok((StatusCode::CREATED, Json(team)))
+}
+
+pub async fn update(
+    DatabaseConnection(mut conn): DatabaseConnection,
+    Session(session): Session,
+    Path(id): Path<Uuid>,
+    Json(params): Json<TeamUpdate>,
+) -> Result<Json<Team>, (StatusCode, Json<ApiError>)> {
+    authorize_team(&mut conn, session.user_id, id, MemberRole::Admin).await?;
+
+    let name = check_name(&params.name)?;
+
+    let team = sqlx::query_as!(
+        Team,
+        r#"
+        UPDATE
+            teams
+        SET
+            name = $1
+        WHERE
+            id = $2
+        RETURNING
+            id, organization_id, name
+        "#,
+        name,
+        id
+    )
+    .fetch_one(&mut *conn)
+    .await
+    .map_err(api_error)?;
+
+    Ok(Json(team))
+}
+
+/// Requires every member of the team to enable two-factor authentication before reaching its
+/// resources, the organization's policy applies as well.
+pub async fn update_two_factor_policy(
+    DatabaseConnection(mut conn): DatabaseConnection,
+    Session(session): Session,
+    Path(id): Path<Uuid>,
+    Json(params): Json<TwoFactorPolicy>,
+) -> Result<Json<TwoFactorPolicy>, (StatusCode, Json<ApiError>)> {
+    authorize_team(&mut conn, session.user_id, id, MemberRole::Admin).await?;
+    check_two_factor_policy_change(&mut conn, session.user_id, &params).await?;
+
+    let required = sqlx::query_scalar!(
+        r#"
+        UPDATE
+            teams
+        SET
+            require_two_factor = $1
+        WHERE
+            id = $2
+        RETURNING
+            require_two_factor
+        "#,
+        params.required,
+        id
+    )
+    .fetch_one(&mut *conn)
+    .await
+    .map_err(api_error)?;
+
+    Ok(Json(TwoFactorPolicy { required }))
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TriggerQuota {
+    /// Pipeline triggers per minute, the api default applies when unset.
+    pub per_minute: Option<i32>,
+}
+
+/// Sets how many pipelines the team can trigger per minute, over it triggers get a `429`.
+pub async fn update_trigger_quota(
+    DatabaseConnection(mut conn): DatabaseConnection,
+    Session(session): Session,
+    Path(id): Path<Uuid>,
+    Json(params): Json<TriggerQuota>,
+) -> Result<Json<TriggerQuota>, (StatusCode, Json<ApiError>)> {
+    authorize_team(&mut conn, session.user_id, id, MemberRole::Admin).await?;
+
+    if params.per_minute.is_some_and(|per_minute| per_minute <= 0) {
+        return Err((
+            StatusCode::UNPROCESSABLE_ENTITY,
+            Json(ApiError {
+                error: "The quota must be positive".to_string(),
+            }),
+        ));
+    }
+
+    let per_minute = sqlx::query_scalar!(
+        r#"
+        UPDATE
+            teams
+        SET
+            trigger_rate_limit_per_minute = $1
+        WHERE
+            id = $2
+        RETURNING
+            trigger_rate_limit_per_minute
+        "#,
+        params.per_minute,
+        id
+    )
+    .fetch_one(&mut *conn)
+    .await
+    .map_err(api_error)?;
+
+    Ok(Json(TriggerQuota { per_minute }))
+}
@@ -0,0 +1,455 @@
+use axum::{
+    response::{IntoResponse, Response},
+    Json,
+};
+use hyper::StatusCode;
+use redis::AsyncCommands;
+use serde::{Deserialize, Serialize};
+use sqlx::Acquire;
+use tracing::error;
+use uuid::Uuid;
+
+use crate::{
+    app_state::{DatabaseConnection, RedisConnection},
+    extractors::session::Session,
+    lib::{
+        api_error::{api_error, ApiError},
+        rate_limit::{
+            check_login_lockout, clear_login_failures, record_login_failure, RateLimitConfig,
+        },
+        session::{create_session, generate_session_token, ClientInfo},
+        token::hash_token,
+        totp::{
+            count_login_challenge_attempt, generate_totp_secret, is_two_factor_required,
+            provisioning_uri, replace_recovery_codes, to_redis_login_challenge_attempts_key,
+            to_redis_login_challenge_key, use_second_factor, verify_totp_code, LoginChallenge,
+            LOGIN_CHALLENGE_MAX_ATTEMPTS,
+        },
+    },
+    routes::api::v0::auth::LoginResponse,
+    utils::internal_error,
+};
+
+fn two_factor_error(status: StatusCode, error: &str) -> (StatusCode, Json<ApiError>) {
+    (
+        status,
+        Json(ApiError {
+            error: error.to_string(),
+        }),
+    )
+}
+
+fn redis_error(error: impl std::fmt::Debug) -> (StatusCode, Json<ApiError>) {
+    (
+        internal_error(error),
+        Json(ApiError {
+            error: "Failed to access the login".to_string(),
+        }),
+    )
+}
+
+struct TwoFactorState {
+    email: String,
+    totp_secret: Option<String>,
+    enabled: bool,
+}
+
+async fn fetch_two_factor_state(
+    db: &mut sqlx::PgConnection,
+    user_id: Uuid,
+) -> Result<TwoFactorState, (StatusCode, Json<ApiError>)> {
+    sqlx::query_as!(
+        TwoFactorState,
+        r#"
+        SELECT
+            email,
+            totp_secret,
+            totp_enabled_at IS NOT NULL AS "enabled!"
+        FROM
+            users
+        WHERE
+            id = $1
+        "#,
+        user_id
+    )
+    .fetch_one(&mut *db)
+    .await
+    .map_err(api_error)
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TwoFactorEnrollment {
+    /// Base32 secret, for authenticator apps that can't scan the provisioning uri.
+    pub secret: String,
+    pub provisioning_uri: String,
+}
+
+/// Generates a new TOTP secret for the caller, pending until `confirm` checks a first code.
+pub async fn enroll(
+    DatabaseConnection(mut conn): DatabaseConnection,
+    Session(session): Session,
+) -> Result<Json<TwoFactorEnrollment>, (StatusCode, Json<ApiError>)> {
+    let state = fetch_two_factor_state(&mut conn, session.user_id).await?;
+
+    if state.enabled {
+        return Err(two_factor_error(
+            StatusCode::CONFLICT,
+            "Two-factor authentication is already enabled",
+        ));
+    }
+
+    let secret = generate_totp_secret();
+
+    sqlx::query!(
+        r#"
+        UPDATE
+            users
+        SET
+            totp_secret = $1
+        WHERE
+            id = $2
+        "#,
+        secret,
+        session.user_id
+    )
+    .execute(&mut *conn)
+    .await
+    .map_err(api_error)?;
+
+    Ok(Json(TwoFactorEnrollment {
+        provisioning_uri: provisioning_uri(&secret, &state.email),
+        secret,
+    }))
+}
+
+#[derive(Deserialize)]
+pub struct TwoFactorCode {
+    code: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RecoveryCodes {
+    /// Only returned this once, each replaces a TOTP code a single time.
+    pub recovery_codes: Vec<String>,
+}
+
+/// Enables two-factor authentication once a code of the enrolled secret checks out.
+pub async fn confirm(
+    DatabaseConnection(mut conn): DatabaseConnection,
+    Session(session): Session,
+    Json(params): Json<TwoFactorCode>,
+) -> Result<Json<RecoveryCodes>, (StatusCode, Json<ApiError>)> {
+    let state = fetch_two_factor_state(&mut conn, session.user_id).await?;
+
+    if state.enabled {
+        return Err(two_factor_error(
+            StatusCode::CONFLICT,
+            "Two-factor authentication is already enabled",
+        ));
+    }
+
+    let Some(totp_secret) = state.totp_secret else {
+        return Err(two_factor_error(
+            StatusCode::CONFLICT,
+            "Enroll in two-factor authentication first",
+        ));
+    };
+
+    let Some(step) = verify_totp_code(&totp_secret, &params.code, chrono::Utc::now().timestamp())
+    else {
+        return Err(two_factor_error(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "The code is invalid",
+        ));
+    };
+
+    let mut tx = conn.begin().await.map_err(api_error)?;
+
+    let enabled = sqlx::query!(
+        r#"
+        UPDATE
+            users
+        SET
+            totp_enabled_at = CURRENT_TIMESTAMP,
+            totp_last_used_step = $2
+        WHERE
+            id = $1 AND totp_secret = $3 AND totp_enabled_at IS NULL
+        "#,
+        session.user_id,
+        step,
+        totp_secret
+    )
+    .execute(&mut *tx)
+    .await
+    .map_err(api_error)?
+    .rows_affected();
+
+    // Enrolled again or confirmed concurrently
+    if enabled == 0 {
+        return Err(two_factor_error(
+            StatusCode::CONFLICT,
+            "The enrollment changed, enroll again",
+        ));
+    }
+
+    let recovery_codes = replace_recovery_codes(&mut tx, session.user_id)
+        .await
+        .map_err(api_error)?;
+
+    tx.commit().await.map_err(api_error)?;
+
+    Ok(Json(RecoveryCodes { recovery_codes }))
+}
+
+/// Disables two-factor authentication, with a TOTP or recovery code. Members of a team or
+/// organization requiring it can't.
+pub async fn disable(
+    DatabaseConnection(mut conn): DatabaseConnection,
+    Session(session): Session,
+    Json(params): Json<TwoFactorCode>,
+) -> Result<StatusCode, (StatusCode, Json<ApiError>)> {
+    let state = fetch_two_factor_state(&mut conn, session.user_id).await?;
+
+    if !state.enabled {
+        return Err(two_factor_error(
+            StatusCode::CONFLICT,
+            "Two-factor authentication isn't enabled",
+        ));
+    }
+
+    if is_two_factor_required(&mut conn, session.user_id)
+        .await
+        .map_err(api_error)?
+    {
+        return Err(two_factor_error(
+            StatusCode::CONFLICT,
+            "Your team or organization requires two-factor authentication",
+        ));
+    }
+
+    let mut tx = conn.begin().await.map_err(api_error)?;
+
+    if !use_second_factor(&mut tx, session.user_id, &params.code)
+        .await
+        .map_err(api_error)?
+    {
+        return Err(two_factor_error(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "The code is invalid",
+        ));
+    }
+
+    sqlx::query!(
+        r#"
+        UPDATE
+            users
+        SET
+            totp_secret = NULL,
+            totp_enabled_at = NULL,
+            totp_last_used_step = NULL
+        WHERE
+            id = $1
+        "#,
+        session.user_id
+    )
+    .execute(&mut *tx)
+    .await
+    .map_err(api_error)?;
+
+    sqlx::query!(
+        r#"
+        DELETE FROM
+            recovery_codes
+        WHERE
+            user_id = $1
+        "#,
+        session.user_id
+    )
+    .execute(&mut *tx)
+    .await
+    .map_err(api_error)?;
+
+    tx.commit().await.map_err(api_error)?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Replaces the recovery codes, with a TOTP code.
+pub async fn regenerate_recovery_codes(
+    DatabaseConnection(mut conn): DatabaseConnection,
+    Session(session): Session,
+    Json(params): Json<TwoFactorCode>,
+) -> Result<Json<RecoveryCodes>, (StatusCode, Json<ApiError>)> {
+    let state = fetch_two_factor_state(&mut conn, session.user_id).await?;
+
+    let Some(totp_secret) = state.totp_secret.filter(|_| state.enabled) else {
+        return Err(two_factor_error(
+            StatusCode::CONFLICT,
+            "Two-factor authentication isn't enabled",
+        ));
+    };
+
+    // A recovery code can't stand in here, or one leaked code would yield new ones
+    if verify_totp_code(&totp_secret, &params.code, chrono::Utc::now().timestamp()).is_none() {
+        return Err(two_factor_error(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "The code is invalid",
+        ));
+    }
+
+    let mut tx = conn.begin().await.map_err(api_error)?;
+
+    if !use_second_factor(&mut tx, session.user_id, &params.code)
+        .await
+        .map_err(api_error)?
+    {
+        return Err(two_factor_error(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "The code was already used",
+        ));
+    }
+
+    let recovery_codes = replace_recovery_codes(&mut tx, session.user_id)
+        .await
+        .map_err(api_error)?;
+
+    tx.commit().await.map_err(api_error)?;
+
+    Ok(Json(RecoveryCodes { recovery_codes }))
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TwoFactorLoginRequest {
+    two_factor_token: String,
+    /// TOTP code or recovery code.
+    code: String,
+}
+
+/// Second step of a login of a user with two-factor authentication enabled. The challenge is
+/// dropped after too many wrong codes, the login has to start over then. Wrong codes count as
+/// failed logins too, toward the lockout of the account and of the address.
+pub async fn login(
+    DatabaseConnection(mut conn): DatabaseConnection,
+    RedisConnection(mut redis): RedisConnection,
+    rate_limit_config: RateLimitConfig,
+    client: ClientInfo,
+    Json(params): Json<TwoFactorLoginRequest>,
+) -> Result<Json<LoginResponse>, Response> {
+    let challenge_id = hash_token(&params.two_factor_token);
+    let challenge_key = to_redis_login_challenge_key(&challenge_id);
+    let attempts_key = to_redis_login_challenge_attempts_key(&challenge_id);
+
+    let challenge: Option<String> = redis
+        .get(&challenge_key)
+        .await
+        .map_err(|error| redis_error(error).into_response())?;
+
+    let Some(challenge) =
+        challenge.and_then(|challenge| serde_json::from_str::<LoginChallenge>(&challenge).ok())
+    else {
+        return Err(
+            two_factor_error(StatusCode::UNAUTHORIZED, "The login expired, log in again")
+                .into_response(),
+        );
+    };
+
+    let attempts = count_login_challenge_attempt(&mut redis, &challenge_id)
+        .await
+        .map_err(|error| redis_error(error).into_response())?;
+
+    if attempts > LOGIN_CHALLENGE_MAX_ATTEMPTS {
+        let _: () = redis
+            .del(&[&challenge_key, &attempts_key])
+            .await
+            .map_err(|error| redis_error(error).into_response())?;
+
+        return Err(
+            two_factor_error(StatusCode::UNAUTHORIZED, "The login expired, log in again")
+                .into_response(),
+        );
+    }
+
+    let email = sqlx::query_scalar!(
+        r#"
+        SELECT
+            email
+        FROM
+            users
+        WHERE
+            id = $1
+        "#,
+        challenge.user_id
+    )
+    .fetch_one(&mut *conn)
+    .await
+    .map_err(|error| api_error(error).into_response())?;
+
+    match check_login_lockout(&mut redis, &rate_limit_config, &email, &client).await {
+        Ok(Ok(())) => {}
+        Ok(Err(rate_limited)) => return Err(rate_limited.into_response()),
+        Err(error) => error!("Failed to check the login lockout: {error}"),
+    }
+
+    if !use_second_factor(&mut conn, challenge.user_id, &params.code)
+        .await
+        .map_err(|error| api_error(error).into_response())?
+    {
+        if let Err(error) =
+            record_login_failure(&mut redis, &rate_limit_config, &email, &client).await
+        {
+            error!("Failed to record the login failure: {error}");
+        }
+
+        if attempts >= LOGIN_CHALLENGE_MAX_ATTEMPTS {
+            let _: () = redis
+                .del(&[&challenge_key, &attempts_key])
+                .await
+                .map_err(|error| redis_error(error).into_response())?;
+        }
+
+        return Err(
+            two_factor_error(StatusCode::UNAUTHORIZED, "The code is invalid").into_response(),
+        );
+    }
+
+    // Each challenge completes a single login
+    let deleted: u32 = redis
+        .del(&challenge_key)
+        .await
+        .map_err(|error| redis_error(error).into_response())?;
+
+    if deleted == 0 {
+        return Err(
+            two_factor_error(StatusCode::UNAUTHORIZED, "The login expired, log in again")
+                .into_response(),
+        );
+    }
+
+    let _: () = redis
+        .del(&attempts_key)
+        .await
+        .map_err(|error| redis_error(error).into_response())?;
+
+    if let Err(error) = clear_login_failures(&mut redis, &email).await {
+        error!("Failed to clear the login failures: {error}");
+    }
+
+    let token = generate_session_token();
+    let session = create_session(
+        redis,
+        &token,
+        challenge.user_id,
+        &challenge.username,
+        client,
+    )
+    .await
+    .map_err(|status| two_factor_error(status, "Failed to create the session").into_response())?;
+
+    Ok(Json(LoginResponse {
+        token,
+        expires_at: session.expires_at,
+    }))
+}
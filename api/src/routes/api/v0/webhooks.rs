@@ -0,0 +1,248 @@
+use axum::{
+    body::Bytes,
+    extract::{Path, Query, State},
+    response::{IntoResponse, Response},
+    Json,
+};
+use db::dtos::{
+    ExecStatus, PipelineExecEvent, PipelineTriggerConfig, PipelineTriggerSource,
+    WebhookResponseConfig,
+};
+use hyper::{HeaderMap, StatusCode};
+use serde::Serialize;
+use sqlx::PgConnection;
+use std::{collections::HashMap, time::Duration};
+use tokio::sync::broadcast::error::RecvError;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::{
+    app_state::{AppState, DatabaseConnection},
+    lib::{
+        pipeline_exec::start_pipeline_exec,
+        rate_limit::RateLimiter,
+        webhook::{map_webhook_params, verify_webhook_request},
+    },
+    utils::internal_error,
+};
+
+const MAX_RESPONSE_TIMEOUT_SECS: u64 = 300;
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WebhookExecResponse {
+    pipeline_exec_id: Uuid,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    status: Option<ExecStatus>,
+}
+
+/// Response once the webhook's response node finished, or the whole execution did without it.
+fn finished_response(
+    pipeline_exec_id: Uuid,
+    response: &WebhookResponseConfig,
+    pipeline_exec_status: Option<ExecStatus>,
+    pipeline_node_exec: Option<(ExecStatus, serde_json::Value)>,
+) -> Option<Response> {
+    match (pipeline_exec_status, pipeline_node_exec) {
+        (_, Some((ExecStatus::Completed, result))) => {
+            let output = match response.output.as_ref() {
+                Some(output) => result.get(output).cloned().unwrap_or_default(),
+                None => result,
+            };
+
+            Some((StatusCode::OK, Json(output)).into_response())
+        }
+        (_, Some((status @ (ExecStatus::Failed | ExecStatus::Cancelled), _)))
+        | (Some(status @ (ExecStatus::Failed | ExecStatus::Cancelled)), _) => Some(
+            (
+                StatusCode::BAD_GATEWAY,
+                Json(WebhookExecResponse {
+                    pipeline_exec_id,
+                    status: Some(status),
+                }),
+            )
+                .into_response(),
+        ),
+        // The execution completed without running the response node
+        (Some(status @ ExecStatus::Completed), _) => Some(
+            (
+                StatusCode::OK,
+                Json(WebhookExecResponse {
+                    pipeline_exec_id,
+                    status: Some(status),
+                }),
+            )
+                .into_response(),
+        ),
+        _ => None,
+    }
+}
+
+/// Looks the execution up, for when its events can't tell whether it finished.
+async fn fetch_finished_response(
+    db: &mut PgConnection,
+    pipeline_exec_id: Uuid,
+    response: &WebhookResponseConfig,
+) -> Result<Option<Response>, StatusCode> {
+    let exec = sqlx::query!(
+        r#"
+        SELECT
+            pe.status AS "pipeline_exec_status: ExecStatus",
+            pne.status AS "pipeline_node_exec_status?: ExecStatus",
+            pne.result
+        FROM
+            pipeline_execs pe
+        LEFT JOIN
+            pipeline_node_execs pne ON pne.pipeline_exec_id = pe.id AND pne.pipeline_node_id = $2
+        WHERE
+            pe.id = $1
+        "#,
+        pipeline_exec_id,
+        response.pipeline_node_id
+    )
+    .fetch_one(&mut *db)
+    .await
+    .map_err(internal_error)?;
+
+    Ok(finished_response(
+        pipeline_exec_id,
+        response,
+        Some(exec.pipeline_exec_status),
+        exec.pipeline_node_exec_status
+            .map(|status| (status, exec.result.unwrap_or_default())),
+    ))
+}
+
+pub async fn handle(
+    Path(path_key): Path<String>,
+    State(state): State<AppState>,
+    DatabaseConnection(mut conn): DatabaseConnection,
+    mut rate_limiter: RateLimiter,
+    Query(query): Query<HashMap<String, String>>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<Response, StatusCode> {
+    let Some(webhook) = sqlx::query!(
+        r#"
+        SELECT
+            ptw.secret, pt.id, pt.pipeline_id, pt.config
+        FROM
+            pipeline_trigger_webhooks ptw
+        JOIN
+            pipeline_triggers pt ON pt.id = ptw.pipeline_trigger_id
+        WHERE
+            ptw.path_key = $1
+        "#,
+        path_key
+    )
+    .fetch_optional(&mut *conn)
+    .await
+    .map_err(internal_error)?
+    else {
+        return Err(StatusCode::NOT_FOUND);
+    };
+
+    let config: PipelineTriggerConfig =
+        serde_json::from_value(webhook.config).map_err(internal_error)?;
+
+    let Some(PipelineTriggerSource::Webhook(webhook_config)) = config.source() else {
+        return Err(StatusCode::NOT_FOUND);
+    };
+
+    if !verify_webhook_request(&webhook_config.auth, &webhook.secret, &headers, &body) {
+        warn!(
+            "Rejected webhook request for pipeline trigger: {}",
+            webhook.id
+        );
+        return Err(StatusCode::UNAUTHORIZED);
+    }
+
+    let params = map_webhook_params(&webhook_config.params, &headers, &query, &body);
+
+    // Subscribed before starting, the events of the execution can't be missed
+    let mut events = state.exec_events_broadcast.0.subscribe();
+    let pipeline_exec_id = match start_pipeline_exec(
+        &mut conn,
+        &state.jetstream,
+        &mut rate_limiter,
+        webhook.pipeline_id,
+        Some(webhook.id),
+        params,
+    )
+    .await?
+    {
+        Ok(pipeline_exec_id) => pipeline_exec_id,
+        Err(rate_limited) => return Ok(rate_limited.into_response()),
+    };
+
+    info!(
+        "Pipeline exec {pipeline_exec_id} started by webhook of pipeline trigger: {}",
+        webhook.id
+    );
+
+    let accepted = (
+        StatusCode::ACCEPTED,
+        Json(WebhookExecResponse {
+            pipeline_exec_id,
+            status: None,
+        }),
+    );
+
+    let Some(response) = webhook_config.response.as_ref() else {
+        return Ok(accepted.into_response());
+    };
+
+    let wait_for_result = async {
+        loop {
+            let response = match events.recv().await {
+                Ok(PipelineExecEvent::Node(pipeline_node_exec))
+                    if pipeline_node_exec.pipeline_exec_id == pipeline_exec_id
+                        && pipeline_node_exec.pipeline_node_id == response.pipeline_node_id =>
+                {
+                    finished_response(
+                        pipeline_exec_id,
+                        response,
+                        None,
+                        Some((pipeline_node_exec.status, pipeline_node_exec.result)),
+                    )
+                }
+                Ok(PipelineExecEvent::Pipeline(pipeline_exec))
+                    if pipeline_exec.id == pipeline_exec_id
+                        && pipeline_exec.status.is_terminal() =>
+                {
+                    // The response node may have finished while the events lagged
+                    fetch_finished_response(&mut conn, pipeline_exec_id, response)
+                        .await?
+                        .or_else(|| {
+                            finished_response(
+                                pipeline_exec_id,
+                                response,
+                                Some(pipeline_exec.status),
+                                None,
+                            )
+                        })
+                }
+                Ok(_) => None,
+                Err(RecvError::Lagged(skipped)) => {
+                    warn!(
+                        "Skipped {skipped} exec events waiting on pipeline exec {pipeline_exec_id}"
+                    );
+                    fetch_finished_response(&mut conn, pipeline_exec_id, response).await?
+                }
+                Err(RecvError::Closed) => return Ok(None),
+            };
+
+            if let Some(response) = response {
+                return Ok(Some(response));
+            }
+        }
+    };
+
+    let timeout = Duration::from_secs(response.timeout_secs.min(MAX_RESPONSE_TIMEOUT_SECS));
+
+    match tokio::time::timeout(timeout, wait_for_result).await {
+        Ok(Ok(Some(response))) => Ok(response),
+        Ok(Err(status)) => Err(status),
+        Ok(Ok(None)) | Err(_) => Ok(accepted.into_response()),
+    }
+}
@@ -0,0 +1,5 @@
+-- Drop triggers
+DROP TRIGGER IF EXISTS set_updated_at_pipeline_trigger_webhooks ON pipeline_trigger_webhooks;
+
+-- Drop tables
+DROP TABLE IF EXISTS pipeline_trigger_webhooks CASCADE;
@@ -0,0 +1,15 @@
+-- Create 'pipeline_trigger_webhooks' table
+CREATE TABLE IF NOT EXISTS pipeline_trigger_webhooks (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    pipeline_trigger_id UUID NOT NULL UNIQUE REFERENCES pipeline_triggers(id) ON DELETE CASCADE,
+    path_key VARCHAR NOT NULL UNIQUE,
+    secret VARCHAR NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP
+);
+
+-- Create triggers
+CREATE TRIGGER set_updated_at_pipeline_trigger_webhooks
+BEFORE UPDATE ON pipeline_trigger_webhooks
+FOR EACH ROW
+EXECUTE FUNCTION update_updated_at_column();
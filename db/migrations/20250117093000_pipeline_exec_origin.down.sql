@@ -0,0 +1,7 @@
+-- Drop indexes
+DROP INDEX IF EXISTS idx_pipeline_execs_pipeline_trigger_id_parent_pipeline_exec_id;
+
+-- Drop columns
+ALTER TABLE pipeline_execs
+    DROP COLUMN IF EXISTS parent_pipeline_exec_id,
+    DROP COLUMN IF EXISTS pipeline_trigger_id;
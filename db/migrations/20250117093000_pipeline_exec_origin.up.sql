@@ -0,0 +1,8 @@
+-- Track what started each pipeline execution
+ALTER TABLE pipeline_execs
+    ADD COLUMN pipeline_trigger_id UUID REFERENCES pipeline_triggers(id) ON DELETE SET NULL,
+    ADD COLUMN parent_pipeline_exec_id UUID REFERENCES pipeline_execs(id) ON DELETE SET NULL;
+
+-- Create indexes
+CREATE UNIQUE INDEX IF NOT EXISTS idx_pipeline_execs_pipeline_trigger_id_parent_pipeline_exec_id
+    ON pipeline_execs(pipeline_trigger_id, parent_pipeline_exec_id);
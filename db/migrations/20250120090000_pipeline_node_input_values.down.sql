@@ -0,0 +1,3 @@
+-- Drop columns
+ALTER TABLE pipeline_nodes
+    DROP COLUMN IF EXISTS input_values;
@@ -0,0 +1,3 @@
+-- Store input values set on each pipeline node, keyed by input key
+ALTER TABLE pipeline_nodes
+    ADD COLUMN input_values JSONB NOT NULL DEFAULT '{}'::jsonb;
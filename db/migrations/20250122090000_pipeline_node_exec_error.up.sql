@@ -0,0 +1,3 @@
+-- Keep the reason a node execution failed
+ALTER TABLE pipeline_node_execs
+    ADD COLUMN error TEXT;
@@ -0,0 +1,6 @@
+-- Drop columns
+ALTER TABLE pipeline_execs
+    DROP COLUMN IF EXISTS output;
+
+ALTER TABLE pipelines
+    DROP COLUMN IF EXISTS contract;
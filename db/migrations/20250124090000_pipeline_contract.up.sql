@@ -0,0 +1,7 @@
+-- Named inputs and outputs of each pipeline
+ALTER TABLE pipelines
+    ADD COLUMN contract JSONB NOT NULL DEFAULT '{"version": "v0", "inputs": [], "outputs": []}'::jsonb;
+
+-- Keep the output document of each pipeline execution
+ALTER TABLE pipeline_execs
+    ADD COLUMN output JSONB;
@@ -0,0 +1,6 @@
+-- Drop indexes
+DROP INDEX IF EXISTS idx_pipeline_execs_pipeline_id_created_at;
+
+-- Drop columns
+ALTER TABLE pipeline_execs
+    DROP COLUMN IF EXISTS params;
@@ -0,0 +1,7 @@
+-- Keep the trigger params each pipeline execution was started with
+ALTER TABLE pipeline_execs
+    ADD COLUMN params JSONB NOT NULL DEFAULT '{}';
+
+-- Create indexes
+CREATE INDEX IF NOT EXISTS idx_pipeline_execs_pipeline_id_created_at
+    ON pipeline_execs(pipeline_id, created_at DESC);
@@ -0,0 +1,3 @@
+-- Drop columns
+ALTER TABLE pipeline_node_execs
+    DROP COLUMN IF EXISTS params;
@@ -0,0 +1,3 @@
+-- Keep the params each node execution was dispatched with, secret inputs redacted
+ALTER TABLE pipeline_node_execs
+    ADD COLUMN params JSONB;
@@ -0,0 +1,6 @@
+-- Drop indexes
+DROP INDEX IF EXISTS idx_pipeline_execs_retry_of_pipeline_exec_id;
+
+-- Drop columns
+ALTER TABLE pipeline_execs
+    DROP COLUMN IF EXISTS retry_of_pipeline_exec_id;
@@ -0,0 +1,7 @@
+-- Track the failed execution a retry reuses the results of
+ALTER TABLE pipeline_execs
+    ADD COLUMN retry_of_pipeline_exec_id UUID REFERENCES pipeline_execs(id) ON DELETE SET NULL;
+
+-- Create indexes
+CREATE INDEX IF NOT EXISTS idx_pipeline_execs_retry_of_pipeline_exec_id
+    ON pipeline_execs(retry_of_pipeline_exec_id);
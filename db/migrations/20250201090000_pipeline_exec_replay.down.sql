@@ -0,0 +1,10 @@
+-- Drop indexes
+DROP INDEX IF EXISTS idx_pipeline_execs_replay_of_pipeline_exec_id;
+
+-- Drop columns
+ALTER TABLE pipeline_execs
+    DROP COLUMN IF EXISTS pinned_node_versions,
+    DROP COLUMN IF EXISTS replay_of_pipeline_exec_id;
+
+ALTER TABLE pipeline_node_execs
+    DROP COLUMN IF EXISTS node_version;
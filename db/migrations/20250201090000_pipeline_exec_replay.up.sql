@@ -0,0 +1,12 @@
+-- Track the node version each node execution ran with
+ALTER TABLE pipeline_node_execs
+    ADD COLUMN node_version VARCHAR;
+
+-- Track the execution a replay starts over, and the node versions it's pinned to
+ALTER TABLE pipeline_execs
+    ADD COLUMN replay_of_pipeline_exec_id UUID REFERENCES pipeline_execs(id) ON DELETE SET NULL,
+    ADD COLUMN pinned_node_versions JSONB;
+
+-- Create indexes
+CREATE INDEX IF NOT EXISTS idx_pipeline_execs_replay_of_pipeline_exec_id
+    ON pipeline_execs(replay_of_pipeline_exec_id);
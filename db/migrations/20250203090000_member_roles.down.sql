@@ -0,0 +1,9 @@
+-- Drop functions
+DROP FUNCTION IF EXISTS team_member_role(UUID, UUID);
+
+-- Drop columns
+ALTER TABLE users DROP COLUMN IF EXISTS organization_role;
+ALTER TABLE user_teams DROP COLUMN IF EXISTS role;
+
+-- Drop types
+DROP TYPE IF EXISTS member_role;
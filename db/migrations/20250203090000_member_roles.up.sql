@@ -0,0 +1,26 @@
+-- Create enum type for member roles, ordered from the least to the most privileged
+CREATE TYPE member_role AS ENUM ('viewer', 'runner', 'editor', 'admin', 'owner');
+
+-- Add columns
+ALTER TABLE user_teams ADD COLUMN role member_role NOT NULL DEFAULT 'owner';
+ALTER TABLE user_teams ALTER COLUMN role SET DEFAULT 'viewer';
+
+ALTER TABLE users ADD COLUMN organization_role member_role;
+
+-- Create functions
+CREATE OR REPLACE FUNCTION team_member_role(member_user_id UUID, member_team_id UUID)
+RETURNS member_role AS $$
+    SELECT GREATEST(
+        (
+            SELECT ut.role
+            FROM user_teams ut
+            WHERE ut.user_id = member_user_id AND ut.team_id = member_team_id
+        ),
+        (
+            SELECT u.organization_role
+            FROM users u
+            JOIN teams t ON t.organization_id = u.organization_id
+            WHERE u.id = member_user_id AND t.id = member_team_id
+        )
+    );
+$$ LANGUAGE sql STABLE;
@@ -0,0 +1,5 @@
+-- Drop triggers
+DROP TRIGGER IF EXISTS set_updated_at_invitations ON invitations;
+
+-- Drop tables
+DROP TABLE IF EXISTS invitations;
@@ -0,0 +1,8 @@
+-- Drop triggers
+DROP TRIGGER IF EXISTS set_updated_at_user_tokens ON user_tokens;
+
+-- Drop tables
+DROP TABLE IF EXISTS user_tokens;
+
+-- Drop types
+DROP TYPE IF EXISTS user_token_kind;
@@ -0,0 +1,23 @@
+-- Create enum type for the purpose of user tokens
+CREATE TYPE user_token_kind AS ENUM ('email_verification');
+
+-- Create 'user_tokens' table, single use tokens sent to users by email, only their hash is stored
+CREATE TABLE IF NOT EXISTS user_tokens (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    kind user_token_kind NOT NULL,
+    token_hash VARCHAR NOT NULL UNIQUE,
+    expires_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    used_at TIMESTAMP WITH TIME ZONE,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP
+);
+
+-- Create indexes
+CREATE INDEX idx_user_tokens_user_id ON user_tokens(user_id);
+
+-- Create triggers
+CREATE TRIGGER set_updated_at_user_tokens
+BEFORE UPDATE ON user_tokens
+FOR EACH ROW
+EXECUTE FUNCTION update_updated_at_column();
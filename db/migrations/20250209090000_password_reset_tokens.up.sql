@@ -0,0 +1,2 @@
+-- Add enum values
+ALTER TYPE user_token_kind ADD VALUE IF NOT EXISTS 'password_reset';
@@ -0,0 +1,13 @@
+-- Drop triggers
+DROP TRIGGER IF EXISTS set_updated_at_api_tokens ON api_tokens;
+
+-- Drop tables
+DROP TABLE IF EXISTS api_tokens;
+
+-- Drop service accounts
+DELETE FROM user_teams WHERE user_id IN (SELECT id FROM users WHERE service_account_team_id IS NOT NULL);
+DELETE FROM users WHERE service_account_team_id IS NOT NULL;
+ALTER TABLE users DROP COLUMN IF EXISTS service_account_team_id;
+
+-- Drop types
+DROP TYPE IF EXISTS api_token_scope;
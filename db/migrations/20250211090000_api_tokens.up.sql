@@ -0,0 +1,30 @@
+-- Create enum type for what an api token may be used for
+CREATE TYPE api_token_scope AS ENUM ('read', 'trigger', 'edit');
+
+-- Service accounts are users owned by a team, they can't log in and only act through api tokens
+ALTER TABLE users ADD COLUMN service_account_team_id UUID REFERENCES teams(id);
+
+-- Create 'api_tokens' table, long lived tokens for automation, only their hash is stored
+CREATE TABLE IF NOT EXISTS api_tokens (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    name VARCHAR NOT NULL,
+    token_hash VARCHAR NOT NULL UNIQUE,
+    scopes api_token_scope[] NOT NULL,
+    expires_at TIMESTAMP WITH TIME ZONE,
+    last_used_at TIMESTAMP WITH TIME ZONE,
+    revoked_at TIMESTAMP WITH TIME ZONE,
+    created_by_user_id UUID REFERENCES users(id) ON DELETE SET NULL,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP
+);
+
+-- Create indexes
+CREATE INDEX idx_api_tokens_user_id ON api_tokens(user_id);
+CREATE INDEX idx_users_service_account_team_id ON users(service_account_team_id);
+
+-- Create triggers
+CREATE TRIGGER set_updated_at_api_tokens
+BEFORE UPDATE ON api_tokens
+FOR EACH ROW
+EXECUTE FUNCTION update_updated_at_column();
@@ -0,0 +1,12 @@
+-- Drop triggers
+DROP TRIGGER IF EXISTS set_updated_at_recovery_codes ON recovery_codes;
+
+-- Drop tables
+DROP TABLE IF EXISTS recovery_codes;
+
+-- Drop columns
+ALTER TABLE teams DROP COLUMN IF EXISTS require_two_factor;
+ALTER TABLE organizations DROP COLUMN IF EXISTS require_two_factor;
+ALTER TABLE users DROP COLUMN IF EXISTS totp_last_used_step;
+ALTER TABLE users DROP COLUMN IF EXISTS totp_enabled_at;
+ALTER TABLE users DROP COLUMN IF EXISTS totp_secret;
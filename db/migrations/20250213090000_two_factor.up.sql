@@ -0,0 +1,28 @@
+-- TOTP two-factor authentication, the secret is pending until the first code confirms it
+ALTER TABLE users ADD COLUMN totp_secret VARCHAR;
+ALTER TABLE users ADD COLUMN totp_enabled_at TIMESTAMP WITH TIME ZONE;
+-- Time step of the last accepted code, a code can't be used twice
+ALTER TABLE users ADD COLUMN totp_last_used_step BIGINT;
+
+-- Policies requiring the members to enable two-factor authentication
+ALTER TABLE organizations ADD COLUMN require_two_factor BOOLEAN NOT NULL DEFAULT FALSE;
+ALTER TABLE teams ADD COLUMN require_two_factor BOOLEAN NOT NULL DEFAULT FALSE;
+
+-- Create 'recovery_codes' table, single use codes replacing a TOTP code, only their hash is stored
+CREATE TABLE IF NOT EXISTS recovery_codes (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    code_hash VARCHAR NOT NULL,
+    used_at TIMESTAMP WITH TIME ZONE,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP
+);
+
+-- Create indexes
+CREATE INDEX idx_recovery_codes_user_id ON recovery_codes(user_id);
+
+-- Create triggers
+CREATE TRIGGER set_updated_at_recovery_codes
+BEFORE UPDATE ON recovery_codes
+FOR EACH ROW
+EXECUTE FUNCTION update_updated_at_column();
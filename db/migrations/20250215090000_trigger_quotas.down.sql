@@ -0,0 +1,2 @@
+-- Drop columns
+ALTER TABLE teams DROP COLUMN IF EXISTS trigger_rate_limit_per_minute;
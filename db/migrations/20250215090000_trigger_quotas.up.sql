@@ -0,0 +1,3 @@
+-- Pipeline triggers per minute the team is allowed, the api default applies when unset
+ALTER TABLE teams ADD COLUMN trigger_rate_limit_per_minute INTEGER
+    CHECK (trigger_rate_limit_per_minute > 0);
@@ -0,0 +1,25 @@
+use serde::{Deserialize, Serialize};
+
+/// What an api token may be used for, the role of its user in the team still applies.
+#[derive(sqlx::Type, PartialEq, Eq, Clone, Copy, Debug, Serialize, Deserialize)]
+#[sqlx(type_name = "api_token_scope", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum ApiTokenScope {
+    /// Reads pipelines and their executions.
+    Read,
+    /// Triggers, retries and replays pipeline executions.
+    Trigger,
+    /// Edits pipelines, their graphs and triggers.
+    Edit,
+}
+
+impl std::fmt::Display for ApiTokenScope {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            ApiTokenScope::Read => "read",
+            ApiTokenScope::Trigger => "trigger",
+            ApiTokenScope::Edit => "edit",
+        };
+        write!(f, "{s}")
+    }
+}
@@ -8,4 +8,26 @@ pub enum ExecStatus {
     Running,
     Completed,
     Failed,
+    Cancelled,
+}
+
+impl ExecStatus {
+    #[must_use]
+    pub fn is_terminal(&self) -> bool {
+        matches!(
+            self,
+            ExecStatus::Completed | ExecStatus::Failed | ExecStatus::Cancelled
+        )
+    }
+
+    #[must_use]
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ExecStatus::Pending => "pending",
+            ExecStatus::Running => "running",
+            ExecStatus::Completed => "completed",
+            ExecStatus::Failed => "failed",
+            ExecStatus::Cancelled => "cancelled",
+        }
+    }
 }
@@ -1,6 +1,6 @@
 use serde::{Deserialize, Serialize};
 
-#[derive(sqlx::Type, Serialize, Deserialize, Clone)]
+#[derive(sqlx::Type, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
 #[sqlx(type_name = "key_provider_type", rename_all = "snake_case")]
 pub enum KeyProviderType {
     Password,
@@ -8,3 +8,15 @@ pub enum KeyProviderType {
     Google,
     Facebook,
 }
+
+impl KeyProviderType {
+    #[must_use]
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            KeyProviderType::Password => "password",
+            KeyProviderType::Github => "github",
+            KeyProviderType::Google => "google",
+            KeyProviderType::Facebook => "facebook",
+        }
+    }
+}
@@ -0,0 +1,45 @@
+use serde::{Deserialize, Serialize};
+
+/// Role of a user within an organization or a team, variants are ordered from the least privileged.
+#[derive(
+    sqlx::Type, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug, Serialize, Deserialize,
+)]
+#[sqlx(type_name = "member_role", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum MemberRole {
+    /// Reads pipelines and their executions.
+    Viewer,
+    /// Also triggers, retries and replays pipeline executions.
+    Runner,
+    /// Also edits pipelines, their graphs and triggers.
+    Editor,
+    /// Also assigns and revokes roles.
+    Admin,
+    Owner,
+}
+
+impl std::fmt::Display for MemberRole {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            MemberRole::Viewer => "viewer",
+            MemberRole::Runner => "runner",
+            MemberRole::Editor => "editor",
+            MemberRole::Admin => "admin",
+            MemberRole::Owner => "owner",
+        };
+        write!(f, "{s}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_member_role_order() {
+        assert!(MemberRole::Viewer < MemberRole::Runner);
+        assert!(MemberRole::Runner < MemberRole::Editor);
+        assert!(MemberRole::Editor < MemberRole::Admin);
+        assert!(MemberRole::Admin < MemberRole::Owner);
+    }
+}
@@ -1,17 +1,25 @@
+mod api_token_scope;
 mod exec_status;
 mod key_provider_type;
+mod member_role;
 mod node_config;
 mod node_container_type;
+mod pipeline_contract;
 mod pipeline_exec_event;
 mod pipeline_execs_payload;
 mod pipeline_plan_payload;
 mod pipeline_trigger_config;
+mod user_token_kind;
 
+pub use api_token_scope::*;
 pub use exec_status::*;
 pub use key_provider_type::*;
+pub use member_role::*;
 pub use node_config::*;
 pub use node_container_type::*;
+pub use pipeline_contract::*;
 pub use pipeline_exec_event::*;
 pub use pipeline_execs_payload::*;
 pub use pipeline_plan_payload::*;
 pub use pipeline_trigger_config::*;
+pub use user_token_kind::*;
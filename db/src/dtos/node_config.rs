@@ -2,14 +2,14 @@ use std::collections::HashMap;
 
 use serde::{Deserialize, Serialize};
 
-#[derive(Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct SelectInput {
     pub value: String,
     pub label: HashMap<String, String>,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub enum NodeInputType {
     Text {
@@ -22,7 +22,7 @@ pub enum NodeInputType {
     Binary,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct NodeInput {
     pub key: String,
@@ -32,15 +32,19 @@ pub struct NodeInput {
     pub description: Option<HashMap<String, String>>,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub enum NodeOutputType {
     Text,
     Status,
     Binary,
+    Number,
+    Boolean,
+    /// Structured value, an object or an array.
+    Json,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct NodeOutput {
     pub key: String,
@@ -49,15 +53,523 @@ pub struct NodeOutput {
     pub description: Option<HashMap<String, String>>,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct NodeConfigV0 {
     pub inputs: Vec<NodeInput>,
     pub outputs: Vec<NodeOutput>,
 }
 
-#[derive(Serialize, Deserialize)]
+/// Input types of `v1` node configs, a superset of the `v0` ones.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum NodeInputTypeV1 {
+    Text {
+        default: Option<String>,
+    },
+    Select {
+        options: Vec<SelectInput>,
+        default: Option<String>,
+    },
+    Binary,
+    Number {
+        min: Option<f64>,
+        max: Option<f64>,
+        default: Option<serde_json::Number>,
+    },
+    Boolean {
+        default: Option<bool>,
+    },
+    Json {
+        /// JSON Schema the value has to match.
+        schema: Option<serde_json::Value>,
+        default: Option<serde_json::Value>,
+    },
+    MultiSelect {
+        options: Vec<SelectInput>,
+        default: Option<Vec<String>>,
+    },
+    List {
+        default: Option<Vec<String>>,
+    },
+    /// Text which is never shown back once stored.
+    Secret,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NodeInputV1 {
+    pub key: String,
+    pub input: NodeInputTypeV1,
+    pub label: Option<HashMap<String, String>>,
+    pub required: bool,
+    pub description: Option<HashMap<String, String>>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NodeConfigV1 {
+    pub inputs: Vec<NodeInputV1>,
+    pub outputs: Vec<NodeOutput>,
+    /// JSON Schema the params of the node have to match, once merged and coerced.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub input_schema: Option<serde_json::Value>,
+    /// JSON Schema the results of the node have to match.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output_schema: Option<serde_json::Value>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(tag = "version", rename_all = "camelCase")]
 pub enum NodeConfig {
     V0(NodeConfigV0),
+    V1(NodeConfigV1),
+}
+
+impl From<NodeInputType> for NodeInputTypeV1 {
+    fn from(input: NodeInputType) -> Self {
+        match input {
+            NodeInputType::Text { default } => NodeInputTypeV1::Text { default },
+            NodeInputType::Select { options, default } => {
+                NodeInputTypeV1::Select { options, default }
+            }
+            NodeInputType::Binary => NodeInputTypeV1::Binary,
+        }
+    }
+}
+
+impl From<NodeInput> for NodeInputV1 {
+    fn from(input: NodeInput) -> Self {
+        NodeInputV1 {
+            key: input.key,
+            input: input.input.into(),
+            label: input.label,
+            required: input.required,
+            description: input.description,
+        }
+    }
+}
+
+impl From<NodeConfigV0> for NodeConfigV1 {
+    fn from(config: NodeConfigV0) -> Self {
+        NodeConfigV1 {
+            inputs: config.inputs.into_iter().map(Into::into).collect(),
+            outputs: config.outputs,
+            input_schema: None,
+            output_schema: None,
+        }
+    }
+}
+
+/// Stands for the value of a secret input wherever params are stored or displayed.
+pub const REDACTED_VALUE: &str = "[redacted]";
+
+impl NodeConfig {
+    /// The config in its latest version, older versions are converted.
+    #[must_use]
+    pub fn into_v1(self) -> NodeConfigV1 {
+        match self {
+            NodeConfig::V0(config) => config.into(),
+            NodeConfig::V1(config) => config,
+        }
+    }
+}
+
+impl NodeConfigV1 {
+    #[must_use]
+    pub fn input(&self, key: &str) -> Option<&NodeInputV1> {
+        self.inputs.iter().find(|input| input.key == key)
+    }
+
+    #[must_use]
+    pub fn output(&self, key: &str) -> Option<&NodeOutput> {
+        self.outputs.iter().find(|output| output.key == key)
+    }
+
+    /// Checks the params of the node against the input schema, if any.
+    ///
+    /// # Errors
+    ///
+    /// Returns a readable reason listing the offending fields.
+    pub fn check_params(&self, params: &serde_json::Value) -> Result<(), String> {
+        self.input_schema.as_ref().map_or(Ok(()), |schema| {
+            check_schema(schema, params).map_err(|violations| {
+                format!(
+                    "params don't match the input schema: {}",
+                    violations.join("; ")
+                )
+            })
+        })
+    }
+
+    /// Checks a result of the node against the output schema, if any.
+    ///
+    /// # Errors
+    ///
+    /// Returns a readable reason listing the offending fields.
+    pub fn check_result(&self, result: &serde_json::Value) -> Result<(), String> {
+        self.output_schema.as_ref().map_or(Ok(()), |schema| {
+            check_schema(schema, result).map_err(|violations| {
+                format!(
+                    "result doesn't match the output schema: {}",
+                    violations.join("; ")
+                )
+            })
+        })
+    }
+
+    /// Default values of the inputs declaring one, by input key.
+    #[must_use]
+    pub fn default_values(&self) -> serde_json::Map<String, serde_json::Value> {
+        self.inputs
+            .iter()
+            .filter_map(|input| Some((input.key.clone(), input.input.default_value()?)))
+            .collect()
+    }
+
+    /// Params with the values of secret inputs replaced by [`REDACTED_VALUE`], safe to store and display.
+    #[must_use]
+    pub fn redact_params(&self, params: &serde_json::Value) -> serde_json::Value {
+        let mut params = params.clone();
+
+        if let Some(values) = params.as_object_mut() {
+            for (key, value) in values.iter_mut() {
+                if self
+                    .input(key)
+                    .is_some_and(|input| matches!(input.input, NodeInputTypeV1::Secret))
+                    && !value.is_null()
+                {
+                    *value = serde_json::Value::String(REDACTED_VALUE.to_string());
+                }
+            }
+        }
+
+        params
+    }
+
+    /// Checks values keyed by input key against the declared inputs, coercing them to the input types.
+    ///
+    /// # Errors
+    ///
+    /// Returns the reason of every rejected value, by input key.
+    pub fn coerce_input_values(
+        &self,
+        values: serde_json::Map<String, serde_json::Value>,
+    ) -> Result<serde_json::Map<String, serde_json::Value>, Vec<(String, String)>> {
+        let mut coerced = serde_json::Map::new();
+        let mut errors = Vec::new();
+
+        for (key, value) in values {
+            let Some(input) = self.input(&key) else {
+                errors.push((key, "not an input of the node".to_string()));
+                continue;
+            };
+
+            match input.input.coerce(value) {
+                Ok(value) => {
+                    coerced.insert(key, value);
+                }
+                Err(error) => errors.push((key, error)),
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(coerced)
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+impl NodeInputTypeV1 {
+    #[must_use]
+    pub fn name(&self) -> &'static str {
+        match self {
+            NodeInputTypeV1::Text { .. } => "text",
+            NodeInputTypeV1::Select { .. } => "select",
+            NodeInputTypeV1::Binary => "binary",
+            NodeInputTypeV1::Number { .. } => "number",
+            NodeInputTypeV1::Boolean { .. } => "boolean",
+            NodeInputTypeV1::Json { .. } => "json",
+            NodeInputTypeV1::MultiSelect { .. } => "multi-select",
+            NodeInputTypeV1::List { .. } => "list",
+            NodeInputTypeV1::Secret => "secret",
+        }
+    }
+
+    #[must_use]
+    pub fn default_value(&self) -> Option<serde_json::Value> {
+        match self {
+            NodeInputTypeV1::Text { default } | NodeInputTypeV1::Select { default, .. } => {
+                default.clone().map(serde_json::Value::String)
+            }
+            NodeInputTypeV1::Number { default, .. } => {
+                default.clone().map(serde_json::Value::Number)
+            }
+            NodeInputTypeV1::Boolean { default } => default.map(serde_json::Value::Bool),
+            NodeInputTypeV1::Json { default, .. } => default.clone(),
+            NodeInputTypeV1::MultiSelect { default, .. } | NodeInputTypeV1::List { default } => {
+                default.as_ref().map(|values| {
+                    serde_json::Value::Array(
+                        values
+                            .iter()
+                            .cloned()
+                            .map(serde_json::Value::String)
+                            .collect(),
+                    )
+                })
+            }
+            NodeInputTypeV1::Binary | NodeInputTypeV1::Secret => None,
+        }
+    }
+
+    /// Whether values of the `output` type may be connected to this input.
+    /// Text inputs take any scalar value, converted at runtime, select inputs take text checked
+    /// against their options, the other inputs only take values of their own kind,
+    /// structured values going to JSON, list and multi-select inputs.
+    #[must_use]
+    pub fn accepts(&self, output: &NodeOutputType) -> bool {
+        match self {
+            NodeInputTypeV1::Text { .. } | NodeInputTypeV1::Secret => matches!(
+                output,
+                NodeOutputType::Text
+                    | NodeOutputType::Status
+                    | NodeOutputType::Number
+                    | NodeOutputType::Boolean
+            ),
+            NodeInputTypeV1::Select { .. } => matches!(output, NodeOutputType::Text),
+            NodeInputTypeV1::Binary => matches!(output, NodeOutputType::Binary),
+            NodeInputTypeV1::Number { .. } => matches!(output, NodeOutputType::Number),
+            NodeInputTypeV1::Boolean { .. } => {
+                matches!(output, NodeOutputType::Boolean | NodeOutputType::Status)
+            }
+            NodeInputTypeV1::Json { .. } => matches!(output, NodeOutputType::Json),
+            NodeInputTypeV1::MultiSelect { .. } | NodeInputTypeV1::List { .. } => {
+                matches!(output, NodeOutputType::Text | NodeOutputType::Json)
+            }
+        }
+    }
+
+    /// Checks a runtime value against the input type, applying the explicit coercions:
+    /// - numbers and booleans (status values) become text,
+    /// - text becomes a number or a boolean when it parses as one,
+    /// - text becomes a select value when among the options,
+    /// - a single value becomes a one item list.
+    ///
+    /// JSON values are passed as is once they match the schema of the input.
+    ///
+    /// # Errors
+    ///
+    /// Returns a readable reason when the value doesn't fit the input type.
+    pub fn coerce(&self, value: serde_json::Value) -> Result<serde_json::Value, String> {
+        match self {
+            NodeInputTypeV1::Text { .. } | NodeInputTypeV1::Secret => {
+                self.coerce_text(value).map(serde_json::Value::String)
+            }
+            NodeInputTypeV1::Binary => match value {
+                serde_json::Value::String(_) => Ok(value),
+                other => Err(self.unexpected(&other)),
+            },
+            NodeInputTypeV1::Select { options, .. } => {
+                let text = self.coerce_text(value)?;
+                check_option(options, &text)?;
+                Ok(serde_json::Value::String(text))
+            }
+            NodeInputTypeV1::Number { min, max, .. } => {
+                let number = match value {
+                    serde_json::Value::Number(number) => number,
+                    serde_json::Value::String(ref text) => text
+                        .trim()
+                        .parse::<i64>()
+                        .map(serde_json::Number::from)
+                        .ok()
+                        .or_else(|| {
+                            text.trim()
+                                .parse::<f64>()
+                                .ok()
+                                .and_then(serde_json::Number::from_f64)
+                        })
+                        .ok_or_else(|| format!("{text:?} is not a number"))?,
+                    other => return Err(self.unexpected(&other)),
+                };
+
+                let float = number.as_f64().unwrap_or_default();
+
+                if let Some(min) = min.filter(|min| float < *min) {
+                    return Err(format!("{number} is less than the minimum of {min}"));
+                }
+
+                if let Some(max) = max.filter(|max| float > *max) {
+                    return Err(format!("{number} is greater than the maximum of {max}"));
+                }
+
+                Ok(serde_json::Value::Number(number))
+            }
+            NodeInputTypeV1::Boolean { .. } => match value {
+                serde_json::Value::Bool(_) => Ok(value),
+                serde_json::Value::String(ref text) => match text.trim() {
+                    "true" => Ok(serde_json::Value::Bool(true)),
+                    "false" => Ok(serde_json::Value::Bool(false)),
+                    _ => Err(format!("{text:?} is not a boolean")),
+                },
+                other => Err(self.unexpected(&other)),
+            },
+            NodeInputTypeV1::Json { schema, .. } => match schema {
+                Some(schema) => check_schema(schema, &value)
+                    .map(|()| value)
+                    .map_err(|violations| violations.join("; ")),
+                None => Ok(value),
+            },
+            NodeInputTypeV1::MultiSelect { options, .. } => {
+                let values = self.coerce_list(value)?;
+
+                for value in &values {
+                    check_option(options, value)?;
+                }
+
+                Ok(serde_json::Value::Array(
+                    values.into_iter().map(serde_json::Value::String).collect(),
+                ))
+            }
+            NodeInputTypeV1::List { .. } => Ok(serde_json::Value::Array(
+                self.coerce_list(value)?
+                    .into_iter()
+                    .map(serde_json::Value::String)
+                    .collect(),
+            )),
+        }
+    }
+
+    fn coerce_text(&self, value: serde_json::Value) -> Result<String, String> {
+        match value {
+            serde_json::Value::String(text) => Ok(text),
+            serde_json::Value::Number(number) => Ok(number.to_string()),
+            serde_json::Value::Bool(flag) => Ok(flag.to_string()),
+            other => Err(self.unexpected(&other)),
+        }
+    }
+
+    fn coerce_list(&self, value: serde_json::Value) -> Result<Vec<String>, String> {
+        match value {
+            serde_json::Value::Array(items) => items
+                .into_iter()
+                .map(|item| self.coerce_text(item))
+                .collect(),
+            other => Ok(vec![self.coerce_text(other)?]),
+        }
+    }
+
+    fn unexpected(&self, value: &serde_json::Value) -> String {
+        format!(
+            "expected a {} value, got {}",
+            self.name(),
+            json_type_name(value)
+        )
+    }
+}
+
+impl NodeOutputType {
+    #[must_use]
+    pub fn name(&self) -> &'static str {
+        match self {
+            NodeOutputType::Text => "text",
+            NodeOutputType::Status => "status",
+            NodeOutputType::Binary => "binary",
+            NodeOutputType::Number => "number",
+            NodeOutputType::Boolean => "boolean",
+            NodeOutputType::Json => "json",
+        }
+    }
+}
+
+/// Validates `value` against a JSON Schema.
+///
+/// # Errors
+///
+/// Returns every violation prefixed by the path of the offending field,
+/// or the reason the schema itself is invalid.
+pub fn check_schema(
+    schema: &serde_json::Value,
+    value: &serde_json::Value,
+) -> Result<(), Vec<String>> {
+    let validator = jsonschema::validator_for(schema)
+        .map_err(|error| vec![format!("invalid schema: {error}")])?;
+
+    let violations = validator
+        .iter_errors(value)
+        .map(|error| {
+            let path = error.instance_path.to_string();
+
+            if path.is_empty() {
+                error.to_string()
+            } else {
+                format!("{path}: {error}")
+            }
+        })
+        .collect::<Vec<_>>();
+
+    if violations.is_empty() {
+        Ok(())
+    } else {
+        Err(violations)
+    }
+}
+
+fn check_option(options: &[SelectInput], value: &str) -> Result<(), String> {
+    if options.iter().any(|option| option.value == value) {
+        Ok(())
+    } else {
+        Err(format!("{value:?} is not one of the select options"))
+    }
+}
+
+fn json_type_name(value: &serde_json::Value) -> &'static str {
+    match value {
+        serde_json::Value::Null => "null",
+        serde_json::Value::Bool(_) => "boolean",
+        serde_json::Value::Number(_) => "number",
+        serde_json::Value::String(_) => "string",
+        serde_json::Value::Array(_) => "array",
+        serde_json::Value::Object(_) => "object",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_accepts() {
+        let text = NodeInputTypeV1::Text { default: None };
+        let number = NodeInputTypeV1::Number {
+            min: None,
+            max: None,
+            default: None,
+        };
+        let boolean = NodeInputTypeV1::Boolean { default: None };
+        let json = NodeInputTypeV1::Json {
+            schema: None,
+            default: None,
+        };
+        let list = NodeInputTypeV1::List { default: None };
+
+        assert!(text.accepts(&NodeOutputType::Text));
+        assert!(text.accepts(&NodeOutputType::Number));
+        assert!(number.accepts(&NodeOutputType::Number));
+        assert!(boolean.accepts(&NodeOutputType::Boolean));
+        assert!(boolean.accepts(&NodeOutputType::Status));
+        assert!(json.accepts(&NodeOutputType::Json));
+        assert!(list.accepts(&NodeOutputType::Json));
+        assert!(NodeInputTypeV1::Binary.accepts(&NodeOutputType::Binary));
+
+        assert!(!text.accepts(&NodeOutputType::Binary));
+        assert!(!text.accepts(&NodeOutputType::Json));
+        assert!(!number.accepts(&NodeOutputType::Text));
+        assert!(!number.accepts(&NodeOutputType::Boolean));
+        assert!(!boolean.accepts(&NodeOutputType::Text));
+        assert!(!boolean.accepts(&NodeOutputType::Number));
+        assert!(!json.accepts(&NodeOutputType::Text));
+        assert!(!list.accepts(&NodeOutputType::Number));
+        assert!(!NodeInputTypeV1::Binary.accepts(&NodeOutputType::Text));
+    }
 }
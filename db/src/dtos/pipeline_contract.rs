@@ -0,0 +1,58 @@
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// Named input of a pipeline, feeding an input of one of its nodes.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct PipelineInput {
+    pub name: String,
+    pub pipeline_node_id: Uuid,
+    pub input: String,
+    #[serde(default)]
+    pub required: bool,
+    pub description: Option<String>,
+}
+
+/// Named output of a pipeline, taken from the result of one of its nodes.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct PipelineOutput {
+    pub name: String,
+    pub pipeline_node_id: Uuid,
+    /// Key of the node result, the whole result when none.
+    pub output: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct PipelineContractV0 {
+    #[serde(default)]
+    pub inputs: Vec<PipelineInput>,
+    #[serde(default)]
+    pub outputs: Vec<PipelineOutput>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "version", rename_all = "camelCase")]
+pub enum PipelineContract {
+    V0(PipelineContractV0),
+}
+
+impl Default for PipelineContract {
+    fn default() -> Self {
+        PipelineContract::V0(PipelineContractV0::default())
+    }
+}
+
+impl PipelineContract {
+    #[must_use]
+    pub fn inputs(&self) -> &[PipelineInput] {
+        match self {
+            PipelineContract::V0(contract) => &contract.inputs,
+        }
+    }
+
+    #[must_use]
+    pub fn outputs(&self) -> &[PipelineOutput] {
+        match self {
+            PipelineContract::V0(contract) => &contract.outputs,
+        }
+    }
+}
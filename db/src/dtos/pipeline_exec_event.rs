@@ -29,6 +29,11 @@ pub struct PipelineNodeExec {
     pub pipeline_node_id: Uuid,
     pub status: ExecStatus,
     pub result: serde_json::Value,
+    #[serde(default)]
+    pub error: Option<String>,
+    /// Params the node was dispatched with, secret inputs redacted.
+    #[serde(default)]
+    pub params: Option<serde_json::Value>,
     #[serde(alias = "created_at")]
     pub created_at: DateTime<Utc>,
     #[serde(alias = "started_at")]
@@ -34,5 +34,8 @@ pub struct PipelineNodeExecResultPayload {
 pub struct PipelineExecPayload {
     pub pipeline_id: Uuid,
     pub pipeline_exec_id: Uuid,
+    /// Trigger which started the execution, only its entry nodes are executed.
+    #[serde(default)]
+    pub pipeline_trigger_id: Option<Uuid>,
     pub params: PipelineExecPayloadParams,
 }
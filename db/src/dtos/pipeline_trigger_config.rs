@@ -1,12 +1,261 @@
 use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use uuid::Uuid;
 
-#[derive(Serialize, Deserialize)]
+use super::ExecStatus;
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct PipelineTriggerConfigV0 {
     pub allow_manual_execution: bool,
 }
 
-#[derive(Serialize, Deserialize)]
+fn default_webhook_signature_header() -> String {
+    "x-shallabuf-signature".to_string()
+}
+
+fn default_webhook_secret_header() -> String {
+    "x-shallabuf-secret".to_string()
+}
+
+fn default_webhook_response_timeout_secs() -> u64 {
+    30
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum WebhookAuth {
+    /// Hex encoded HMAC-SHA256 of the raw request body, optionally prefixed with `sha256=`.
+    HmacSha256 {
+        #[serde(default = "default_webhook_signature_header")]
+        header: String,
+    },
+    /// The trigger secret itself, sent as is.
+    SharedSecret {
+        #[serde(default = "default_webhook_secret_header")]
+        header: String,
+    },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(tag = "from", rename_all = "snake_case")]
+pub enum WebhookParamSource {
+    /// JSON pointer into the request body, an empty pointer selects the whole body.
+    Body {
+        pointer: String,
+    },
+    Header {
+        name: String,
+    },
+    Query {
+        name: String,
+    },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct WebhookParamMapping {
+    pub pipeline_node_id: Uuid,
+    pub input: String,
+    pub source: WebhookParamSource,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct WebhookResponseConfig {
+    pub pipeline_node_id: Uuid,
+    /// Output key to respond with, the whole node result is returned when omitted.
+    pub output: Option<String>,
+    #[serde(default = "default_webhook_response_timeout_secs")]
+    pub timeout_secs: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct WebhookTriggerConfig {
+    pub auth: WebhookAuth,
+    #[serde(default)]
+    pub params: Vec<WebhookParamMapping>,
+    /// When set, the webhook waits for the run to finish and responds with the node output.
+    pub response: Option<WebhookResponseConfig>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct EventParamMapping {
+    pub pipeline_node_id: Uuid,
+    pub input: String,
+    pub source_pipeline_node_id: Uuid,
+    /// Output key of the upstream node, the whole node result is passed when omitted.
+    pub output: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct EventTriggerConfig {
+    /// Upstream pipeline whose executions are watched.
+    pub pipeline_id: Uuid,
+    /// Terminal statuses of the upstream execution which start this pipeline.
+    pub statuses: Vec<ExecStatus>,
+    #[serde(default)]
+    pub params: Vec<EventParamMapping>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "from", rename_all = "snake_case")]
+pub enum NatsParamSource {
+    /// JSON pointer into the message payload, an empty pointer selects the whole payload.
+    Body {
+        pointer: String,
+    },
+    Header {
+        name: String,
+    },
+    /// Subject the message was published to, useful with wildcard subjects.
+    Subject,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct NatsParamMapping {
+    pub pipeline_node_id: Uuid,
+    pub input: String,
+    pub source: NatsParamSource,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct NatsJetStreamConfig {
+    pub stream: String,
+    /// Durable consumer name, defaults to one derived from the trigger id.
+    pub durable_name: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct NatsTriggerConfig {
+    pub subject: String,
+    /// Consume through a JetStream durable consumer instead of a core NATS subscription.
+    pub jetstream: Option<NatsJetStreamConfig>,
+    #[serde(default)]
+    pub params: Vec<NatsParamMapping>,
+    /// Maximum number of unfinished executions started by the trigger, unlimited when omitted.
+    pub max_concurrency: Option<u32>,
+    /// Run one execution at a time, in the order the messages were received.
+    #[serde(default)]
+    pub ordered: bool,
+}
+
+impl NatsTriggerConfig {
+    #[must_use]
+    pub fn concurrency_limit(&self) -> Option<u32> {
+        if self.ordered {
+            Some(1)
+        } else {
+            self.max_concurrency
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum PipelineTriggerSource {
+    Webhook(WebhookTriggerConfig),
+    Event(EventTriggerConfig),
+    Nats(NatsTriggerConfig),
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct PipelineTriggerConfigV1 {
+    pub allow_manual_execution: bool,
+    pub source: Option<PipelineTriggerSource>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
 #[serde(tag = "version")]
 pub enum PipelineTriggerConfig {
     V0(PipelineTriggerConfigV0),
+    V1(PipelineTriggerConfigV1),
+}
+
+impl PipelineTriggerConfig {
+    #[must_use]
+    pub fn allow_manual_execution(&self) -> bool {
+        match self {
+            PipelineTriggerConfig::V0(config) => config.allow_manual_execution,
+            PipelineTriggerConfig::V1(config) => config.allow_manual_execution,
+        }
+    }
+
+    /// Same config with the source replaced, upgraded to the latest version.
+    #[must_use]
+    pub fn with_source(&self, source: Option<PipelineTriggerSource>) -> Self {
+        PipelineTriggerConfig::V1(PipelineTriggerConfigV1 {
+            allow_manual_execution: self.allow_manual_execution(),
+            source,
+        })
+    }
+
+    #[must_use]
+    pub fn source(&self) -> Option<&PipelineTriggerSource> {
+        match self {
+            PipelineTriggerConfig::V0(_) => None,
+            PipelineTriggerConfig::V1(config) => config.source.as_ref(),
+        }
+    }
+}
+
+/// Pipeline nodes which don't take part in a run started by `pipeline_trigger_id`:
+/// entry nodes bound to another trigger and everything downstream of them.
+/// Entry nodes without a trigger start with any of them.
+pub fn excluded_pipeline_nodes(
+    entry_triggers: &HashMap<Uuid, Option<Uuid>>,
+    connections: &[(Uuid, Uuid)],
+    pipeline_trigger_id: Option<Uuid>,
+) -> HashSet<Uuid> {
+    let Some(pipeline_trigger_id) = pipeline_trigger_id else {
+        return HashSet::new();
+    };
+
+    let mut excluded = entry_triggers
+        .iter()
+        .filter(|(pipeline_node_id, trigger_id)| {
+            trigger_id.is_some_and(|trigger_id| trigger_id != pipeline_trigger_id)
+                && !connections.iter().any(|(_, to)| to == *pipeline_node_id)
+        })
+        .map(|(pipeline_node_id, _)| *pipeline_node_id)
+        .collect::<HashSet<_>>();
+
+    let mut stack = excluded.iter().copied().collect::<Vec<_>>();
+
+    while let Some(pipeline_node_id) = stack.pop() {
+        for (_, to) in connections
+            .iter()
+            .filter(|(from, _)| *from == pipeline_node_id)
+        {
+            if excluded.insert(*to) {
+                stack.push(*to);
+            }
+        }
+    }
+
+    excluded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_excluded_pipeline_nodes() {
+        let (manual, webhook) = (Uuid::new_v4(), Uuid::new_v4());
+        let (a, b, c, d) = (
+            Uuid::new_v4(),
+            Uuid::new_v4(),
+            Uuid::new_v4(),
+            Uuid::new_v4(),
+        );
+
+        // a (manual) -> c, b (webhook) -> d, unbound entry nodes start with every trigger
+        let entry_triggers =
+            HashMap::from([(a, Some(manual)), (b, Some(webhook)), (c, None), (d, None)]);
+        let connections = vec![(a, c), (b, d)];
+
+        assert_eq!(
+            excluded_pipeline_nodes(&entry_triggers, &connections, Some(manual)),
+            HashSet::from([b, d])
+        );
+        assert!(excluded_pipeline_nodes(&entry_triggers, &connections, None).is_empty());
+    }
 }
@@ -0,0 +1,10 @@
+use serde::{Deserialize, Serialize};
+
+/// What a single use token sent to a user by email is for.
+#[derive(sqlx::Type, PartialEq, Eq, Clone, Copy, Debug, Serialize, Deserialize)]
+#[sqlx(type_name = "user_token_kind", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum UserTokenKind {
+    EmailVerification,
+    PasswordReset,
+}
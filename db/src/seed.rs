@@ -6,8 +6,9 @@ use std::collections::HashMap;
 use uuid::Uuid;
 
 use crate::dtos::{
-    KeyProviderType, NodeConfig, NodeConfigV0, NodeContainerType, NodeInput, NodeInputType,
-    NodeOutput, NodeOutputType, PipelineTriggerConfig, PipelineTriggerConfigV0, SelectInput,
+    KeyProviderType, MemberRole, NodeConfig, NodeConfigV0, NodeContainerType, NodeInput,
+    NodeInputType, NodeOutput, NodeOutputType, PipelineTriggerConfig, PipelineTriggerConfigV0,
+    SelectInput,
 };
 use aws_sdk_s3::error::SdkError;
 use aws_sdk_s3::types::BucketVersioningStatus;
@@ -148,8 +149,8 @@ pub async fn seed_database(db: &PgPool) -> anyhow::Result<()> {
 
     let user_alex = sqlx::query!(
         r#"
-        INSERT INTO users (id, name, email, password_hash, email_verified, organization_id)
-        VALUES ($1, $2, $3, $4, $5, $6)
+        INSERT INTO users (id, name, email, password_hash, email_verified, organization_id, organization_role)
+        VALUES ($1, $2, $3, $4, $5, $6, $7)
         RETURNING id
         "#,
         alex_id,
@@ -157,7 +158,8 @@ pub async fn seed_database(db: &PgPool) -> anyhow::Result<()> {
         "alex@mail.com",
         hashed_password,
         true,
-        organization_id
+        organization_id,
+        MemberRole::Owner as MemberRole
     )
     .fetch_one(db)
     .await?;
@@ -211,22 +213,24 @@ pub async fn seed_database(db: &PgPool) -> anyhow::Result<()> {
 
     sqlx::query!(
         r#"
-        INSERT INTO user_teams (user_id, team_id)
-        VALUES ($1, $2)
+        INSERT INTO user_teams (user_id, team_id, role)
+        VALUES ($1, $2, $3)
         "#,
         user_alex.id,
-        team_id
+        team_id,
+        MemberRole::Owner as MemberRole
     )
     .execute(db)
     .await?;
 
     sqlx::query!(
         r#"
-        INSERT INTO user_teams (user_id, team_id)
-        VALUES ($1, $2)
+        INSERT INTO user_teams (user_id, team_id, role)
+        VALUES ($1, $2, $3)
         "#,
         user_bob.id,
-        team_id
+        team_id,
+        MemberRole::Editor as MemberRole
     )
     .execute(db)
     .await?;
@@ -504,7 +508,7 @@ pub async fn seed_database(db: &PgPool) -> anyhow::Result<()> {
     }))
     .unwrap();
 
-    let btc_price_node = sqlx::query!(
+    let _btc_price_node = sqlx::query!(
         r#"
         INSERT INTO nodes (name, identifier_name, description, publisher_name, container_type, config, version_id)
         VALUES ($1, $2, $3, $4, $5, $6, $7)
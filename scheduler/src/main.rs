@@ -1,7 +1,7 @@
-use db::dtos::{self, ExecStatus, PipelinePlanPayload};
+use db::dtos::{self, excluded_pipeline_nodes, ExecStatus, PipelinePlanPayload};
 use dotenvy::dotenv;
 use futures::StreamExt;
-use petgraph::graph::DiGraph;
+use petgraph::{algo::is_cyclic_directed, graph::DiGraph};
 use pipeline_run::{GraphEdgeConnection, PipelineRun};
 use sqlx::postgres::PgPoolOptions;
 use std::sync::Arc;
@@ -75,7 +75,7 @@ async fn main() -> Result<(), async_nats::Error> {
                     }
                 };
 
-            match sqlx::query!(
+            let (retry_of_pipeline_exec_id, pinned_node_versions) = match sqlx::query!(
                 r#"
                 UPDATE
                     pipeline_execs
@@ -84,30 +84,47 @@ async fn main() -> Result<(), async_nats::Error> {
                     started_at = NOW()
                 WHERE
                     id = $2
+                RETURNING
+                    retry_of_pipeline_exec_id, pinned_node_versions
                 "#,
                 dtos::ExecStatus::Running as ExecStatus,
                 payload.pipeline_exec_id
             )
-            .execute(&pg_pool)
+            .fetch_one(&pg_pool)
             .await
             {
-                Ok(_) => {
+                Ok(pipeline_exec) => {
                     info!(
                         "Pipeline execution status updated to 'running', pipeline_execs_id: {}",
                         payload.pipeline_exec_id
                     );
+
+                    // A replay may run the nodes with the versions of the execution it replays
+                    let pinned_node_versions = pipeline_exec
+                        .pinned_node_versions
+                        .and_then(|versions| {
+                            serde_json::from_value::<HashMap<Uuid, String>>(versions).ok()
+                        })
+                        .unwrap_or_default();
+
+                    (
+                        pipeline_exec.retry_of_pipeline_exec_id,
+                        pinned_node_versions,
+                    )
                 }
                 Err(error) => {
                     error!("Failed to update pipeline execution record: {error:?}, pipeline_exec_id: {}", payload.pipeline_exec_id);
                     continue;
                 }
-            }
+            };
 
             let pipeline_nodes = match sqlx::query!(
                 r#"
                 SELECT
                     pn.id,
                     pn.node_version,
+                    pn.trigger_id,
+                    pn.input_values,
                     n.publisher_name,
                     n.identifier_name,
                     n.config,
@@ -131,12 +148,6 @@ async fn main() -> Result<(), async_nats::Error> {
                 }
             };
 
-            let mut graph = DiGraph::new();
-            let mut node_index_map = std::collections::HashMap::new();
-            for pn in &pipeline_nodes {
-                let idx = graph.add_node(pn.id);
-                node_index_map.insert(pn.id, idx);
-            }
             let node_ids: Vec<Uuid> = pipeline_nodes.iter().map(|pn| pn.id).collect();
 
             let connections = match sqlx::query!(
@@ -167,6 +178,28 @@ async fn main() -> Result<(), async_nats::Error> {
                 }
             };
 
+            let excluded_pipeline_node_ids = excluded_pipeline_nodes(
+                &pipeline_nodes
+                    .iter()
+                    .map(|pn| (pn.id, pn.trigger_id))
+                    .collect(),
+                &connections
+                    .iter()
+                    .map(|conn| (conn.from_pipeline_node_id, conn.to_pipeline_node_id))
+                    .collect::<Vec<_>>(),
+                payload.pipeline_trigger_id,
+            );
+
+            let mut graph = DiGraph::new();
+            let mut node_index_map = std::collections::HashMap::new();
+            for pn in pipeline_nodes
+                .iter()
+                .filter(|pn| !excluded_pipeline_node_ids.contains(&pn.id))
+            {
+                let idx = graph.add_node(pn.id);
+                node_index_map.insert(pn.id, idx);
+            }
+
             for conn in connections {
                 if let (Some(&from_idx), Some(&to_idx)) = (
                     node_index_map.get(&conn.from_pipeline_node_id),
@@ -183,22 +216,68 @@ async fn main() -> Result<(), async_nats::Error> {
                 }
             }
 
+            // Graphs saved before connections were checked for cycles would never finish
+            if is_cyclic_directed(&graph) {
+                error!(
+                    "Pipeline graph contains a cycle, pipeline_exec_id: {}",
+                    payload.pipeline_exec_id
+                );
+
+                if let Err(error) = sqlx::query!(
+                    r#"
+                    UPDATE
+                        pipeline_execs
+                    SET
+                        status = $1,
+                        finished_at = NOW()
+                    WHERE
+                        id = $2;
+                    "#,
+                    dtos::ExecStatus::Failed as ExecStatus,
+                    payload.pipeline_exec_id
+                )
+                .execute(&pg_pool)
+                .await
+                {
+                    error!(
+                        "Failed to update pipeline execution status, error {error:?}, pipeline_exec_id: {}",
+                        payload.pipeline_exec_id
+                    );
+                }
+
+                continue;
+            }
+
             let mut pipeline_node_execs_payloads: HashMap<Uuid, dtos::PipelineNodeExecPayload> =
                 HashMap::new();
+            let mut pipeline_nodes_configs = HashMap::new();
+
+            let (node_exec_pipeline_node_ids, node_exec_versions): (Vec<Uuid>, Vec<String>) =
+                pipeline_nodes
+                    .iter()
+                    .filter(|pn| node_index_map.contains_key(&pn.id))
+                    .map(|pn| {
+                        let node_version =
+                            pinned_node_versions.get(&pn.id).unwrap_or(&pn.node_version);
+
+                        (pn.id, node_version.clone())
+                    })
+                    .unzip();
 
             match sqlx::query!(
                 r#"
                 INSERT INTO
-                    pipeline_node_execs (pipeline_exec_id, pipeline_node_id)
+                    pipeline_node_execs (pipeline_exec_id, pipeline_node_id, node_version)
                 SELECT
-                    pipeline_exec_id, pipeline_node_id
+                    pipeline_exec_id, pipeline_node_id, node_version
                 FROM
-                    UNNEST($1::uuid[], $2::uuid[]) AS a(pipeline_exec_id, pipeline_node_id)
+                    UNNEST($1::uuid[], $2::uuid[], $3::varchar[]) AS a(pipeline_exec_id, pipeline_node_id, node_version)
                 RETURNING
-                    pipeline_node_execs.id, pipeline_node_execs.pipeline_node_id
+                    pipeline_node_execs.id, pipeline_node_execs.pipeline_node_id, pipeline_node_execs.node_version AS "node_version!"
                 "#,
-                &vec![payload.pipeline_exec_id; node_index_map.keys().len()] as &[Uuid],
-                &node_index_map.keys().copied().collect::<Vec<Uuid>>()
+                &vec![payload.pipeline_exec_id; node_exec_pipeline_node_ids.len()] as &[Uuid],
+                &node_exec_pipeline_node_ids,
+                &node_exec_versions
             )
             .fetch_all(&pg_pool)
             .await
@@ -221,51 +300,41 @@ async fn main() -> Result<(), async_nats::Error> {
                             continue;
                         };
 
-                        // Merge payload params with defaults from node config.
-                        let user_params_value = payload
-                            .params
-                            .get(&row.pipeline_node_id)
-                            .cloned()
-                            .unwrap_or_default();
-
-                        let user_params: std::collections::HashMap<String, String> =
-                            if let serde_json::Value::Object(map) = user_params_value {
-                                map.into_iter()
-                                    .filter_map(|(k, v)| v.as_str().map(|s| (k, s.to_string())))
-                                    .collect()
-                            } else {
-                                std::collections::HashMap::new()
-                            };
-
-                        let default_params: std::collections::HashMap<String, String> =
-                            match serde_json::from_value(pipeline_node.config.clone()) {
-                                Ok(config) => {
-                                    let dtos::NodeConfig::V0(v0) = config;
-                                    let mut defaults = std::collections::HashMap::new();
-
-                                    for input in v0.inputs {
-                                        match input.input {
-                                            dtos::NodeInputType::Text { default }
-                                            | dtos::NodeInputType::Select { default, .. } => {
-                                                if let Some(val) = default {
-                                                    defaults.insert(input.key.clone(), val);
-                                                }
-                                            }
-                                            dtos::NodeInputType::Binary => {}
-                                        }
-                                    }
-
-                                    defaults
-                                }
-                                Err(_) => std::collections::HashMap::new(),
-                            };
-
-                        let mut merged_params = default_params;
-                        merged_params.extend(user_params);
-
-                        // Convert merged parameters to a serde_json::Value.
-                        let merged_params_json =
-                            serde_json::to_value(merged_params).unwrap_or_default();
+                        // Merge payload params over the values stored on the pipeline node and the defaults from node config,
+                        // values keep their JSON type and get checked against the input types before dispatching.
+                        let mut merged_params = match serde_json::from_value::<dtos::NodeConfig>(
+                            pipeline_node.config.clone(),
+                        ) {
+                            Ok(config) => {
+                                let config = config.into_v1();
+
+                                let default_values = config.default_values();
+                                pipeline_nodes_configs.insert(row.pipeline_node_id, config);
+
+                                default_values
+                            }
+                            Err(error) => {
+                                error!(
+                                    "Invalid config of pipeline node: {}, {error:?}",
+                                    row.pipeline_node_id
+                                );
+                                serde_json::Map::new()
+                            }
+                        };
+
+                        if let serde_json::Value::Object(stored_params) =
+                            &pipeline_node.input_values
+                        {
+                            merged_params.extend(stored_params.clone());
+                        }
+
+                        if let Some(serde_json::Value::Object(user_params)) =
+                            payload.params.get(&row.pipeline_node_id)
+                        {
+                            merged_params.extend(user_params.clone());
+                        }
+
+                        let merged_params_json = serde_json::Value::Object(merged_params);
 
                         pipeline_node_execs_payloads.insert(
                             row.pipeline_node_id,
@@ -277,7 +346,7 @@ async fn main() -> Result<(), async_nats::Error> {
                                     "{}@{}:{}",
                                     pipeline_node.publisher_name,
                                     pipeline_node.identifier_name,
-                                    pipeline_node.node_version
+                                    row.node_version
                                 ),
                                 params: merged_params_json,
                             },
@@ -290,7 +359,89 @@ async fn main() -> Result<(), async_nats::Error> {
                 }
             }
 
-            let pipeline_run = PipelineRun::new(graph, pipeline_node_execs_payloads);
+            let pipeline_outputs = match sqlx::query!(
+                r#"
+                SELECT
+                    contract
+                FROM
+                    pipelines
+                WHERE
+                    id = $1
+                "#,
+                payload.pipeline_id
+            )
+            .fetch_one(&pg_pool)
+            .await
+            .map_err(|error| error.to_string())
+            .and_then(|pipeline| {
+                serde_json::from_value::<dtos::PipelineContract>(pipeline.contract)
+                    .map_err(|error| error.to_string())
+            }) {
+                Ok(contract) => contract.outputs().to_vec(),
+                Err(error) => {
+                    error!("Failed to fetch pipeline contract: {error}");
+                    Vec::new()
+                }
+            };
+
+            let mut pipeline_run = PipelineRun::new(
+                graph,
+                pipeline_node_execs_payloads,
+                pipeline_nodes_configs,
+                pipeline_outputs,
+            );
+
+            // A retry reuses what succeeded in the failed execution,
+            // only the failed nodes and their descendants get executed again
+            if let Some(retry_of_pipeline_exec_id) = retry_of_pipeline_exec_id {
+                match sqlx::query!(
+                    r#"
+                    UPDATE
+                        pipeline_node_execs pne
+                    SET
+                        status = $1,
+                        params = prior.params,
+                        result = prior.result,
+                        started_at = NOW(),
+                        finished_at = NOW()
+                    FROM
+                        pipeline_node_execs prior
+                    WHERE
+                        pne.pipeline_exec_id = $2
+                        AND prior.pipeline_exec_id = $3
+                        AND prior.pipeline_node_id = pne.pipeline_node_id
+                        AND prior.status = $1
+                        AND prior.result IS NOT NULL
+                    RETURNING
+                        pne.id, pne.result AS "result!"
+                    "#,
+                    dtos::ExecStatus::Completed as ExecStatus,
+                    payload.pipeline_exec_id,
+                    retry_of_pipeline_exec_id
+                )
+                .fetch_all(&pg_pool)
+                .await
+                {
+                    Ok(reused) => {
+                        info!(
+                            "Reusing {} node results of pipeline_exec_id: {retry_of_pipeline_exec_id}",
+                            reused.len()
+                        );
+
+                        pipeline_run.seed_node_exec_results(
+                            reused
+                                .into_iter()
+                                .map(|node_exec| (node_exec.id, node_exec.result)),
+                        );
+                    }
+                    Err(error) => {
+                        error!(
+                            "Failed to reuse node results of pipeline_exec_id: {retry_of_pipeline_exec_id}, {error:?}"
+                        );
+                    }
+                }
+            }
+
             let mut runs = runs_clone.write().await;
             runs.insert(payload.pipeline_exec_id, pipeline_run.clone());
 
@@ -355,24 +506,32 @@ async fn main() -> Result<(), async_nats::Error> {
             if next_nodes_to_be_executed.is_empty() && pipeline_run.is_finished() {
                 info!("Pipeline run finished for ID: {}", payload.pipeline_exec_id);
 
+                let exec_status = if pipeline_run.is_failed() {
+                    ExecStatus::Failed
+                } else {
+                    ExecStatus::Completed
+                };
+
                 match sqlx::query!(
                     r#"
                     UPDATE
                         pipeline_execs
                     SET
                         status = $1,
+                        output = $2,
                         finished_at = NOW()
                     WHERE
-                        id = $2;
+                        id = $3;
                     "#,
-                    dtos::ExecStatus::Completed as ExecStatus,
+                    exec_status.clone() as ExecStatus,
+                    pipeline_run.output(),
                     payload.pipeline_exec_id
                 )
                 .execute(&pg_pool)
                 .await
                 {
                     Ok(_) => {
-                        info!("Pipeline execution status updated to 'completed', pipeline_execs_id: {}", payload.pipeline_exec_id);
+                        info!("Pipeline execution status updated to '{exec_status:?}', pipeline_execs_id: {}", payload.pipeline_exec_id);
                     }
                     Err(error) => {
                         error!(
@@ -385,9 +544,60 @@ async fn main() -> Result<(), async_nats::Error> {
                 continue;
             }
 
-            for payload in next_nodes_to_be_executed {
+            for mut payload in next_nodes_to_be_executed {
+                let resolved_params = pipeline_run.redact_params(&payload);
+
+                // Values are checked against the declared input types before reaching a worker,
+                // an invalid one fails the node the same way a failed execution does
+                if let Err(reason) = pipeline_run.coerce_params(&mut payload) {
+                    error!(
+                        "Invalid params for pipeline_node_exec_id: {}, {reason}",
+                        payload.pipeline_node_exec_id
+                    );
+
+                    if let Err(error) = sqlx::query!(
+                        r#"
+                        UPDATE
+                            pipeline_node_execs
+                        SET
+                            params = $1
+                        WHERE
+                            id = $2;
+                        "#,
+                        resolved_params,
+                        payload.pipeline_node_exec_id
+                    )
+                    .execute(&pg_pool)
+                    .await
+                    {
+                        error!("Failed to store pipeline node exec params: {error:?}");
+                    }
+
+                    let nats_payload =
+                        match serde_json::to_string(&dtos::PipelineNodeExecResultPayload {
+                            pipeline_exec_id: payload.pipeline_execs_id,
+                            pipeline_node_exec_id: payload.pipeline_node_exec_id,
+                            outcome: dtos::ExecutionOutcome::Failure(reason),
+                        }) {
+                            Ok(payload) => payload,
+                            Err(error) => {
+                                error!("Failed to serialize payload: {error:?}");
+                                continue;
+                            }
+                        };
+
+                    if let Err(error) = nats_client_clone
+                        .publish("pipeline.node.result", nats_payload.into())
+                        .await
+                    {
+                        error!("Failed to publish message to JetStream: {error:?}");
+                    }
+
+                    continue;
+                }
+
                 info!(
-                    "Publishing message to JetStream for pipeline_node_exec_id: {} with payload: {payload:?}",
+                    "Publishing message to JetStream for pipeline_node_exec_id: {}",
                     payload.pipeline_node_exec_id
                 );
 
@@ -397,11 +607,13 @@ async fn main() -> Result<(), async_nats::Error> {
                         pipeline_node_execs
                     SET
                         status = $1,
+                        params = $2,
                         started_at = NOW()
                     WHERE
-                        id = $2;
+                        id = $3;
                     "#,
                     dtos::ExecStatus::Running as ExecStatus,
+                    resolved_params,
                     payload.pipeline_node_exec_id
                 )
                 .execute(&pg_pool)
@@ -470,12 +682,25 @@ async fn main() -> Result<(), async_nats::Error> {
                 continue;
             };
 
-            let (exec_status, exec_result) = match payload.outcome {
+            // A result not matching the output schema of the node fails it as well
+            let outcome = match payload.outcome {
+                dtos::ExecutionOutcome::Success(result) => {
+                    match pipeline_run.check_result(payload.pipeline_node_exec_id, &result) {
+                        Ok(()) => dtos::ExecutionOutcome::Success(result),
+                        Err(reason) => dtos::ExecutionOutcome::Failure(reason),
+                    }
+                }
+                failure @ dtos::ExecutionOutcome::Failure(_) => failure,
+            };
+
+            let (exec_status, exec_result, exec_error) = match outcome {
                 dtos::ExecutionOutcome::Failure(error) => {
                     error!("Pipeline node exec failed: {error:?}");
-                    (ExecStatus::Failed, None)
+                    (ExecStatus::Failed, None, Some(error))
+                }
+                dtos::ExecutionOutcome::Success(result) => {
+                    (ExecStatus::Completed, Some(result), None)
                 }
-                dtos::ExecutionOutcome::Success(result) => (ExecStatus::Completed, Some(result)),
             };
 
             match sqlx::query!(
@@ -485,12 +710,14 @@ async fn main() -> Result<(), async_nats::Error> {
                 SET
                     status = $1,
                     result = $2,
+                    error = $3,
                     finished_at = NOW()
                 WHERE
-                    id = $3;
+                    id = $4;
                 "#,
                 exec_status.clone() as ExecStatus,
                 exec_result,
+                exec_error,
                 payload.pipeline_node_exec_id
             )
             .execute(&pg_pool)
@@ -504,13 +731,32 @@ async fn main() -> Result<(), async_nats::Error> {
             }
 
             // There's nothing to be executed after a failed node,
-            // All the downstream nodes executions will be cancelled
-            if exec_status == ExecStatus::Failed {
-                continue;
-            }
-
+            // All the downstream nodes executions are cancelled,
+            // planning still runs so the pipeline execution gets finished once the other branches are done
             if let Some(exec_result) = exec_result {
                 pipeline_run.update_node_exec_result(payload.pipeline_node_exec_id, exec_result);
+            } else {
+                let cancelled = pipeline_run.mark_node_exec_failed(payload.pipeline_node_exec_id);
+
+                // Each update is published as an exec event by the notify trigger of the table
+                if let Err(error) = sqlx::query!(
+                    r#"
+                    UPDATE
+                        pipeline_node_execs
+                    SET
+                        status = $1,
+                        finished_at = NOW()
+                    WHERE
+                        id = ANY($2) AND status = 'pending'
+                    "#,
+                    ExecStatus::Cancelled as ExecStatus,
+                    &cancelled
+                )
+                .execute(&pg_pool)
+                .await
+                {
+                    error!("Failed to cancel downstream pipeline node execs: {error:?}");
+                }
             }
 
             let nats_payload = match serde_json::to_string(&PipelinePlanPayload {
@@ -1,7 +1,7 @@
 use db::dtos;
 use petgraph::graph::DiGraph;
-use petgraph::visit::EdgeRef;
-use std::collections::HashMap;
+use petgraph::visit::{Dfs, EdgeRef};
+use std::collections::{HashMap, HashSet};
 use uuid::Uuid;
 
 #[derive(Clone, Debug)]
@@ -15,17 +15,30 @@ pub struct PipelineRun {
     pub graph: DiGraph<Uuid, GraphEdgeConnection>,
     pub payloads: HashMap<Uuid, dtos::PipelineNodeExecPayload>,
     pub nodes_exec_results: HashMap<Uuid, serde_json::Value>,
+    pub failed_nodes_execs: HashSet<Uuid>,
+    /// Node execs downstream of a failed one, never dispatched.
+    pub cancelled_nodes_execs: HashSet<Uuid>,
+    /// Node configs of every pipeline node.
+    pub configs: HashMap<Uuid, dtos::NodeConfigV1>,
+    /// Named outputs of the pipeline, making up the output document of the run.
+    pub outputs: Vec<dtos::PipelineOutput>,
 }
 
 impl PipelineRun {
     pub fn new(
         graph: DiGraph<Uuid, GraphEdgeConnection>,
         payloads: HashMap<Uuid, dtos::PipelineNodeExecPayload>,
+        configs: HashMap<Uuid, dtos::NodeConfigV1>,
+        outputs: Vec<dtos::PipelineOutput>,
     ) -> Self {
         Self {
             graph,
             payloads,
             nodes_exec_results: HashMap::new(),
+            failed_nodes_execs: HashSet::new(),
+            cancelled_nodes_execs: HashSet::new(),
+            configs,
+            outputs,
         }
     }
 
@@ -33,6 +46,53 @@ impl PipelineRun {
         self.nodes_exec_results.insert(node_exec_id, result);
     }
 
+    /// Results known before the run starts, keyed by node exec, their nodes are never dispatched.
+    pub fn seed_node_exec_results(
+        &mut self,
+        results: impl IntoIterator<Item = (Uuid, serde_json::Value)>,
+    ) {
+        self.nodes_exec_results.extend(results);
+    }
+
+    /// Fails the node exec and cancels the execs of every node downstream of it, they can't run
+    /// without its result. Returns the newly cancelled node execs.
+    pub fn mark_node_exec_failed(&mut self, node_exec_id: Uuid) -> Vec<Uuid> {
+        self.failed_nodes_execs.insert(node_exec_id);
+
+        let Some(start) = self.graph.node_indices().find(|&node_index| {
+            self.payloads
+                .get(&self.graph[node_index])
+                .is_some_and(|payload| payload.pipeline_node_exec_id == node_exec_id)
+        }) else {
+            return vec![];
+        };
+
+        let mut cancelled = vec![];
+        let mut dfs = Dfs::new(&self.graph, start);
+
+        while let Some(node_index) = dfs.next(&self.graph) {
+            let Some(payload) = self.payloads.get(&self.graph[node_index]) else {
+                continue;
+            };
+
+            let pipeline_node_exec_id = payload.pipeline_node_exec_id;
+
+            if node_index != start
+                && !self.nodes_exec_results.contains_key(&pipeline_node_exec_id)
+                && !self.failed_nodes_execs.contains(&pipeline_node_exec_id)
+                && self.cancelled_nodes_execs.insert(pipeline_node_exec_id)
+            {
+                cancelled.push(pipeline_node_exec_id);
+            }
+        }
+
+        cancelled
+    }
+
+    pub fn is_failed(&self) -> bool {
+        !self.failed_nodes_execs.is_empty()
+    }
+
     pub fn next_nodes_to_execute(
         &self,
         parent_pipeline_node_exec_id: Option<Uuid>,
@@ -44,7 +104,10 @@ impl PipelineRun {
                 let pipeline_node_exec_id =
                     self.payloads.get(&pipeline_node_id)?.pipeline_node_exec_id;
 
-                if self.nodes_exec_results.contains_key(&pipeline_node_exec_id) {
+                if self.nodes_exec_results.contains_key(&pipeline_node_exec_id)
+                    || self.failed_nodes_execs.contains(&pipeline_node_exec_id)
+                    || self.cancelled_nodes_execs.contains(&pipeline_node_exec_id)
+                {
                     return None;
                 }
 
@@ -136,7 +199,356 @@ impl PipelineRun {
             .collect::<Vec<_>>()
     }
 
+    fn node_config(&self, pipeline_node_exec_id: Uuid) -> Option<&dtos::NodeConfigV1> {
+        self.payloads
+            .iter()
+            .find(|(_, node_exec_payload)| {
+                node_exec_payload.pipeline_node_exec_id == pipeline_node_exec_id
+            })
+            .and_then(|(pipeline_node_id, _)| self.configs.get(pipeline_node_id))
+    }
+
+    /// Checks the params against the declared input types of the node, coercing them where allowed,
+    /// then against the input schema of the node. Params of undeclared inputs are passed as is.
+    pub fn coerce_params(&self, payload: &mut dtos::PipelineNodeExecPayload) -> Result<(), String> {
+        let Some(config) = self.node_config(payload.pipeline_node_exec_id) else {
+            return Ok(());
+        };
+
+        if let Some(params) = payload.params.as_object_mut() {
+            for (key, value) in params.iter_mut() {
+                if let Some(input) = config.input(key) {
+                    *value = input
+                        .input
+                        .coerce(value.take())
+                        .map_err(|error| format!("Invalid value of input {key}: {error}"))?;
+                }
+            }
+        }
+
+        config
+            .check_params(&payload.params)
+            .map_err(|error| format!("Invalid params: {error}"))
+    }
+
+    /// Params of the payload as they can be stored, with the values of secret inputs redacted.
+    pub fn redact_params(&self, payload: &dtos::PipelineNodeExecPayload) -> serde_json::Value {
+        self.node_config(payload.pipeline_node_exec_id).map_or_else(
+            || payload.params.clone(),
+            |config| config.redact_params(&payload.params),
+        )
+    }
+
+    /// Checks a result against the output schema of the node.
+    pub fn check_result(
+        &self,
+        pipeline_node_exec_id: Uuid,
+        result: &serde_json::Value,
+    ) -> Result<(), String> {
+        self.node_config(pipeline_node_exec_id)
+            .map_or(Ok(()), |config| config.check_result(result))
+            .map_err(|error| format!("Invalid result: {error}"))
+    }
+
+    /// Output document of the run, outputs of nodes without a result are null.
+    pub fn output(&self) -> serde_json::Value {
+        self.outputs
+            .iter()
+            .map(|output| {
+                let result = self
+                    .payloads
+                    .get(&output.pipeline_node_id)
+                    .and_then(|payload| {
+                        self.nodes_exec_results.get(&payload.pipeline_node_exec_id)
+                    });
+
+                let value = match (result, output.output.as_ref()) {
+                    (Some(result), Some(key)) => result.get(key).cloned(),
+                    (Some(result), None) => Some(result.clone()),
+                    (None, _) => None,
+                };
+
+                (output.name.clone(), value.unwrap_or_default())
+            })
+            .collect::<serde_json::Map<_, _>>()
+            .into()
+    }
+
     pub fn is_finished(&self) -> bool {
         self.next_nodes_to_execute(None).is_empty()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node_exec_payload(params: serde_json::Value) -> dtos::PipelineNodeExecPayload {
+        dtos::PipelineNodeExecPayload {
+            pipeline_execs_id: Uuid::new_v4(),
+            pipeline_node_exec_id: Uuid::new_v4(),
+            container_type: dtos::NodeContainerType::Wasm,
+            path: "builtins@echo:v1".to_string(),
+            params,
+        }
+    }
+
+    #[test]
+    fn test_coerce_params() {
+        let pipeline_node_id = Uuid::new_v4();
+        let payload = node_exec_payload(serde_json::json!({}));
+
+        let inputs = [
+            ("message", dtos::NodeInputTypeV1::Text { default: None }),
+            (
+                "language",
+                dtos::NodeInputTypeV1::Select {
+                    options: vec![dtos::SelectInput {
+                        value: "en".to_string(),
+                        label: HashMap::new(),
+                    }],
+                    default: None,
+                },
+            ),
+            ("media", dtos::NodeInputTypeV1::Binary),
+            (
+                "count",
+                dtos::NodeInputTypeV1::Number {
+                    min: Some(1.0),
+                    max: None,
+                    default: None,
+                },
+            ),
+            ("enabled", dtos::NodeInputTypeV1::Boolean { default: None }),
+            ("tags", dtos::NodeInputTypeV1::List { default: None }),
+        ];
+
+        let run = PipelineRun::new(
+            DiGraph::new(),
+            HashMap::from([(pipeline_node_id, payload.clone())]),
+            HashMap::from([(
+                pipeline_node_id,
+                dtos::NodeConfigV1 {
+                    inputs: inputs
+                        .into_iter()
+                        .map(|(key, input)| dtos::NodeInputV1 {
+                            key: key.to_string(),
+                            input,
+                            label: None,
+                            required: false,
+                            description: None,
+                        })
+                        .collect(),
+                    outputs: vec![],
+                    input_schema: Some(serde_json::json!({
+                        "properties": { "extra": { "type": "integer" } }
+                    })),
+                    output_schema: Some(serde_json::json!({
+                        "type": "object",
+                        "required": ["text"],
+                        "properties": { "text": { "type": "string" } }
+                    })),
+                },
+            )]),
+            vec![],
+        );
+
+        let mut valid = payload.clone();
+        valid.params = serde_json::json!({
+            "message": true,
+            "language": "en",
+            "count": "3",
+            "enabled": "false",
+            "tags": "news",
+            "extra": 1,
+        });
+
+        assert_eq!(run.coerce_params(&mut valid), Ok(()));
+        assert_eq!(
+            valid.params,
+            serde_json::json!({
+                "message": "true",
+                "language": "en",
+                "count": 3,
+                "enabled": false,
+                "tags": ["news"],
+                "extra": 1,
+            })
+        );
+
+        let mut below_minimum = payload.clone();
+        below_minimum.params = serde_json::json!({ "count": 0 });
+
+        assert!(run.coerce_params(&mut below_minimum).is_err());
+
+        let mut not_an_option = payload.clone();
+        not_an_option.params = serde_json::json!({ "language": "fr" });
+
+        assert!(run.coerce_params(&mut not_an_option).is_err());
+
+        let mut not_binary = payload.clone();
+        not_binary.params = serde_json::json!({ "media": 42 });
+
+        assert!(run.coerce_params(&mut not_binary).is_err());
+
+        let mut schema_violation = payload.clone();
+        schema_violation.params = serde_json::json!({ "extra": "one" });
+
+        assert!(run
+            .coerce_params(&mut schema_violation)
+            .is_err_and(|error| error.contains("/extra")));
+
+        assert_eq!(
+            run.check_result(
+                payload.pipeline_node_exec_id,
+                &serde_json::json!({ "text": "hello" })
+            ),
+            Ok(())
+        );
+        assert!(run
+            .check_result(
+                payload.pipeline_node_exec_id,
+                &serde_json::json!({ "text": 42 })
+            )
+            .is_err_and(|error| error.contains("/text")));
+    }
+
+    #[test]
+    fn test_output() {
+        let pipeline_node_id = Uuid::new_v4();
+        let payload = node_exec_payload(serde_json::json!({}));
+        let output = |name: &str, key: Option<&str>| dtos::PipelineOutput {
+            name: name.to_string(),
+            pipeline_node_id,
+            output: key.map(str::to_string),
+        };
+
+        let mut run = PipelineRun::new(
+            DiGraph::new(),
+            HashMap::from([(pipeline_node_id, payload.clone())]),
+            HashMap::new(),
+            vec![
+                output("text", Some("text")),
+                output("result", None),
+                output("missing", Some("missing")),
+            ],
+        );
+
+        run.update_node_exec_result(
+            payload.pipeline_node_exec_id,
+            serde_json::json!({ "text": "hello" }),
+        );
+
+        assert_eq!(
+            run.output(),
+            serde_json::json!({
+                "text": "hello",
+                "result": { "text": "hello" },
+                "missing": null,
+            })
+        );
+    }
+
+    #[test]
+    fn test_seed_node_exec_results() {
+        let (source_id, failed_id, sibling_id) = (Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4());
+        let payloads = HashMap::from([
+            (source_id, node_exec_payload(serde_json::json!({}))),
+            (failed_id, node_exec_payload(serde_json::json!({}))),
+            (sibling_id, node_exec_payload(serde_json::json!({}))),
+        ]);
+
+        let mut graph = DiGraph::new();
+        let source = graph.add_node(source_id);
+        let failed = graph.add_node(failed_id);
+        let sibling = graph.add_node(sibling_id);
+
+        for target in [failed, sibling] {
+            graph.add_edge(
+                source,
+                target,
+                GraphEdgeConnection {
+                    source: "text".to_string(),
+                    target: "message".to_string(),
+                },
+            );
+        }
+
+        let mut run = PipelineRun::new(graph, payloads.clone(), HashMap::new(), vec![]);
+
+        run.seed_node_exec_results([
+            (
+                payloads[&source_id].pipeline_node_exec_id,
+                serde_json::json!({ "text": "hello" }),
+            ),
+            (
+                payloads[&sibling_id].pipeline_node_exec_id,
+                serde_json::json!({}),
+            ),
+        ]);
+
+        let next = run.next_nodes_to_execute(None);
+
+        assert_eq!(next.len(), 1);
+        assert_eq!(
+            next[0].pipeline_node_exec_id,
+            payloads[&failed_id].pipeline_node_exec_id
+        );
+        assert_eq!(next[0].params, serde_json::json!({ "message": "hello" }));
+    }
+
+    #[test]
+    fn test_mark_node_exec_failed() {
+        let ids = [(); 5].map(|()| Uuid::new_v4());
+        let [source_id, failed_id, child_id, grandchild_id, sibling_id] = ids;
+        let payloads = ids
+            .iter()
+            .map(|&id| (id, node_exec_payload(serde_json::json!({}))))
+            .collect::<HashMap<_, _>>();
+
+        let mut graph = DiGraph::new();
+        let [source, failed, child, grandchild, sibling] = ids.map(|id| graph.add_node(id));
+
+        for (from, to) in [
+            (source, failed),
+            (source, sibling),
+            (failed, child),
+            (child, grandchild),
+            (sibling, grandchild),
+        ] {
+            graph.add_edge(
+                from,
+                to,
+                GraphEdgeConnection {
+                    source: "text".to_string(),
+                    target: "message".to_string(),
+                },
+            );
+        }
+
+        let mut run = PipelineRun::new(graph, payloads.clone(), HashMap::new(), vec![]);
+        let exec_id = |id: Uuid| payloads[&id].pipeline_node_exec_id;
+
+        run.update_node_exec_result(exec_id(source_id), serde_json::json!({ "text": "hello" }));
+
+        let mut cancelled = run.mark_node_exec_failed(exec_id(failed_id));
+        cancelled.sort();
+
+        let mut expected = vec![exec_id(child_id), exec_id(grandchild_id)];
+        expected.sort();
+
+        assert_eq!(cancelled, expected);
+        assert!(run.is_failed());
+
+        // The sibling branch still runs, the grandchild waiting on it stays cancelled
+        let next = run.next_nodes_to_execute(None);
+        assert_eq!(next.len(), 1);
+        assert_eq!(next[0].pipeline_node_exec_id, exec_id(sibling_id));
+
+        run.update_node_exec_result(exec_id(sibling_id), serde_json::json!({ "text": "hello" }));
+        assert!(run.is_finished());
+
+        // Already cancelled execs aren't reported twice
+        assert!(run.mark_node_exec_failed(exec_id(failed_id)).is_empty());
+    }
+}
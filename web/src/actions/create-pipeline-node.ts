@@ -1,6 +1,7 @@
 "use server";
 
 import { env } from "~/env";
+import { getSessionToken } from "~/lib/auth";
 
 export interface CreatePipelineNodeParams {
 	pipelineId: string;
@@ -12,10 +13,13 @@ export interface CreatePipelineNodeParams {
 export async function createPipelineNodeAction(
 	params: CreatePipelineNodeParams,
 ) {
+	const sessionToken = await getSessionToken();
+
 	const response = await fetch(`${env.API_URL}/pipeline-nodes`, {
 		method: "POST",
 		headers: {
 			"Content-Type": "application/json",
+			Authorization: `Bearer ${sessionToken}`,
 		},
 		body: JSON.stringify(params),
 	});
@@ -11,7 +11,7 @@ export interface CreatePipelineTriggerConnectionParams {
 export async function createPipelineTriggerConnectionAction(
   params: CreatePipelineTriggerConnectionParams,
 ) {
-  const sessionToken = getSessionToken();
+  const sessionToken = await getSessionToken();
 
   const response = await fetch(
     `${env.API_URL}/pipeline-nodes/${params.nodeId}`,
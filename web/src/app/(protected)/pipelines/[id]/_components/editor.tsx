@@ -35,7 +35,6 @@ import { createPipelineTriggerConnectionAction } from "~/actions/create-pipeline
 import { updatePipelineNodeAction } from "~/actions/update-pipeline-node";
 import { updatePipelineTriggerAction } from "~/actions/update-pipeline-trigger";
 import { useWsStore } from "~/contexts/ws-store-context";
-import { env } from "~/env";
 import {
 	type Node,
 	NodeType,
@@ -91,7 +90,7 @@ export const Editor = (props: EditorProps) => {
 	useEffect(() => {
 		if (execId) {
 			const eventSource = new EventSource(
-				`${env.NEXT_PUBLIC_API_URL}/pipeline-execs/${execId}/events`,
+				`/api/pipeline-execs/${execId}/events`,
 			);
 
 			eventSource.onmessage = (event) => {
@@ -232,7 +231,7 @@ export const Editor = (props: EditorProps) => {
 					return addEdge(
 						{
 							...commonEdgeParams,
-							id: pipelineNode.triggerId ?? "",
+							id: `${pipelineNode.triggerId}:${pipelineNode.id}`,
 							source: pipelineNode.triggerId ?? "",
 							target: pipelineNode.id,
 						},
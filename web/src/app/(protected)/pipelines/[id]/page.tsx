@@ -89,17 +89,19 @@ export default async function PipelineDetails(props: {
     },
   );
 
-  nodes.push({
-    id: pipeline.trigger.id,
-    position: pipeline.trigger.coords,
-    type: NodeType.Trigger,
-    data: {
-      id: pipeline.trigger.id,
-      name: "Trigger",
-      pipelineId: pipeline.id,
-      config: pipeline.trigger.config,
-    },
-  });
+  for (const trigger of pipeline.triggers) {
+    nodes.push({
+      id: trigger.id,
+      position: trigger.coords,
+      type: NodeType.Trigger,
+      data: {
+        id: trigger.id,
+        name: "Trigger",
+        pipelineId: pipeline.id,
+        config: trigger.config,
+      },
+    });
+  }
 
   const edges: Parameters<typeof useEdgesState>[0] = pipeline.connections.map(
     (connection) => ({
@@ -125,14 +127,14 @@ export default async function PipelineDetails(props: {
     }),
   );
 
-  const triggerNode = pipeline.nodes.find(
-    (node) => node.triggerId === pipeline.trigger.id,
-  );
+  for (const triggerNode of pipeline.nodes) {
+    if (!triggerNode.triggerId) {
+      continue;
+    }
 
-  if (triggerNode) {
     edges.push({
-      id: pipeline.trigger.id,
-      source: pipeline.trigger.id,
+      id: `${triggerNode.triggerId}:${triggerNode.id}`,
+      source: triggerNode.triggerId,
       target: triggerNode.id,
       animated: true,
       deletable: true,
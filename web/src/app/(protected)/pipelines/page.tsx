@@ -13,6 +13,7 @@ interface Pipeline {
 interface Team {
   id: string;
   name: string;
+  role: "viewer" | "runner" | "editor" | "admin" | "owner";
 }
 
 export default async function Home() {
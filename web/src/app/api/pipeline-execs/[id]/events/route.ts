@@ -11,7 +11,7 @@ export async function GET(
 	{ params }: { params: Promise<{ id: string }> },
 ) {
 	const { id } = await params;
-	const upstreamUrl = `${env.API_URL}/pipeline-execs/${id}`;
+	const upstreamUrl = `${env.API_URL}/pipeline-execs/${id}/events`;
 	const sessionToken = await getSessionToken();
 
 	const upstreamResponse = await fetch(upstreamUrl, {
@@ -14,23 +14,43 @@ export interface Pipeline {
 	id: string;
 	name: string;
 	description?: string;
-	trigger: {
-		id: string;
-		coords: { x: number; y: number };
-		config: {
-			[key: string]: string;
-		};
-	};
+	contract: PipelineContract;
+	triggers: PipelineTrigger[];
 	nodes: PipelineNode[];
 	connections: PipelineNodeConnection[];
 	participants?: PipelineParticipant[];
 }
 
+export interface PipelineContract {
+	version: "v0";
+	inputs: {
+		name: string;
+		pipeline_node_id: string;
+		input: string;
+		required: boolean;
+		description?: string;
+	}[];
+	outputs: {
+		name: string;
+		pipeline_node_id: string;
+		output?: string;
+	}[];
+}
+
+export interface PipelineTrigger {
+	id: string;
+	coords: { x: number; y: number };
+	config: {
+		[key: string]: string;
+	};
+}
+
 export interface PipelineNode {
 	id: string;
 	nodeId: string;
 	nodeVersion: string;
 	triggerId?: string;
+	inputValues: Record<string, unknown>;
 	inputs: { id: string; key: string }[];
 	outputs: { id: string; key: string }[];
 	coords: {
@@ -105,7 +125,13 @@ export const isTaskNodeConfigV0InputBinary = (
 	return input === "binary";
 };
 
-export type TaskNodeConfigV0Output = "text" | "status" | "binary";
+export type TaskNodeConfigV0Output =
+	| "text"
+	| "status"
+	| "binary"
+	| "number"
+	| "boolean"
+	| "json";
 
 export interface TaskNodeConfigV0 {
 	version: "v0";
@@ -158,6 +184,73 @@ export interface PipelineExecNode {
 	finishedAt: Date;
 }
 
+export interface PipelineExecNodeDetails {
+	id: string;
+	pipelineNodeId: string;
+	nodeVersion: string | null;
+	status: ExecStatus;
+	params: Record<string, unknown> | null;
+	result: Record<string, unknown> | null;
+	error: string | null;
+	createdAt: Date | null;
+	startedAt: Date | null;
+	finishedAt: Date | null;
+}
+
+export interface PipelineExecDetails {
+	id: string;
+	pipelineId: string;
+	pipelineTriggerId: string | null;
+	parentPipelineExecId: string | null;
+	retryOfPipelineExecId: string | null;
+	replayOfPipelineExecId: string | null;
+	pinnedNodeVersions: Record<string, string> | null;
+	status: ExecStatus;
+	params: Record<string, Record<string, unknown>>;
+	output: Record<string, unknown> | null;
+	createdAt: Date | null;
+	startedAt: Date | null;
+	finishedAt: Date | null;
+	nodeExecs: PipelineExecNodeDetails[];
+}
+
+export interface PipelineExecOutputChange {
+	output: string | null;
+	original: unknown;
+	replayed: unknown;
+}
+
+export interface PipelineExecNodeDiff {
+	pipelineNodeId: string;
+	originalNodeVersion: string | null;
+	nodeVersion: string | null;
+	originalStatus: ExecStatus | null;
+	status: ExecStatus | null;
+	changes: PipelineExecOutputChange[];
+}
+
+export interface PipelineExecDiff {
+	originalPipelineExecId: string;
+	pipelineExecId: string;
+	nodes: PipelineExecNodeDiff[];
+}
+
+export interface PipelineExecListItem {
+	id: string;
+	pipelineTriggerId: string | null;
+	status: ExecStatus;
+	createdAt: Date | null;
+	startedAt: Date | null;
+	finishedAt: Date | null;
+}
+
+export interface PipelineExecList {
+	items: PipelineExecListItem[];
+	total: number;
+	limit: number;
+	offset: number;
+}
+
 export type PipelineExecNotification =
 	| {
 			type: "pipeline";